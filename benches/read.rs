@@ -1,4 +1,3 @@
-use citi;
 use criterion::{black_box, criterion_group, Criterion};
 use std::fs::File;
 use std::path::PathBuf;
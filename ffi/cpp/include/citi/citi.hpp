@@ -84,7 +84,34 @@ namespace citi {
             // CString::new
             NullByte = -40,
 
-            IndexOutOfBounds = -41
+            IndexOutOfBounds = -41,
+
+            RecordReadErrorUnexpectedEndOfRecord = -42,
+            RecordReadErrorParserPoisoned = -43,
+
+            // Record modification errors
+            RecordErrorWrongNumberOfSamples = -44,
+
+            RecordReadErrorVersionNotFirst = -45,
+            RecordParseErrorNonFiniteValue = -46,
+            RecordReadErrorEmptyRecord = -47,
+            RecordErrorArrayLengthMismatch = -48,
+            RecordErrorDataArrayLengthsDiffer = -49,
+            RecordReadErrorLineTooLong = -50,
+            RecordReadErrorComplexIndependentVariableUnsupported = -51,
+            RecordReadErrorDataBlockWrongLength = -52,
+            RecordErrorNotMonotonic = -53,
+            RecordErrorOutOfInterpolationRange = -54,
+            RecordParseErrorMalformedDataPair = -55,
+            RecordReadErrorTooManyPoints = -56,
+            RecordWriteErrorControlCharacterInName = -57,
+            RecordErrorVarBehindData = -58,
+            RecordWriteErrorUnknownFormat = -59,
+            RecordReadErrorUnmatchedListEnd = -60,
+            RecordWriteErrorLossySample = -61,
+            RecordWriteErrorControlCharacterInText = -62,
+            RecordReadErrorInvalidLogSegment = -63,
+            RecordWriteErrorFormattingError = -64
         };
 
         class RuntimeException : public std::runtime_error {
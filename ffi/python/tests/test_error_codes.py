@@ -17,7 +17,7 @@ class TestErrorCodes(unittest.TestCase):
         self.runner(1, 'Invalid error code')
 
     def test_non_existant_last_error_code(self):
-        self.runner(-42, 'Invalid error code')
+        self.runner(-65, 'Invalid error code')
 
     def test_no_error(self):
         self.runner(0, 'No error')
@@ -169,3 +169,134 @@ class TestErrorCodes(unittest.TestCase):
 
     def test_index_out_of_bounds(self):
         self.runner(-41, 'Index is outside of acceptable bounds')
+
+    def test_record_read_error_unexpected_end_of_record(self):
+        self.runner(
+            -42,
+            'Record read error due to unterminated block at end of record'
+        )
+
+    def test_record_read_error_parser_poisoned(self):
+        self.runner(
+            -43,
+            'Record read error due to use of parser after a previous error'
+        )
+
+    def test_record_error_wrong_number_of_samples(self):
+        self.runner(
+            -44,
+            'Record error due to wrong number of samples for data arrays'
+        )
+
+    def test_record_read_error_version_not_first(self):
+        self.runner(-45, 'Record read error due to keyword before version')
+
+    def test_record_parse_error_non_finite_value(self):
+        self.runner(-46, 'Infinite or NaN value found when parsing to record')
+
+    def test_record_read_error_empty_record(self):
+        self.runner(-47, 'Record read error due to empty record')
+
+    def test_record_error_array_length_mismatch(self):
+        self.runner(
+            -48,
+            'Record error due to data array and independent variable lengths'
+        )
+
+    def test_record_error_data_array_lengths_differ(self):
+        self.runner(
+            -49,
+            'Record error due to data arrays of different lengths'
+        )
+
+    def test_record_read_error_line_too_long(self):
+        self.runner(
+            -50,
+            'Record read error due to line exceeding the length limit'
+        )
+
+    def test_record_read_error_complex_independent_variable(self):
+        self.runner(
+            -51,
+            'Record read error due to complex independent variable'
+        )
+
+    def test_record_read_error_data_block_wrong_length(self):
+        self.runner(
+            -52,
+            'Record read error due to data block of the wrong length'
+        )
+
+    def test_record_error_not_monotonic(self):
+        self.runner(
+            -53,
+            'Record error due to non-monotonic independent variable'
+        )
+
+    def test_record_error_out_of_interpolation_range(self):
+        self.runner(
+            -54,
+            'Record error due to interpolation outside of the independent '
+            'variable range'
+        )
+
+    def test_record_parse_error_malformed_data_pair(self):
+        self.runner(
+            -55,
+            'Data pair without exactly two values found when parsing to record'
+        )
+
+    def test_record_read_error_too_many_points(self):
+        self.runner(
+            -56,
+            'Record read error due to more points than the limit'
+        )
+
+    def test_record_write_error_control_character_in_name(self):
+        self.runner(
+            -57,
+            'Record write error due to control character in a name'
+        )
+
+    def test_record_error_var_behind_data(self):
+        self.runner(
+            -58,
+            'Record error due to independent variable behind the data'
+        )
+
+    def test_record_write_error_unknown_format(self):
+        self.runner(
+            -59,
+            'Record write error due to unknown format'
+        )
+
+    def test_record_read_error_unmatched_list_end(self):
+        self.runner(
+            -60,
+            'Record read error due to list end without a begin'
+        )
+
+    def test_record_write_error_lossy_sample(self):
+        self.runner(
+            -61,
+            'Record write error due to a sample its format cannot represent'
+        )
+
+    def test_record_write_error_control_character_in_text(self):
+        self.runner(
+            -62,
+            'Record write error due to control character in a text field'
+        )
+
+    def test_record_read_error_invalid_log_segment(self):
+        self.runner(
+            -63,
+            'Record read error due to a logarithmic SEG without a positive '
+            'ratio'
+        )
+
+    def test_record_write_error_formatting_error(self):
+        self.runner(
+            -64,
+            'Record write error due to a formatting failure'
+        )
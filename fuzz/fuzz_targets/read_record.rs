@@ -0,0 +1,23 @@
+#![no_main]
+use citi::{ReaderOptions, Record};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Limits keep a single `SEG` item or line from exhausting memory
+    let options = ReaderOptions {
+        allow_unterminated_final_block: true,
+        case_insensitive_keywords: true,
+        preserve_unknown_keywords: true,
+        max_line_bytes: Some(1 << 16),
+        max_points: Some(1 << 16),
+        ..ReaderOptions::default()
+    };
+    let _ = Record::from_reader_with_options(&mut &data[..], &options);
+    let _ = Record::from_reader_with_options(
+        &mut &data[..],
+        &ReaderOptions {
+            max_points: Some(1 << 16),
+            ..ReaderOptions::default()
+        },
+    );
+});
@@ -3,10 +3,10 @@
 //! This FFI was designed to work as follows:
 //! - Rust creates and destorys a pointer to a [Record]
 //! - The interfacing language holds the pointer and is
-//! responsible for creation, destruction, and avoiding
-//! memory leaks.
+//!   responsible for creation, destruction, and avoiding
+//!   memory leaks.
 //! - Rust methods take the pointer and modify the pointer
-//! or return a value based on the interface.
+//!   or return a value based on the interface.
 //!
 //! Error handling
 //!
@@ -17,7 +17,7 @@
 //! return a pointer (null pointers represent an error) or an integer
 //! where negative values represent an error code.
 
-use crate::{Record, DataArray, Device, Error, ParseError, ReadError, WriteError};
+use crate::{Record, DataArray, Device, Error, ParseError, ReadError, RecordError, WriteError};
 
 use num_complex::Complex;
 use std::ffi::{CString, CStr};
@@ -85,6 +85,33 @@ enum ErrorCode {
     NullByte = -40,
 
     IndexOutOfBounds = -41,
+
+    RecordReadErrorUnexpectedEndOfRecord = -42,
+    RecordReadErrorParserPoisoned = -43,
+
+    // Record modification errors
+    RecordErrorWrongNumberOfSamples = -44,
+
+    RecordReadErrorVersionNotFirst = -45,
+    RecordParseErrorNonFiniteValue = -46,
+    RecordReadErrorEmptyRecord = -47,
+    RecordErrorArrayLengthMismatch = -48,
+    RecordErrorDataArrayLengthsDiffer = -49,
+    RecordReadErrorLineTooLong = -50,
+    RecordReadErrorComplexIndependentVariableUnsupported = -51,
+    RecordReadErrorDataBlockWrongLength = -52,
+    RecordErrorNotMonotonic = -53,
+    RecordErrorOutOfInterpolationRange = -54,
+    RecordParseErrorMalformedDataPair = -55,
+    RecordReadErrorTooManyPoints = -56,
+    RecordWriteErrorControlCharacterInName = -57,
+    RecordErrorVarBehindData = -58,
+    RecordWriteErrorUnknownFormat = -59,
+    RecordReadErrorUnmatchedListEnd = -60,
+    RecordWriteErrorLossySample = -61,
+    RecordWriteErrorControlCharacterInText = -62,
+    RecordReadErrorInvalidLogSegment = -63,
+    RecordWriteErrorFormattingError = -64,
 }
 
 /// Note that this static array must be kept in sync with the error code enum.
@@ -146,6 +173,32 @@ const ERROR_DESCRIPTION: &[&str] = &[
     "An interior null byte was found in string",
 
     "Index is outside of acceptable bounds",
+
+    "Record read error due to unterminated block at end of record",
+    "Record read error due to use of parser after a previous error",
+
+    "Record error due to wrong number of samples for data arrays",
+
+    "Record read error due to keyword before version",
+    "Infinite or NaN value found when parsing to record",
+    "Record read error due to empty record",
+    "Record error due to data array and independent variable lengths",
+    "Record error due to data arrays of different lengths",
+    "Record read error due to line exceeding the length limit",
+    "Record read error due to complex independent variable",
+    "Record read error due to data block of the wrong length",
+    "Record error due to non-monotonic independent variable",
+    "Record error due to interpolation outside of the independent variable range",
+    "Data pair without exactly two values found when parsing to record",
+    "Record read error due to more points than the limit",
+    "Record write error due to control character in a name",
+    "Record error due to independent variable behind the data",
+    "Record write error due to unknown format",
+    "Record read error due to list end without a begin",
+    "Record write error due to a sample its format cannot represent",
+    "Record write error due to control character in a text field",
+    "Record read error due to a logarithmic SEG without a positive ratio",
+    "Record write error due to a formatting failure",
 ];
 
 thread_local!{
@@ -153,7 +206,7 @@ thread_local!{
     /// Node that this error code enum must be kept in sync with the 
     /// static array corresponding to error descriptions.
 
-    static LAST_ERROR_CODE: RefCell<Option<ErrorCode>> = RefCell::new(None);
+    static LAST_ERROR_CODE: RefCell<Option<ErrorCode>> = const { RefCell::new(None) };
 }
 
 /// Update the last saved error code
@@ -210,11 +263,7 @@ pub extern "C" fn get_error_description(error_code: c_int) -> *const c_char {
 /// Check if index is out of bounds
 fn check_index_bounds(idx: size_t, size: size_t) -> bool {
 
-    if idx >= size {
-        return false
-    }
-
-    return true
+    idx < size
 }
 
 /// Helper function to validate pointers and update data field
@@ -279,7 +328,7 @@ fn check_ptr_index_transform_to_cstring<T>(
     let record_ref = unsafe { &*record };
     let vals = get_vals(record_ref);
 
-    if check_index_bounds(idx, vals.len()) == false {
+    if !check_index_bounds(idx, vals.len()) {
         update_error_code(ErrorCode::IndexOutOfBounds);
         return std::ptr::null_mut()
     }
@@ -331,7 +380,9 @@ fn map_record_error_to_error_code(err: Error) -> ErrorCode {
             match parse_err {
                 ParseError::BadKeyword(_) => update_error_code(ErrorCode::RecordParseErrorBadKeyword),
                 ParseError::BadRegex => update_error_code(ErrorCode::RecordParseErrorBadRegex),
-                ParseError::NumberParseError(_) => update_error_code(ErrorCode::RecordParseErrorNumber)
+                ParseError::NumberParseError(_) => update_error_code(ErrorCode::RecordParseErrorNumber),
+                ParseError::NonFiniteValue(_) => update_error_code(ErrorCode::RecordParseErrorNonFiniteValue),
+                ParseError::MalformedDataPair(_) => update_error_code(ErrorCode::RecordParseErrorMalformedDataPair),
             }
         },
         Error::ReadError(read_err) => {
@@ -339,7 +390,7 @@ fn map_record_error_to_error_code(err: Error) -> ErrorCode {
                 ReadError::DataArrayOverIndex => update_error_code(ErrorCode::RecordReadErrorDataArrayOverIndex),
                 ReadError::IndependentVariableDefinedTwice => update_error_code(ErrorCode::RecordReadErrorIndependentVariableDefinedTwice),
                 ReadError::SingleUseKeywordDefinedTwice(_) => update_error_code(ErrorCode::RecordReadErrorSingleUseKeywordDefinedTwice),
-                ReadError::OutOfOrderKeyword(_) => update_error_code(ErrorCode::RecordReadErrorOutOfOrderKeyword),
+                ReadError::OutOfOrderKeyword(_, _) => update_error_code(ErrorCode::RecordReadErrorOutOfOrderKeyword),
                 ReadError::LineError(_, _) => update_error_code(ErrorCode::RecordReadErrorLineError),
                 ReadError::ReadingError(_) => update_error_code(ErrorCode::RecordReadErrorIO),
                 ReadError::NoVersion => update_error_code(ErrorCode::RecordReadErrorNoVersion),
@@ -347,6 +398,16 @@ fn map_record_error_to_error_code(err: Error) -> ErrorCode {
                 ReadError::NoIndependentVariable => update_error_code(ErrorCode::RecordReadErrorNoIndependentVariable),
                 ReadError::NoData => update_error_code(ErrorCode::RecordReadErrorNoData),
                 ReadError::VarAndDataDifferentLengths(_, _, _) => update_error_code(ErrorCode::RecordReadErrorVarAndDataDifferentLengths),
+                ReadError::UnexpectedEndOfRecord => update_error_code(ErrorCode::RecordReadErrorUnexpectedEndOfRecord),
+                ReadError::ParserPoisoned => update_error_code(ErrorCode::RecordReadErrorParserPoisoned),
+                ReadError::VersionNotFirst(_) => update_error_code(ErrorCode::RecordReadErrorVersionNotFirst),
+                ReadError::EmptyRecord => update_error_code(ErrorCode::RecordReadErrorEmptyRecord),
+                ReadError::LineTooLong(_, _) => update_error_code(ErrorCode::RecordReadErrorLineTooLong),
+                ReadError::TooManyPoints(_) => update_error_code(ErrorCode::RecordReadErrorTooManyPoints),
+                ReadError::UnmatchedListEnd(_) => update_error_code(ErrorCode::RecordReadErrorUnmatchedListEnd),
+                ReadError::InvalidLogSegment(..) => update_error_code(ErrorCode::RecordReadErrorInvalidLogSegment),
+                ReadError::ComplexIndependentVariableUnsupported(_) => update_error_code(ErrorCode::RecordReadErrorComplexIndependentVariableUnsupported),
+                ReadError::DataBlockWrongLength(_, _, _, _) => update_error_code(ErrorCode::RecordReadErrorDataBlockWrongLength),
             }
         },
         Error::WriteError(write_err) => {
@@ -356,6 +417,21 @@ fn map_record_error_to_error_code(err: Error) -> ErrorCode {
                 WriteError::NoDataName(_) => update_error_code(ErrorCode::RecordWriteErrorNoDataName),
                 WriteError::NoDataFormat(_) => update_error_code(ErrorCode::RecordWriteErrorNoDataFormat),
                 WriteError::WrittingError(_) => update_error_code(ErrorCode::RecordWriteErrorWrittingError),
+                WriteError::ControlCharacterInName(_) => update_error_code(ErrorCode::RecordWriteErrorControlCharacterInName),
+                WriteError::ControlCharacterInText(_) => update_error_code(ErrorCode::RecordWriteErrorControlCharacterInText),
+                WriteError::FormattingError(_) => update_error_code(ErrorCode::RecordWriteErrorFormattingError),
+                WriteError::UnknownFormat(_, _) => update_error_code(ErrorCode::RecordWriteErrorUnknownFormat),
+                WriteError::LossySample(_, _, _) => update_error_code(ErrorCode::RecordWriteErrorLossySample),
+            }
+        }
+        Error::RecordError(record_err) => {
+            match record_err {
+                RecordError::WrongNumberOfSamples(_, _) => update_error_code(ErrorCode::RecordErrorWrongNumberOfSamples),
+                RecordError::ArrayLengthMismatch(_, _, _) => update_error_code(ErrorCode::RecordErrorArrayLengthMismatch),
+                RecordError::DataArrayLengthsDiffer(_, _) => update_error_code(ErrorCode::RecordErrorDataArrayLengthsDiffer),
+                RecordError::NotMonotonic(_) => update_error_code(ErrorCode::RecordErrorNotMonotonic),
+                RecordError::OutOfInterpolationRange(_) => update_error_code(ErrorCode::RecordErrorOutOfInterpolationRange),
+                RecordError::VarBehindData(_, _) => update_error_code(ErrorCode::RecordErrorVarBehindData),
             }
         }
     }
@@ -413,7 +489,7 @@ pub extern "C" fn record_default() -> *mut Record {
 /// This allocates memory and must be destroyed by the caller
 /// (see [`record_destroy`]).
 /// - A null pointer is returned if the filename is null, a file corresponding
-/// to the filename does not exist, or the file cannot be read
+///   to the filename does not exist, or the file cannot be read
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn record_read(filename: *const c_char) -> *mut Record {
@@ -636,7 +712,7 @@ pub extern "C" fn record_get_device_number_of_entries(record: *mut Record, idx:
     }
 
     let record_ref = unsafe { &*record };
-    if check_index_bounds(idx, record_ref.header.devices.len()) == false {
+    if !check_index_bounds(idx, record_ref.header.devices.len()) {
         return update_error_code(ErrorCode::IndexOutOfBounds) as c_int
     }
 
@@ -657,7 +733,7 @@ pub extern "C" fn record_get_device_entry(record: *mut Record, device_idx: size_
     }
 
     let record_ref = unsafe { &*record };
-    if check_index_bounds(device_idx, record_ref.header.devices.len()) == false {
+    if !check_index_bounds(device_idx, record_ref.header.devices.len()) {
         update_error_code(ErrorCode::IndexOutOfBounds);
         return std::ptr::null_mut();
     }
@@ -681,7 +757,7 @@ pub extern "C" fn record_append_entry_to_device(
     }
 
     let record_ref = unsafe { &mut *record };
-    if check_index_bounds(device_idx, record_ref.header.devices.len()) == false {
+    if !check_index_bounds(device_idx, record_ref.header.devices.len()) {
         return update_error_code(ErrorCode::IndexOutOfBounds) as c_int
     }
 
@@ -778,7 +854,7 @@ pub extern "C" fn record_set_independent_variable(
     };
 
     let vals_slice = unsafe { std::slice::from_raw_parts(vals, len) };
-    let vals = vals_slice.iter().cloned().collect();
+    let vals = vals_slice.to_vec();
 
     let record_ref = unsafe { &mut *record };
     record_ref.header.independent_variable.name = name_str;
@@ -842,7 +918,7 @@ pub extern "C" fn record_get_data_array_length(record: *mut Record, idx: size_t)
     }
 
     let record_ref = unsafe { &*record };
-    if check_index_bounds(idx, record_ref.data.len()) == false {
+    if !check_index_bounds(idx, record_ref.data.len()) {
         return update_error_code(ErrorCode::IndexOutOfBounds) as c_int
     }
 
@@ -873,15 +949,15 @@ pub extern "C" fn record_get_data_array(
     }
 
     let record_ref = unsafe { &*record };
-    if check_index_bounds(idx, record_ref.data.len()) == false {
+    if !check_index_bounds(idx, record_ref.data.len()) {
         return update_error_code(ErrorCode::IndexOutOfBounds) as c_int
     }
 
     // Fill array
     for (i, item) in record_ref.data[idx].samples.iter().enumerate() {
         unsafe {
-            *real.offset(i as isize) = item.re;
-            *imag.offset(i as isize) = item.im;
+            *real.add(i) = item.re;
+            *imag.add(i) = item.im;
         }
     }
 
@@ -930,7 +1006,7 @@ pub extern "C" fn record_append_data_array(
     record_ref.data.push(DataArray {
         name: name_str,
         format: format_str,
-        samples: samples
+        samples
     });
 
     ErrorCode::NoError as c_int
@@ -956,9 +1032,9 @@ fn teardown(record_ptr: *mut Record) {
 
 /// Test runner to handle creation and destruction of pointer
 #[cfg(test)]
-fn test_runner<S, T>(setup_fn: S, test_fn: T) -> () where
+fn test_runner<S, T>(setup_fn: S, test_fn: T) where
     S: Fn() -> *mut Record,
-    T: FnOnce(*mut Record) -> () + std::panic::UnwindSafe
+    T: FnOnce(*mut Record) + std::panic::UnwindSafe
 {
     // Setup
     let record_ptr: *mut Record = setup_fn();
@@ -976,6 +1052,7 @@ fn test_runner<S, T>(setup_fn: S, test_fn: T) -> () where
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod test_runners {
     use super::*;
 
@@ -1866,7 +1943,7 @@ mod read {
                 ];
 
                 for (i, item) in expected.iter().enumerate() {
-                    let comment = record_get_device_entry(record_ptr, 0_usize, i as usize);
+                    let comment = record_get_device_entry(record_ptr, 0_usize, i);
                     assert!(!comment.is_null());
                     assert_eq!(CStr::from_ptr(comment), &CString::new(*item).unwrap()[..]);
                 }
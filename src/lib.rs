@@ -21,7 +21,41 @@
 //! !COMMENT
 //! ```
 //!
-//! These are used to provide internal comments.
+//! These are used to provide internal comments. Some files use a `COMMENT` keyword instead,
+//! which is read the same way:
+//!
+//! ```no_test
+//! COMMENT text
+//! ```
+//!
+//! ## Features
+//!
+//! Keywords are parsed with regular expressions by default (`regex-parser`). The `no-regex`
+//! feature selects a hand-written tokenizer giving the same results. Disable the default
+//! features to also drop the `regex` and `lazy_static` dependencies:
+//!
+//! ```toml
+//! citi = { version = "0.3", default-features = false, features = ["std", "no-regex"] }
+//! ```
+//!
+//! The `timestamp` feature adds `Record::append_timestamped_comment`, using `chrono`.
+//!
+//! The `mmap` feature adds the unsafe `Record::read_mmap`, which parses a file mapped into
+//! memory with `memmap2` instead of copying it through a read buffer.
+//!
+//! The default `std` feature adds reading and writing through `std::io`, and the C interface
+//! in [`ffi`]. Without it the crate is `no_std` and only needs `alloc`: records are read with
+//! [`Record::from_str_with_options`] or [`ParserState`], and written with
+//! [`Record::to_string_with_options`]. The regular expression parser needs `std`, so pick the
+//! tokenizer:
+//!
+//! ```toml
+//! citi = { version = "0.3", default-features = false, features = ["no-regex"] }
+//! ```
+//!
+//! The crate is also built as a C library (`staticlib` and `cdylib`) for the C++ and Python
+//! bindings, and those artifacts need `std` to link. Without `std`, build the Rust library
+//! alone with `cargo rustc --lib --no-default-features --crate-type rlib`.
 //!
 //! ## IO Example
 //!
@@ -56,20 +90,43 @@
 //!
 //! - ASCII representation of floating points may change because of the String -> Float -> String conversion.
 //! - Floats may be shifted in exponential format.
-//! - All `SEG_LIST` keywords will be converted to `VAR_LIST`
+//! - A `SEG_LIST` is converted to a `VAR_LIST` once the independent variable no longer matches
+//!   it, see [`Record::independent_variable_source`].
+//! - `COMMENT` lines are written with a bang unless [`WriteOptions::comment_keyword_style`] asks
+//!   otherwise.
+//! - Device (`#`) and comment (`!`) lines between data blocks are collected into the header and
+//!   written before the data. Inside a `BEGIN`/`END` block they are an error.
+//! - Data arrays in a known format other than `RI` (see [`DataFormat`]) are stored as complex
+//!   numbers and converted back on write, which may change the last digits.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "regex-parser")]
 use lazy_static::lazy_static;
 use num_complex::Complex;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+#[cfg(feature = "regex-parser")]
 use regex::Regex;
 
-use std::convert::TryFrom;
-use std::fmt;
-use std::io::BufRead;
-use std::str::FromStr;
+#[cfg(not(feature = "std"))]
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Read};
 
 use thiserror::Error;
 
 mod macros;
+#[cfg(feature = "std")]
 pub mod ffi;
 
 /// Crate error
@@ -84,9 +141,25 @@ pub enum Error {
     ReadError(#[from] ReadError),
     #[error("Error writing record: `{0}`")]
     WriteError(#[from] WriteError),
+    #[error("Error modifying record: `{0}`")]
+    RecordError(#[from] RecordError),
 }
 /// Crate interface result
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl Error {
+    /// Line number the error occured on, if known
+    ///
+    /// Line numbers are zero-based, as in [`ReadError::LineError`].
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Error::ReadError(ReadError::LineError(line, _)) => Some(*line),
+            Error::ReadError(ReadError::DataBlockWrongLength(line, _, _, _)) => Some(*line),
+            Error::ReadError(ReadError::InvalidLogSegment(line, _, _)) => Some(*line),
+            _ => None,
+        }
+    }
+}
 
 #[cfg(test)]
 mod test_error {
@@ -121,6 +194,58 @@ mod test_error {
                 "Error writing record: `Version is not defined`"
             );
         }
+
+        #[test]
+        fn record_error() {
+            let error = Error::RecordError(RecordError::WrongNumberOfSamples(2, 1));
+            assert_eq!(
+                format!("{}", error),
+                "Error modifying record: `Expected 2 samples, one per data array, found 1`"
+            );
+        }
+    }
+
+    mod test_line {
+        use super::*;
+
+        #[test]
+        fn line_error() {
+            let error = Error::ReadError(ReadError::LineError(10, ParseError::BadRegex));
+            assert_eq!(error.line(), Some(10));
+        }
+
+        #[test]
+        fn data_block_wrong_length() {
+            let error = Error::ReadError(ReadError::DataBlockWrongLength(12, 1, 3, 2));
+            assert_eq!(error.line(), Some(12));
+        }
+
+        #[test]
+        fn invalid_log_segment() {
+            let error = Error::ReadError(ReadError::InvalidLogSegment(4, 0., 1e9));
+            assert_eq!(error.line(), Some(4));
+        }
+
+        #[test]
+        fn parse_failure_while_reading() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nnot a keyword\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(error) => assert_eq!(error.line(), Some(2)),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn read_error_without_line() {
+            let error = Error::ReadError(ReadError::NoName);
+            assert_eq!(error.line(), None);
+        }
+
+        #[test]
+        fn write_error() {
+            let error = Error::WriteError(WriteError::NoVersion);
+            assert_eq!(error.line(), None);
+        }
     }
 
     mod from_error {
@@ -149,6 +274,14 @@ mod test_error {
                 e => panic!("{:?}", e),
             }
         }
+
+        #[test]
+        fn from_record_error() {
+            match Error::from(RecordError::WrongNumberOfSamples(2, 1)) {
+                Error::RecordError(RecordError::WrongNumberOfSamples(2, 1)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
     }
 }
 
@@ -161,8 +294,25 @@ pub enum ParseError {
     BadRegex,
     #[error("Cannot parse as number `{0}`")]
     NumberParseError(String),
+    #[error("Infinite or NaN value in `{0}`")]
+    NonFiniteValue(String),
+    #[error("Data pair `{0}` does not have exactly two comma separated values")]
+    MalformedDataPair(String),
+}
+// type ParseResult<T> = core::result::Result<T, ParseError>;
+
+impl ParseError {
+    /// Same error reporting `line` in place of the text that was parsed
+    fn with_line(self, line: &str) -> ParseError {
+        match self {
+            ParseError::BadKeyword(_) => ParseError::BadKeyword(String::from(line)),
+            ParseError::BadRegex => ParseError::BadRegex,
+            ParseError::NumberParseError(_) => ParseError::NumberParseError(String::from(line)),
+            ParseError::NonFiniteValue(_) => ParseError::NonFiniteValue(String::from(line)),
+            ParseError::MalformedDataPair(_) => ParseError::MalformedDataPair(String::from(line)),
+        }
+    }
 }
-// type ParseResult<T> = std::result::Result<T, ParseError>;
 
 #[cfg(test)]
 mod test_parse_error {
@@ -195,6 +345,21 @@ mod test_parse_error {
             assert_eq!(format!("{}", error), "Cannot parse as number `----`");
         }
 
+        #[test]
+        fn non_finite_value() {
+            let error = ParseError::NonFiniteValue(String::from("INF,0"));
+            assert_eq!(format!("{}", error), "Infinite or NaN value in `INF,0`");
+        }
+
+        #[test]
+        fn malformed_data_pair() {
+            let error = ParseError::MalformedDataPair(String::from("1E9,2E9,3E9"));
+            assert_eq!(
+                format!("{}", error),
+                "Data pair `1E9,2E9,3E9` does not have exactly two comma separated values"
+            );
+        }
+
         #[test]
         fn bad_regex() {
             let error = ParseError::BadRegex;
@@ -206,7 +371,7 @@ mod test_parse_error {
 /// Representation of the per-line keywords
 ///
 /// A vector of these keywords represents a file.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Keyword {
     /// CitiFile version e.g. A.01.01
     CitiFile { version: String },
@@ -230,6 +395,10 @@ pub enum Keyword {
         first: f64,
         last: f64,
         number: usize,
+        /// Optional spacing token e.g. `LOG` in `SEG LOG 1e9 1e12 4`
+        ///
+        /// Without one, points are evenly spaced.
+        spacing: Option<SegSpacing>,
     },
     /// End of independent variable segments
     SegListEnd,
@@ -249,12 +418,71 @@ pub enum Keyword {
     End,
     /// Comment (non-standard)
     Comment(String),
+    /// Unrecognised line, kept verbatim e.g. SWEEP_TYPE LOG
+    Unknown(String),
+}
+
+/// Spacing of the points in a `SEG` item
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SegSpacing {
+    /// `LIN`, evenly spaced
+    Linear,
+    /// `LOG`, evenly spaced on a logarithmic scale
+    Logarithmic,
+}
+
+impl SegSpacing {
+    fn from_token(token: &str) -> Option<SegSpacing> {
+        match token {
+            "LIN" => Some(SegSpacing::Linear),
+            "LOG" => Some(SegSpacing::Logarithmic),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SegSpacing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SegSpacing::Linear => write!(f, "LIN"),
+            SegSpacing::Logarithmic => write!(f, "LOG"),
+        }
+    }
+}
+
+/// Category of a [`Keyword`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum KeywordKind {
+    /// `CITIFILE`
+    Version,
+    /// `NAME`
+    Name,
+    /// `VAR`
+    Var,
+    /// `CONSTANT`
+    Constant,
+    /// `#` device lines
+    Device,
+    /// `SEG_LIST_BEGIN`, `SEG` items, and `SEG_LIST_END`
+    SegList,
+    /// `VAR_LIST_BEGIN`, items, and `VAR_LIST_END`
+    VarList,
+    /// `DATA`
+    Data,
+    /// Pair within a data array
+    DataPair,
+    /// `!` comments
+    Comment,
+    /// `BEGIN` and `END`
+    Control,
+    /// Unrecognised lines
+    Unknown,
 }
 
 impl FromStr for Keyword {
     type Err = ParseError;
 
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
         Keyword::try_from(s)
     }
 }
@@ -262,238 +490,780 @@ impl FromStr for Keyword {
 impl TryFrom<&str> for Keyword {
     type Error = ParseError;
 
-    fn try_from(line: &str) -> std::result::Result<Self, Self::Error> {
-        // Avoid recompiling each time
-        lazy_static! {
-            static ref RE_DEVICE: Regex = Regex::new(r"^#(?P<Name>\S+) (?P<Value>.*)$").unwrap();
-            static ref RE_VAR: Regex = Regex::new(r"^VAR (?P<Name>\S+) ?(?P<Format>\S*) (?P<Length>\d+)$").unwrap();
-            static ref RE_CITIFILE: Regex = Regex::new(r"^CITIFILE (?P<Version>\S+)$").unwrap();
-            static ref RE_NAME: Regex = Regex::new(r"^NAME (?P<Name>\S+)$").unwrap();
-            static ref RE_DATA: Regex = Regex::new(r"^DATA (?P<Name>\S+) (?P<Format>\S+)$").unwrap();
-            static ref RE_SEG_ITEM: Regex = Regex::new(r"^SEG (?P<First>[+-]?(\d+)\.?\d*[eE]?[+-]?\d+) (?P<Last>[+-]?(\d+)\.?\d*[eE]?[+-]?\d+) (?P<Number>\d+)$").unwrap();
-            static ref RE_VAR_ITEM: Regex = Regex::new(r"^(?P<Value>[+-]?(\d+)\.?\d*[eE]?[+-]?\d+)$").unwrap();
-            static ref RE_DATA_PAIR: Regex = Regex::new(r"^(?P<Real>\S+),\s*(?P<Imag>\S+)$").unwrap();
-            static ref RE_CONSTANT: Regex = Regex::new(r"^CONSTANT (?P<Name>\S+) (?P<Value>\S+)$").unwrap();
-            static ref RE_COMMENT: Regex = Regex::new(r"^!(?P<Comment>.*)$").unwrap();
-        }
-
-        match line {
-            "SEG_LIST_BEGIN" => Ok(Keyword::SegListBegin),
-            "SEG_LIST_END" => Ok(Keyword::SegListEnd),
-            "VAR_LIST_BEGIN" => Ok(Keyword::VarListBegin),
-            "VAR_LIST_END" => Ok(Keyword::VarListEnd),
-            "BEGIN" => Ok(Keyword::Begin),
-            "END" => Ok(Keyword::End),
-            _ if RE_DATA_PAIR.is_match(line) => {
-                let cap = RE_DATA_PAIR.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::DataPair {
-                    real: cap
-                        .name("Real")
-                        .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                    imag: cap
-                        .name("Imag")
-                        .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                })
-            }
-            _ if RE_DEVICE.is_match(line) => {
-                let cap = RE_DEVICE.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Device {
-                    name: String::from(
-                        cap.name("Name")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                    value: String::from(
-                        cap.name("Value")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                })
-            }
-            _ if RE_SEG_ITEM.is_match(line) => {
-                let cap = RE_SEG_ITEM.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::SegItem {
-                    first: cap
-                        .name("First")
+    #[cfg(all(feature = "regex-parser", not(feature = "no-regex")))]
+    fn try_from(line: &str) -> core::result::Result<Self, Self::Error> {
+        parse_keyword_regex(line)
+    }
+
+    #[cfg(any(feature = "no-regex", not(feature = "regex-parser")))]
+    fn try_from(line: &str) -> core::result::Result<Self, Self::Error> {
+        tokenize_keyword(line)
+    }
+}
+
+/// Parse a line with regular expressions
+#[cfg(feature = "regex-parser")]
+#[cfg_attr(feature = "no-regex", allow(dead_code))]
+fn parse_keyword_regex(line: &str) -> core::result::Result<Keyword, ParseError> {
+    // Avoid recompiling each time
+    lazy_static! {
+        static ref RE_DEVICE: Regex = Regex::new(r"^#(?P<Name>\S+) (?P<Value>.*)$").unwrap();
+        static ref RE_VAR: Regex = Regex::new(r"^VAR\s+(?P<Name>\S+)\s+(?:(?P<Format>\S+(?:\s+\S+)*)\s+)?(?P<Length>\d+)\s*$").unwrap();
+        static ref RE_CITIFILE: Regex = Regex::new(r"^CITIFILE\s+(?P<Version>\S+)\s*$").unwrap();
+        static ref RE_NAME: Regex = Regex::new(r"^NAME\s+(?P<Name>\S+)\s*$").unwrap();
+        static ref RE_DATA: Regex = Regex::new(r"^DATA\s+(?P<Name>\S+)\s+(?P<Format>\S+)\s*$").unwrap();
+        static ref RE_SEG_ITEM: Regex = Regex::new(r"^SEG\s+(?:(?P<Spacing>LIN|LOG)\s+)?(?P<First>[+-]?((\d+)\.?\d*[eE]?[+-]?\d+|(?i:inf|infinity|nan)))\s+(?P<Last>[+-]?((\d+)\.?\d*[eE]?[+-]?\d+|(?i:inf|infinity|nan)))\s+(?P<Number>\d+)\s*$").unwrap();
+        static ref RE_VAR_ITEM: Regex = Regex::new(r"^(?P<Value>[+-]?((\d+)\.?\d*[eE]?[+-]?\d+|(?i:inf|infinity|nan)))$").unwrap();
+        static ref RE_DATA_PAIR: Regex = Regex::new(r"^(?P<Real>[^\s,]+),\s*(?P<Imag>[^\s,]+)$").unwrap();
+        static ref RE_DATA_VALUES: Regex = Regex::new(r"^\S+,\s*\S+$").unwrap();
+        static ref RE_CONSTANT: Regex = Regex::new(r"^CONSTANT\s+(?P<Name>\S+)\s+(?P<Value>\S+)\s*$").unwrap();
+        static ref RE_COMMENT: Regex = Regex::new(r"^(?:!|COMMENT )(?P<Comment>.*)$").unwrap();
+    }
+
+    match line {
+        "SEG_LIST_BEGIN" => Ok(Keyword::SegListBegin),
+        "SEG_LIST_END" => Ok(Keyword::SegListEnd),
+        "VAR_LIST_BEGIN" => Ok(Keyword::VarListBegin),
+        "VAR_LIST_END" => Ok(Keyword::VarListEnd),
+        "BEGIN" => Ok(Keyword::Begin),
+        "END" => Ok(Keyword::End),
+        _ if RE_DATA_PAIR.is_match(line) => {
+            let cap = RE_DATA_PAIR.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::DataPair {
+                real: cap
+                    .name("Real")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<f64>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+                imag: cap
+                    .name("Imag")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<f64>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+            })
+        }
+        _ if RE_DATA_VALUES.is_match(line) => {
+            Err(ParseError::MalformedDataPair(String::from(line)))
+        }
+        _ if RE_DEVICE.is_match(line) => {
+            let cap = RE_DEVICE.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Device {
+                name: String::from(
+                    cap.name("Name")
                         .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                    last: cap
-                        .name("Last")
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+                value: String::from(
+                    cap.name("Value")
                         .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                    number: cap
-                        .name("Number")
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+            })
+        }
+        _ if RE_SEG_ITEM.is_match(line) => {
+            let cap = RE_SEG_ITEM.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::SegItem {
+                first: cap
+                    .name("First")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<f64>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+                last: cap
+                    .name("Last")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<f64>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+                number: cap
+                    .name("Number")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<usize>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+                spacing: cap
+                    .name("Spacing")
+                    .and_then(|m| SegSpacing::from_token(m.as_str())),
+            })
+        }
+        _ if RE_VAR_ITEM.is_match(line) => {
+            let cap = RE_VAR_ITEM.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::VarListItem(
+                cap.name("Value")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<f64>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+            ))
+        }
+        _ if RE_DATA.is_match(line) => {
+            let cap = RE_DATA.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Data {
+                name: String::from(
+                    cap.name("Name")
                         .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<usize>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                })
-            }
-            _ if RE_VAR_ITEM.is_match(line) => {
-                let cap = RE_VAR_ITEM.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::VarListItem(
-                    cap.name("Value")
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+                format: String::from(
+                    cap.name("Format")
                         .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                ))
-            }
-            _ if RE_DATA.is_match(line) => {
-                let cap = RE_DATA.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Data {
-                    name: String::from(
-                        cap.name("Name")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                    format: String::from(
-                        cap.name("Format")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                })
-            }
-            _ if RE_VAR.is_match(line) => {
-                let cap = RE_VAR.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Var {
-                    name: String::from(
-                        cap.name("Name")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                    format: String::from(
-                        cap.name("Format")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                    length: cap
-                        .name("Length")
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+            })
+        }
+        _ if RE_VAR.is_match(line) => {
+            let cap = RE_VAR.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Var {
+                name: String::from(
+                    cap.name("Name")
                         .map(|m| m.as_str())
-                        .ok_or(ParseError::BadRegex)?
-                        .parse::<usize>()
-                        .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
-                })
-            }
-            _ if RE_COMMENT.is_match(line) => {
-                let cap = RE_COMMENT.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Comment(String::from(
-                    cap.name("Comment")
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+                // Format is optional
+                format: String::from(cap.name("Format").map_or("", |m| m.as_str())),
+                length: cap
+                    .name("Length")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?
+                    .parse::<usize>()
+                    .map_err(|_| ParseError::NumberParseError(String::from(line)))?,
+            })
+        }
+        _ if RE_COMMENT.is_match(line) => {
+            let cap = RE_COMMENT.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Comment(String::from(
+                cap.name("Comment")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?,
+            )))
+        }
+        _ if RE_CITIFILE.is_match(line) => {
+            let cap = RE_CITIFILE.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::CitiFile {
+                version: String::from(
+                    cap.name("Version")
                         .map(|m| m.as_str())
                         .ok_or(ParseError::BadRegex)?,
-                )))
-            }
-            _ if RE_CITIFILE.is_match(line) => {
-                let cap = RE_CITIFILE.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::CitiFile {
-                    version: String::from(
-                        cap.name("Version")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                })
-            }
-            _ if RE_NAME.is_match(line) => {
-                let cap = RE_NAME.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Name(String::from(
+                ),
+            })
+        }
+        _ if RE_NAME.is_match(line) => {
+            let cap = RE_NAME.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Name(String::from(
+                cap.name("Name")
+                    .map(|m| m.as_str())
+                    .ok_or(ParseError::BadRegex)?,
+            )))
+        }
+        _ if RE_CONSTANT.is_match(line) => {
+            let cap = RE_CONSTANT.captures(line).ok_or(ParseError::BadRegex)?;
+            Ok(Keyword::Constant {
+                name: String::from(
                     cap.name("Name")
                         .map(|m| m.as_str())
                         .ok_or(ParseError::BadRegex)?,
-                )))
-            }
-            _ if RE_CONSTANT.is_match(line) => {
-                let cap = RE_CONSTANT.captures(line).ok_or(ParseError::BadRegex)?;
-                Ok(Keyword::Constant {
-                    name: String::from(
-                        cap.name("Name")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                    value: String::from(
-                        cap.name("Value")
-                            .map(|m| m.as_str())
-                            .ok_or(ParseError::BadRegex)?,
-                    ),
-                })
-            }
-            _ => Err(ParseError::BadKeyword(String::from(line))),
+                ),
+                value: String::from(
+                    cap.name("Value")
+                        .map(|m| m.as_str())
+                        .ok_or(ParseError::BadRegex)?,
+                ),
+            })
         }
+        _ => Err(ParseError::BadKeyword(String::from(line))),
     }
 }
 
-impl fmt::Display for Keyword {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Keyword::CitiFile { version } => write!(f, "CITIFILE {}", version),
-            Keyword::Name(name) => write!(f, "NAME {}", name),
-            Keyword::Var {
-                name,
-                format,
-                length,
-            } => write!(f, "VAR {} {} {}", name, format, length),
-            Keyword::Constant { name, value } => write!(f, "CONSTANT {} {}", name, value),
-            Keyword::Device { name, value } => write!(f, "#{} {}", name, value),
-            Keyword::SegListBegin => write!(f, "SEG_LIST_BEGIN"),
-            Keyword::SegItem {
-                first,
-                last,
-                number,
-            } => write!(f, "SEG {} {} {}", first, last, number),
-            Keyword::SegListEnd => write!(f, "SEG_LIST_END"),
-            Keyword::VarListBegin => write!(f, "VAR_LIST_BEGIN"),
-            Keyword::VarListItem(n) => write!(f, "{}", n),
-            Keyword::VarListEnd => write!(f, "VAR_LIST_END"),
-            Keyword::Data { name, format } => write!(f, "DATA {} {}", name, format),
-            Keyword::DataPair { real, imag } => write!(f, "{:E},{:E}", real, imag),
-            Keyword::Begin => write!(f, "BEGIN"),
-            Keyword::End => write!(f, "END"),
-            Keyword::Comment(comment) => write!(f, "!{}", comment),
+/// Parse a line without regular expressions
+///
+/// Selected by the `no-regex` feature. Lines are tokenized on whitespace and
+/// matched on their keyword prefix, giving the same keywords and errors as
+/// [`parse_keyword_regex`]. The one difference is that only ASCII digits
+/// are recognised: the regular expressions also match other decimal digits,
+/// which then fail with [`ParseError::NumberParseError`] rather than
+/// [`ParseError::BadKeyword`].
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn tokenize_keyword(line: &str) -> core::result::Result<Keyword, ParseError> {
+    let parse_f64 = |s: &str| {
+        s.parse::<f64>()
+            .map_err(|_| ParseError::NumberParseError(String::from(line)))
+    };
+    let parse_usize = |s: &str| {
+        s.parse::<usize>()
+            .map_err(|_| ParseError::NumberParseError(String::from(line)))
+    };
+
+    match line {
+        "SEG_LIST_BEGIN" => return Ok(Keyword::SegListBegin),
+        "SEG_LIST_END" => return Ok(Keyword::SegListEnd),
+        "VAR_LIST_BEGIN" => return Ok(Keyword::VarListBegin),
+        "VAR_LIST_END" => return Ok(Keyword::VarListEnd),
+        "BEGIN" => return Ok(Keyword::Begin),
+        "END" => return Ok(Keyword::End),
+        _ => (),
+    }
+
+    if let Some((real, imag)) = split_data_pair(line) {
+        if line.matches(',').count() > 1 {
+            return Err(ParseError::MalformedDataPair(String::from(line)));
+        }
+        return Ok(Keyword::DataPair {
+            real: parse_f64(real)?,
+            imag: parse_f64(imag)?,
+        });
+    }
+    if let Some((name, value)) = split_device(line) {
+        return Ok(Keyword::Device {
+            name: String::from(name),
+            value: String::from(value),
+        });
+    }
+    if let Some([first, last, number]) = keyword_arguments(line, "SEG") {
+        if is_number_token(first) && is_number_token(last) && is_digits(number) {
+            return Ok(Keyword::SegItem {
+                first: parse_f64(first)?,
+                last: parse_f64(last)?,
+                number: parse_usize(number)?,
+                spacing: None,
+            });
+        }
+    }
+    if let Some([spacing, first, last, number]) = keyword_arguments(line, "SEG") {
+        if let Some(spacing) = SegSpacing::from_token(spacing) {
+            if is_number_token(first) && is_number_token(last) && is_digits(number) {
+                return Ok(Keyword::SegItem {
+                    first: parse_f64(first)?,
+                    last: parse_f64(last)?,
+                    number: parse_usize(number)?,
+                    spacing: Some(spacing),
+                });
+            }
+        }
+    }
+    if is_number_token(line) {
+        return Ok(Keyword::VarListItem(parse_f64(line)?));
+    }
+    if let Some([name, format]) = keyword_arguments(line, "DATA") {
+        return Ok(Keyword::Data {
+            name: String::from(name),
+            format: String::from(format),
+        });
+    }
+    if let Some([name, length]) = keyword_arguments(line, "VAR") {
+        if is_digits(length) {
+            return Ok(Keyword::Var {
+                name: String::from(name),
+                format: String::new(),
+                length: parse_usize(length)?,
+            });
+        }
+    }
+    if let Some((name, format, length)) = var_arguments(line) {
+        if is_digits(length) {
+            return Ok(Keyword::Var {
+                name: String::from(name),
+                format: String::from(format),
+                length: parse_usize(length)?,
+            });
+        }
+    }
+    if let Some(comment) = line
+        .strip_prefix('!')
+        .or_else(|| line.strip_prefix("COMMENT "))
+    {
+        if !comment.contains('\n') {
+            return Ok(Keyword::Comment(String::from(comment)));
         }
     }
+    if let Some([version]) = keyword_arguments(line, "CITIFILE") {
+        return Ok(Keyword::CitiFile {
+            version: String::from(version),
+        });
+    }
+    if let Some([name]) = keyword_arguments(line, "NAME") {
+        return Ok(Keyword::Name(String::from(name)));
+    }
+    if let Some([name, value]) = keyword_arguments(line, "CONSTANT") {
+        return Ok(Keyword::Constant {
+            name: String::from(name),
+            value: String::from(value),
+        });
+    }
+    Err(ParseError::BadKeyword(String::from(line)))
 }
 
-#[cfg(test)]
-mod test_keywords {
-    use super::*;
+/// Exactly `N` whitespace separated arguments after `keyword`
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn keyword_arguments<'a, const N: usize>(line: &'a str, keyword: &str) -> Option<[&'a str; N]> {
+    let rest = line.strip_prefix(keyword)?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut tokens = rest.split_whitespace();
+    let mut arguments = [""; N];
+    for argument in arguments.iter_mut() {
+        *argument = tokens.next()?;
+    }
+    match tokens.next() {
+        Some(_) => None,
+        None => Some(arguments),
+    }
+}
 
-    #[cfg(test)]
-    mod test_fmt_display {
-        use super::*;
+/// Name, format and length of a `VAR` line whose format may span several words
+///
+/// The format is everything between the name and the last argument,
+/// kept with its inner whitespace.
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn var_arguments(line: &str) -> Option<(&str, &str, &str)> {
+    let rest = line.strip_prefix("VAR")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (name, rest) = rest.trim().split_once(char::is_whitespace)?;
+    let (format, length) = rest.trim_start().rsplit_once(char::is_whitespace)?;
+    Some((name, format.trim_end(), length))
+}
 
-        #[test]
-        fn citirecord_a_01_00() {
-            let keyword = Keyword::CitiFile {
-                version: String::from("A.01.00"),
-            };
-            assert_eq!("CITIFILE A.01.00", format!("{}", keyword));
-        }
+/// Real and imaginary parts of `real,imag`, with optional whitespace after the comma
+///
+/// As with a greedy match, the last comma that gives a valid split is used.
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn split_data_pair(line: &str) -> Option<(&str, &str)> {
+    line.char_indices()
+        .rev()
+        .filter(|&(_, c)| c == ',')
+        .find_map(|(i, _)| {
+            let real = &line[..i];
+            let imag = line[i + 1..].trim_start_matches(char::is_whitespace);
+            let valid = |s: &str| !s.is_empty() && !s.contains(char::is_whitespace);
+            match valid(real) && valid(imag) {
+                true => Some((real, imag)),
+                false => None,
+            }
+        })
+}
 
-        #[test]
-        fn citirecord_a_01_01() {
-            let keyword = Keyword::CitiFile {
-                version: String::from("A.01.01"),
-            };
-            assert_eq!("CITIFILE A.01.01", format!("{}", keyword));
-        }
+/// Name and value of `#NAME VALUE`
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn split_device(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('#')?;
+    let end = rest.find(char::is_whitespace)?;
+    let (name, value) = (&rest[..end], rest[end..].strip_prefix(' ')?);
+    match name.is_empty() || value.contains('\n') {
+        true => None,
+        false => Some((name, value)),
+    }
+}
 
-        #[test]
-        fn name() {
-            let keyword = Keyword::Name(String::from("CAL_SET"));
-            assert_eq!("NAME CAL_SET", format!("{}", keyword));
-        }
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn is_digits(token: &str) -> bool {
+    !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit())
+}
 
-        #[test]
-        fn var() {
+/// Whether `token` is a number as accepted in `SEG` and `VAR_LIST` items
+///
+/// That is an optional sign followed by either `inf`, `infinity` or `nan`
+/// in any case, or by `D[.]D[eE][+-]D` where the first and last digit
+/// groups are not empty.
+#[cfg(any(test, feature = "no-regex", not(feature = "regex-parser")))]
+fn is_number_token(token: &str) -> bool {
+    let token = token.strip_prefix(['+', '-']).unwrap_or(token);
+    if ["inf", "infinity", "nan"]
+        .iter()
+        .any(|word| token.eq_ignore_ascii_case(word))
+    {
+        return true;
+    }
+
+    let leading = token.bytes().take_while(u8::is_ascii_digit).count();
+    if leading == 0 {
+        return false;
+    }
+    let rest = &token[leading..];
+    if rest.is_empty() {
+        // Split between the first and last digit groups
+        return leading >= 2;
+    }
+    let (has_point, rest) = match rest.strip_prefix('.') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let fraction = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &rest[fraction..];
+    if rest.is_empty() {
+        // The last digit group is the end of the fraction
+        return has_point && fraction >= 1;
+    }
+    let rest = rest.strip_prefix(['e', 'E']).unwrap_or(rest);
+    let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    is_digits(rest)
+}
+
+#[cfg(all(test, feature = "regex-parser"))]
+mod test_tokenize_keyword {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn assert_same(line: &str) {
+        assert_eq!(
+            format!("{:?}", tokenize_keyword(line)),
+            format!("{:?}", parse_keyword_regex(line)),
+            "{:?}",
+            line
+        );
+    }
+
+    #[test]
+    fn known_lines() {
+        let lines = [
+            "",
+            " ",
+            "BEGIN",
+            "BEGIN ",
+            "END",
+            "SEG_LIST_BEGIN",
+            "SEG_LIST_END",
+            "VAR_LIST_BEGIN",
+            "VAR_LIST_END",
+            "CITIFILE A.01.00",
+            "CITIFILE  A.01.00 ",
+            "CITIFILE",
+            "CITIFILEA.01.00",
+            "CITIFILE A.01.00 extra",
+            "NAME MEMORY",
+            "NAME\tMEMORY",
+            "NAME",
+            "VAR FREQ MAG 201",
+            "VAR FREQ 201",
+            "VAR FREQ MAG",
+            "VAR FREQ MAG 201 ",
+            "VAR FREQ MAG 1e3",
+            "VAR FREQ MAG 99999999999999999999999",
+            "VAR  FREQ\tMAG   201",
+            "VAR TIME SEC LIN 100",
+            "VAR TIME SEC  LIN\t100 ",
+            "VAR TIME SEC LIN",
+            "VAR TIME SEC LIN 1e3",
+            "VARFREQ MAG 201",
+            "DATA S[1,1] RI",
+            "DATA S[1,1]",
+            "DATA S RI extra",
+            "CONSTANT A 1",
+            "CONSTANT A",
+            "#NA REGISTER 1",
+            "#NA",
+            "#NA ",
+            "#NA\tREGISTER",
+            "# NA",
+            "#NA  two spaces",
+            "#NA value\nnext",
+            "!",
+            "!A comment",
+            "!A comment\nnext",
+            "COMMENT A comment",
+            "COMMENT ",
+            "COMMENT",
+            "COMMENT  two spaces",
+            "COMMENT\ttab",
+            "COMMENTS A comment",
+            "COMMENT 1,2",
+            "COMMENT A comment\nnext",
+            "1,2",
+            "1, 2",
+            "1,\t 2",
+            "1 ,2",
+            "1,2,3",
+            "1,,2",
+            ",2",
+            "1,",
+            "a,b",
+            "-3.54545E-2,-1.38601E-3",
+            "SEG 1000000000 4000000000 10",
+            "SEG 10 20 1e3",
+            "SEG 1 2 3",
+            "SEG LIN 1e9 4e9 3",
+            "SEG LOG 1e9 1e12 4",
+            "SEG  LOG\t1 2 3 ",
+            "SEG LOG 1 2",
+            "SEG lin 1 2 3",
+            "SEG EXP 1 2 3",
+            "SEG LOG LOG 1 2 3",
+            "SEG 10 20",
+            "SEG inf -NaN 10",
+            "SEG 10 20 99999999999999999999999",
+            "10",
+            "1",
+            "1.",
+            "1.5",
+            "1.e5",
+            "1.5e",
+            "1+5",
+            "1.5-3",
+            "+10",
+            "-1.5E-10",
+            "1e+",
+            "1ee5",
+            "1.2.3",
+            ".5",
+            "inf",
+            "-Infinity",
+            "NAN",
+            "nana",
+            "SOMETHING ELSE",
+        ];
+        for line in lines.iter() {
+            assert_same(line);
+        }
+    }
+
+    #[test]
+    fn non_ascii_digits() {
+        let line = "\u{661}\u{662}";
+        match parse_keyword_regex(line) {
+            Err(ParseError::NumberParseError(_)) => (),
+            e => panic!("{:?}", e),
+        }
+        match tokenize_keyword(line) {
+            Err(ParseError::BadKeyword(_)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn random_lines() {
+        let prefixes = [
+            "",
+            "",
+            "",
+            "#",
+            "!",
+            "SEG ",
+            "VAR ",
+            "DATA ",
+            "NAME ",
+            "CITIFILE ",
+            "CONSTANT ",
+        ];
+        let alphabet = [
+            "0", "1", "5", "9", ".", "e", "E", "+", "-", ",", " ", "  ", "\t", "\n", "a", "A",
+            "inf", "nan", "Infinity", "S[1,1]", "RI", "MAG",
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20_000 {
+            let mut line = String::from(prefixes[rng.gen_range(0..prefixes.len())]);
+            for _ in 0..rng.gen_range(0..8) {
+                line.push_str(alphabet[rng.gen_range(0..alphabet.len())]);
+            }
+            assert_same(&line);
+        }
+    }
+}
+
+impl Keyword {
+    /// Category of the keyword
+    pub fn kind(&self) -> KeywordKind {
+        match self {
+            Keyword::CitiFile { .. } => KeywordKind::Version,
+            Keyword::Name(_) => KeywordKind::Name,
+            Keyword::Var { .. } => KeywordKind::Var,
+            Keyword::Constant { .. } => KeywordKind::Constant,
+            Keyword::Device { .. } => KeywordKind::Device,
+            Keyword::SegListBegin | Keyword::SegItem { .. } | Keyword::SegListEnd => {
+                KeywordKind::SegList
+            }
+            Keyword::VarListBegin | Keyword::VarListItem(_) | Keyword::VarListEnd => {
+                KeywordKind::VarList
+            }
+            Keyword::Data { .. } => KeywordKind::Data,
+            Keyword::DataPair { .. } => KeywordKind::DataPair,
+            Keyword::Begin | Keyword::End => KeywordKind::Control,
+            Keyword::Comment(_) => KeywordKind::Comment,
+            Keyword::Unknown(_) => KeywordKind::Unknown,
+        }
+    }
+
+    /// Parse a line as the reader would with `options`
+    ///
+    /// Unlike [`Keyword::from_str`], this honours the reader options e.g.
+    /// case-insensitive keywords, comment prefix, whitespace trimming and
+    /// rejection of non-finite values. Errors
+    /// report `line` as given, even if an option rewrote it before parsing.
+    pub fn parse_line(
+        line: &str,
+        options: &ReaderOptions,
+    ) -> core::result::Result<Keyword, ParseError> {
+        let trimmed = match options.trim_whitespace {
+            true => line.trim(),
+            false => line,
+        };
+        if let Some(comment) = options.comment_prefix.and_then(|p| trimmed.strip_prefix(p)) {
+            return Ok(Keyword::Comment(String::from(comment)));
+        }
+        let mut text = alloc::borrow::Cow::Borrowed(trimmed);
+        if options.case_insensitive_keywords {
+            text = alloc::borrow::Cow::Owned(uppercase_keyword(&text));
+        }
+        if options.decimal_comma {
+            if let Some(converted) = decimal_comma_line(&text) {
+                text = alloc::borrow::Cow::Owned(converted);
+            }
+        }
+        let keyword = match Keyword::from_str(&text) {
+            Err(ParseError::BadKeyword(_)) if options.preserve_unknown_keywords => {
+                Keyword::Unknown(String::from(line))
+            }
+            result => result.map_err(|e| e.with_line(line))?,
+        };
+        if options.reject_non_finite && !keyword.is_finite() {
+            return Err(ParseError::NonFiniteValue(String::from(line)));
+        }
+        Ok(keyword)
+    }
+
+    /// True unless a numeric field is infinite or NaN
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Keyword::SegItem { first, last, .. } => first.is_finite() && last.is_finite(),
+            Keyword::VarListItem(value) => value.is_finite(),
+            Keyword::DataPair { real, imag } => real.is_finite() && imag.is_finite(),
+            _ => true,
+        }
+    }
+}
+
+/// Rewrite numbers written with a decimal comma e.g. `1,5` as `1.5`
+///
+/// Only `SEG` items and lines of one or two numbers are rewritten. Two
+/// numbers are a data pair, and are joined with a comma. Returns `None` for
+/// any other line.
+fn decimal_comma_line(line: &str) -> Option<String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens.as_slice() {
+        ["SEG", numbers @ ..] => {
+            let numbers: Vec<String> = numbers.iter().map(|n| n.replace(',', ".")).collect();
+            Some(format!("SEG {}", numbers.join(" ")))
+        }
+        [_] | [_, _] => {
+            let numbers: Vec<String> = tokens.iter().map(|n| n.replace(',', ".")).collect();
+            match numbers.iter().all(|n| n.parse::<f64>().is_ok()) {
+                true => Some(numbers.join(",")),
+                false => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Uppercase the leading keyword, leaving names and values untouched
+fn uppercase_keyword(line: &str) -> String {
+    if line.starts_with('#') || line.starts_with('!') {
+        return String::from(line);
+    }
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let keyword = line[..end].to_uppercase();
+    let rest = &line[end..];
+    if keyword == "SEG" {
+        // The optional spacing token is a keyword too
+        let trimmed = rest.trim_start();
+        let spacing_end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        let spacing = &trimmed[..spacing_end];
+        if spacing.eq_ignore_ascii_case("LIN") || spacing.eq_ignore_ascii_case("LOG") {
+            return format!(
+                "{}{}{}{}",
+                keyword,
+                &rest[..rest.len() - trimmed.len()],
+                spacing.to_uppercase(),
+                &trimmed[spacing_end..]
+            );
+        }
+    }
+    format!("{}{}", keyword, rest)
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Keyword::CitiFile { version } => write!(f, "CITIFILE {}", version),
+            Keyword::Name(name) => write!(f, "NAME {}", name),
+            Keyword::Var {
+                name,
+                format,
+                length,
+            } => match format.is_empty() {
+                true => write!(f, "VAR {} {}", name, length),
+                false => write!(f, "VAR {} {} {}", name, format, length),
+            },
+            Keyword::Constant { name, value } => write!(f, "CONSTANT {} {}", name, value),
+            Keyword::Device { name, value } => write!(f, "#{} {}", name, value),
+            Keyword::SegListBegin => write!(f, "SEG_LIST_BEGIN"),
+            Keyword::SegItem {
+                first,
+                last,
+                number,
+                spacing: None,
+            } => write!(f, "SEG {} {} {}", first, last, number),
+            Keyword::SegItem {
+                first,
+                last,
+                number,
+                spacing: Some(spacing),
+            } => write!(f, "SEG {} {} {} {}", spacing, first, last, number),
+            Keyword::SegListEnd => write!(f, "SEG_LIST_END"),
+            Keyword::VarListBegin => write!(f, "VAR_LIST_BEGIN"),
+            Keyword::VarListItem(n) => write!(f, "{}", n),
+            Keyword::VarListEnd => write!(f, "VAR_LIST_END"),
+            Keyword::Data { name, format } => write!(f, "DATA {} {}", name, format),
+            Keyword::DataPair { real, imag } => write!(f, "{:E},{:E}", real, imag),
+            Keyword::Begin => write!(f, "BEGIN"),
+            Keyword::End => write!(f, "END"),
+            Keyword::Comment(comment) => write!(f, "!{}", comment),
+            Keyword::Unknown(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_keywords {
+    use super::*;
+
+    #[cfg(test)]
+    mod test_fmt_display {
+        use super::*;
+
+        #[test]
+        fn unknown() {
+            let keyword = Keyword::Unknown(String::from("SWEEP_TYPE LOG"));
+            assert_eq!(format!("{}", keyword), "SWEEP_TYPE LOG");
+        }
+
+        #[test]
+        fn citirecord_a_01_00() {
+            let keyword = Keyword::CitiFile {
+                version: String::from("A.01.00"),
+            };
+            assert_eq!("CITIFILE A.01.00", format!("{}", keyword));
+        }
+
+        #[test]
+        fn citirecord_a_01_01() {
+            let keyword = Keyword::CitiFile {
+                version: String::from("A.01.01"),
+            };
+            assert_eq!("CITIFILE A.01.01", format!("{}", keyword));
+        }
+
+        #[test]
+        fn name() {
+            let keyword = Keyword::Name(String::from("CAL_SET"));
+            assert_eq!("NAME CAL_SET", format!("{}", keyword));
+        }
+
+        #[test]
+        fn var() {
             let keyword = Keyword::Var {
                 name: String::from("FREQ"),
                 format: String::from("MAG"),
@@ -502,6 +1272,26 @@ mod test_keywords {
             assert_eq!("VAR FREQ MAG 201", format!("{}", keyword));
         }
 
+        #[test]
+        fn var_no_format() {
+            let keyword = Keyword::Var {
+                name: String::from("TIME[0]"),
+                format: String::new(),
+                length: 100,
+            };
+            assert_eq!("VAR TIME[0] 100", format!("{}", keyword));
+        }
+
+        #[test]
+        fn var_multi_word_format() {
+            let keyword = Keyword::Var {
+                name: String::from("TIME"),
+                format: String::from("SEC LIN"),
+                length: 100,
+            };
+            assert_eq!("VAR TIME SEC LIN 100", format!("{}", keyword));
+        }
+
         #[test]
         fn constant() {
             let keyword = Keyword::Constant {
@@ -550,10 +1340,22 @@ mod test_keywords {
                 first: 1000000000.,
                 last: 4000000000.,
                 number: 10,
+                spacing: None,
             };
             assert_eq!("SEG 1000000000 4000000000 10", format!("{}", keyword));
         }
 
+        #[test]
+        fn seg_item_spacing() {
+            let keyword = Keyword::SegItem {
+                first: 1e9,
+                last: 1e12,
+                number: 4,
+                spacing: Some(SegSpacing::Logarithmic),
+            };
+            assert_eq!("SEG LOG 1000000000 1000000000000 4", format!("{}", keyword));
+        }
+
         #[test]
         fn seg_list_end() {
             let keyword = Keyword::SegListEnd;
@@ -633,321 +1435,423 @@ mod test_keywords {
         }
     }
 
-    #[cfg(test)]
-    mod test_from_str_slice {
+    mod test_kind {
         use super::*;
-        use approx::*;
 
         #[test]
-        fn fails_on_bad_string() {
-            match Keyword::from_str("this is a bad string") {
-                Err(ParseError::BadKeyword(bad_string)) => {
-                    assert_eq!(bad_string, "this is a bad string")
-                }
-                e => panic!("{:?}", e),
-            }
+        fn header() {
+            let version = Keyword::CitiFile {
+                version: String::from("A.01.00"),
+            };
+            assert_eq!(version.kind(), KeywordKind::Version);
+            assert_eq!(
+                Keyword::Name(String::from("CAL_SET")).kind(),
+                KeywordKind::Name
+            );
+            let var = Keyword::Var {
+                name: String::from("FREQ"),
+                format: String::from("MAG"),
+                length: 201,
+            };
+            assert_eq!(var.kind(), KeywordKind::Var);
+            let device = Keyword::Device {
+                name: String::from("NA"),
+                value: String::from("VERSION HP8510B.05.00"),
+            };
+            assert_eq!(device.kind(), KeywordKind::Device);
+            assert_eq!(Keyword::Comment(String::new()).kind(), KeywordKind::Comment);
         }
 
         #[test]
-        fn citirecord_a_01_00() {
-            match Keyword::from_str("CITIFILE A.01.00") {
-                Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.00"),
-                e => panic!("{:?}", e),
-            }
+        fn lists() {
+            assert_eq!(Keyword::SegListBegin.kind(), KeywordKind::SegList);
+            let item = Keyword::SegItem {
+                first: 1e9,
+                last: 2e9,
+                number: 3,
+                spacing: None,
+            };
+            assert_eq!(item.kind(), KeywordKind::SegList);
+            assert_eq!(Keyword::VarListItem(1e9).kind(), KeywordKind::VarList);
+            assert_eq!(Keyword::VarListEnd.kind(), KeywordKind::VarList);
         }
 
         #[test]
-        fn citirecord_a_01_01() {
-            match Keyword::from_str("CITIFILE A.01.01") {
-                Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.01"),
-                e => panic!("{:?}", e),
-            }
+        fn data() {
+            let data = Keyword::Data {
+                name: String::from("S[1,1]"),
+                format: String::from("RI"),
+            };
+            assert_eq!(data.kind(), KeywordKind::Data);
+            let pair = Keyword::DataPair { real: 1., imag: 2. };
+            assert_eq!(pair.kind(), KeywordKind::DataPair);
+            assert_eq!(Keyword::Begin.kind(), KeywordKind::Control);
+            assert_eq!(Keyword::End.kind(), KeywordKind::Control);
         }
 
         #[test]
-        fn name_cal_set() {
-            match Keyword::from_str("NAME CAL_SET") {
-                Ok(Keyword::Name(name)) => assert_eq!(name, "CAL_SET"),
-                e => panic!("{:?}", e),
-            }
+        fn unknown() {
+            let keyword = Keyword::Unknown(String::from("SWEEP_TYPE LOG"));
+            assert_eq!(keyword.kind(), KeywordKind::Unknown);
         }
+    }
+
+    mod test_is_finite {
+        use super::*;
 
         #[test]
-        fn name_raw_data() {
-            match Keyword::from_str("NAME RAW_DATA") {
-                Ok(Keyword::Name(name)) => assert_eq!(name, "RAW_DATA"),
-                e => panic!("{:?}", e),
-            }
+        fn data_pair() {
+            assert!(Keyword::DataPair { real: 1., imag: 2. }.is_finite());
         }
 
         #[test]
-        fn constant() {
-            match Keyword::from_str("CONSTANT A_CONSTANT 1.2345") {
-                Ok(Keyword::Constant { name, value }) => {
-                    assert_eq!(name, "A_CONSTANT");
-                    assert_eq!(value, "1.2345");
-                }
+        fn data_pair_infinite() {
+            let keyword = Keyword::DataPair {
+                real: f64::INFINITY,
+                imag: 0.,
+            };
+            assert!(!keyword.is_finite());
+        }
+
+        #[test]
+        fn data_pair_nan() {
+            let keyword = Keyword::DataPair {
+                real: 0.,
+                imag: f64::NAN,
+            };
+            assert!(!keyword.is_finite());
+        }
+
+        #[test]
+        fn var_list_item() {
+            assert!(!Keyword::VarListItem(f64::NEG_INFINITY).is_finite());
+        }
+
+        #[test]
+        fn seg_item() {
+            let keyword = Keyword::SegItem {
+                first: 1.,
+                last: f64::NAN,
+                number: 2,
+                spacing: None,
+            };
+            assert!(!keyword.is_finite());
+        }
+
+        #[test]
+        fn other() {
+            assert!(Keyword::Begin.is_finite());
+        }
+    }
+
+    mod test_parse_line {
+        use super::*;
+
+        #[test]
+        fn preserve_unknown_keywords() {
+            let options = ReaderOptions {
+                preserve_unknown_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Keyword::parse_line("SWEEP_TYPE LOG", &options) {
+                Ok(Keyword::Unknown(line)) => assert_eq!(line, "SWEEP_TYPE LOG"),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn device() {
-            match Keyword::from_str("#NA REGISTER 1") {
-                Ok(Keyword::Device { name, value }) => {
-                    assert_eq!(name, "NA");
-                    assert_eq!(value, "REGISTER 1");
-                }
+        fn unknown_keywords_rejected_by_default() {
+            match Keyword::parse_line("SWEEP_TYPE LOG", &ReaderOptions::default()) {
+                Err(ParseError::BadKeyword(_)) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn device_number() {
-            match Keyword::from_str("#NA POWER2 1.0E1") {
-                Ok(Keyword::Device { name, value }) => {
-                    assert_eq!(name, "NA");
-                    assert_eq!(value, "POWER2 1.0E1");
-                }
+        fn default_options() {
+            match Keyword::parse_line("BEGIN", &ReaderOptions::default()) {
+                Ok(Keyword::Begin) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn device_name() {
-            match Keyword::from_str("#WVI A B") {
-                Ok(Keyword::Device { name, value }) => {
-                    assert_eq!(name, "WVI");
-                    assert_eq!(value, "A B");
-                }
+        fn lowercase_rejected_by_default() {
+            match Keyword::parse_line("var Freq MAG 3", &ReaderOptions::default()) {
+                Err(ParseError::BadKeyword(_)) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn var() {
-            match Keyword::from_str("VAR FREQ MAG 201") {
+        fn lowercase_case_insensitive() {
+            let options = ReaderOptions {
+                case_insensitive_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Keyword::parse_line("var Freq MAG 3", &options) {
                 Ok(Keyword::Var {
                     name,
                     format,
                     length,
                 }) => {
-                    assert_eq!(name, "FREQ");
+                    assert_eq!(name, "Freq");
                     assert_eq!(format, "MAG");
-                    assert_eq!(length, 201);
+                    assert_eq!(length, 3);
                 }
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn seg_list_begin() {
-            match Keyword::from_str("SEG_LIST_BEGIN") {
-                Ok(Keyword::SegListBegin) => (),
+        fn seg_spacing_case_insensitive() {
+            let options = ReaderOptions {
+                case_insensitive_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Keyword::parse_line("seg log 1e9 1e12 4", &options) {
+                Ok(Keyword::SegItem {
+                    spacing: Some(SegSpacing::Logarithmic),
+                    number: 4,
+                    ..
+                }) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn seg_item() {
-            match Keyword::from_str("SEG 1000000000 4000000000 10") {
-                Ok(Keyword::SegItem {
-                    first,
-                    last,
-                    number,
-                }) => {
-                    assert_relative_eq!(first, 1000000000.);
-                    assert_relative_eq!(last, 4000000000.);
-                    assert_eq!(number, 10);
-                }
-                e => panic!("{:?}", e),
-            }
-        }
-
-        #[test]
-        fn seg_item_exponential() {
-            match Keyword::from_str("SEG 1e9 1E4 100") {
-                Ok(Keyword::SegItem {
-                    first,
-                    last,
-                    number,
-                }) => {
-                    assert_relative_eq!(first, 1e9);
-                    assert_relative_eq!(last, 1e4);
-                    assert_eq!(number, 100);
-                }
-                e => panic!("{:?}", e),
-            }
-        }
-
-        #[test]
-        fn seg_item_negative() {
-            match Keyword::from_str("SEG -1e9 1E-4 1") {
-                Ok(Keyword::SegItem {
-                    first,
-                    last,
-                    number,
-                }) => {
-                    assert_relative_eq!(first, -1e9);
-                    assert_relative_eq!(last, 1e-4);
-                    assert_eq!(number, 1);
+        fn device_and_comment_untouched() {
+            let options = ReaderOptions {
+                case_insensitive_keywords: true,
+                ..ReaderOptions::default()
+            };
+            assert_eq!(
+                Keyword::parse_line("#NA version a", &options).unwrap(),
+                Keyword::Device {
+                    name: String::from("NA"),
+                    value: String::from("version a")
                 }
-                e => panic!("{:?}", e),
-            }
+            );
+            assert_eq!(
+                Keyword::parse_line("!comment", &options).unwrap(),
+                Keyword::Comment(String::from("comment"))
+            );
         }
 
         #[test]
-        fn seg_list_end() {
-            match Keyword::from_str("SEG_LIST_END") {
-                Ok(Keyword::SegListEnd) => (),
+        fn comment_keyword_case_insensitive() {
+            let options = ReaderOptions {
+                case_insensitive_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Keyword::parse_line("Comment hello", &options) {
+                Ok(Keyword::Comment(s)) => assert_eq!(s, "hello"),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn var_list_begin() {
-            match Keyword::from_str("VAR_LIST_BEGIN") {
-                Ok(Keyword::VarListBegin) => (),
+        fn reject_non_finite() {
+            let options = ReaderOptions {
+                reject_non_finite: true,
+                ..ReaderOptions::default()
+            };
+            match Keyword::parse_line("0,NAN", &options) {
+                Err(ParseError::NonFiniteValue(line)) => assert_eq!(line, "0,NAN"),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn var_item() {
-            match Keyword::from_str("100000") {
-                Ok(Keyword::VarListItem(value)) => {
-                    assert_relative_eq!(value, 100000.);
-                }
+        fn comment_prefix() {
+            let options = ReaderOptions {
+                comment_prefix: Some(';'),
+                ..ReaderOptions::default()
+            };
+            assert_eq!(
+                Keyword::parse_line(";A comment", &options).unwrap(),
+                Keyword::Comment(String::from("A comment"))
+            );
+            assert_eq!(
+                Keyword::parse_line("!Still a comment", &options).unwrap(),
+                Keyword::Comment(String::from("Still a comment"))
+            );
+            match Keyword::parse_line(";A comment", &ReaderOptions::default()) {
+                Err(ParseError::BadKeyword(line)) => assert_eq!(line, ";A comment"),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn var_item_exponential() {
-            match Keyword::from_str("100E+6") {
-                Ok(Keyword::VarListItem(value)) => {
-                    assert_relative_eq!(value, 100E+6);
-                }
+        fn trim_whitespace() {
+            let options = ReaderOptions {
+                trim_whitespace: true,
+                ..ReaderOptions::default()
+            };
+            assert_eq!(
+                Keyword::parse_line("  BEGIN \t", &options).unwrap(),
+                Keyword::Begin
+            );
+            assert_eq!(
+                Keyword::parse_line("\t1E0,2E0 ", &options).unwrap(),
+                Keyword::DataPair { real: 1., imag: 2. }
+            );
+            match Keyword::parse_line("  BEGIN", &ReaderOptions::default()) {
+                Err(ParseError::BadKeyword(line)) => assert_eq!(line, "  BEGIN"),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn var_item_negative_exponential() {
-            match Keyword::from_str("-1e-2") {
-                Ok(Keyword::VarListItem(value)) => {
-                    assert_relative_eq!(value, -1e-2);
-                }
-                e => panic!("{:?}", e),
-            }
+        fn trim_whitespace_before_comment_prefix() {
+            let options = ReaderOptions {
+                comment_prefix: Some('%'),
+                trim_whitespace: true,
+                ..ReaderOptions::default()
+            };
+            assert_eq!(
+                Keyword::parse_line("  % note ", &options).unwrap(),
+                Keyword::Comment(String::from(" note"))
+            );
         }
 
         #[test]
-        fn var_item_negative() {
-            match Keyword::from_str("-100000") {
-                Ok(Keyword::VarListItem(value)) => {
-                    assert_relative_eq!(value, -100000.);
-                }
-                e => panic!("{:?}", e),
-            }
+        fn read_with_comment_prefix_and_whitespace() {
+            let contents = "CITIFILE A.01.00\n  NAME MEMORY\n;A comment\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n  1E0,2E0\nEND  \n";
+            let options = ReaderOptions {
+                comment_prefix: Some(';'),
+                trim_whitespace: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            assert_eq!(record.header.name, "MEMORY");
+            assert_eq!(record.header.comments, vec!["A comment"]);
+            assert_eq!(record.data[0].samples, vec![Complex::new(1., 2.)]);
+            assert!(Record::from_reader(&mut contents.as_bytes()).is_err());
         }
 
-        #[test]
-        fn var_list_end() {
-            match Keyword::from_str("VAR_LIST_END") {
-                Ok(Keyword::VarListEnd) => (),
-                e => panic!("{:?}", e),
-            }
-        }
+        mod test_decimal_comma {
+            use super::*;
+            use approx::assert_relative_eq;
 
-        #[test]
-        fn data_s11() {
-            match Keyword::from_str("DATA S[1,1] RI") {
-                Ok(Keyword::Data { name, format }) => {
-                    assert_eq!(name, "S[1,1]");
-                    assert_eq!(format, "RI");
+            fn options() -> ReaderOptions {
+                ReaderOptions {
+                    decimal_comma: true,
+                    ..ReaderOptions::default()
                 }
-                e => panic!("{:?}", e),
             }
-        }
 
-        #[test]
-        fn data_e() {
-            match Keyword::from_str("DATA E RI") {
-                Ok(Keyword::Data { name, format }) => {
-                    assert_eq!(name, "E");
-                    assert_eq!(format, "RI");
+            #[test]
+            fn var_list_item() {
+                match Keyword::parse_line("1,5", &options()) {
+                    Ok(Keyword::VarListItem(value)) => assert_relative_eq!(value, 1.5),
+                    e => panic!("{:?}", e),
                 }
-                e => panic!("{:?}", e),
             }
-        }
 
-        #[test]
-        fn data_pair_simple() {
-            match Keyword::from_str("1E9,-1E9") {
-                Ok(Keyword::DataPair { real, imag }) => {
-                    assert_relative_eq!(real, 1e9);
-                    assert_relative_eq!(imag, -1e9);
+            #[test]
+            fn var_list_item_default() {
+                match Keyword::parse_line("1,5", &ReaderOptions::default()) {
+                    Ok(Keyword::DataPair { real, imag }) => {
+                        assert_relative_eq!(real, 1.);
+                        assert_relative_eq!(imag, 5.);
+                    }
+                    e => panic!("{:?}", e),
                 }
-                e => panic!("{:?}", e),
             }
-        }
 
-        #[test]
-        fn data_pair() {
-            match Keyword::from_str("8.6303E-2,-8.98651E-1") {
-                Ok(Keyword::DataPair { real, imag }) => {
-                    assert_relative_eq!(real, 0.86303e-1);
-                    assert_relative_eq!(imag, -8.98651e-1);
+            #[test]
+            fn seg_item() {
+                match Keyword::parse_line("SEG 1,5E9 2,5E9 101", &options()) {
+                    Ok(Keyword::SegItem {
+                        first,
+                        last,
+                        number,
+                        spacing: None,
+                    }) => {
+                        assert_relative_eq!(first, 1.5e9);
+                        assert_relative_eq!(last, 2.5e9);
+                        assert_eq!(number, 101);
+                    }
+                    e => panic!("{:?}", e),
                 }
-                e => panic!("{:?}", e),
             }
-        }
 
-        #[test]
-        fn data_pair_spaced() {
-            match Keyword::from_str("8.6303E-2, -8.98651E-1") {
-                Ok(Keyword::DataPair { real, imag }) => {
-                    assert_relative_eq!(real, 0.86303e-1);
-                    assert_relative_eq!(imag, -8.98651e-1);
+            #[test]
+            fn data_pair() {
+                match Keyword::parse_line("1,5 -2,25E-1", &options()) {
+                    Ok(Keyword::DataPair { real, imag }) => {
+                        assert_relative_eq!(real, 1.5);
+                        assert_relative_eq!(imag, -0.225);
+                    }
+                    e => panic!("{:?}", e),
                 }
-                e => panic!("{:?}", e),
             }
-        }
 
-        #[test]
-        fn begin() {
-            match Keyword::from_str("BEGIN") {
-                Ok(Keyword::Begin) => (),
-                e => panic!("{:?}", e),
+            #[test]
+            fn keywords_untouched() {
+                assert_eq!(
+                    Keyword::parse_line("DATA S[1,1] RI", &options()).unwrap(),
+                    Keyword::Data {
+                        name: String::from("S[1,1]"),
+                        format: String::from("RI"),
+                    }
+                );
+                assert_eq!(
+                    Keyword::parse_line("CONSTANT A 1,5", &options()).unwrap(),
+                    Keyword::Constant {
+                        name: String::from("A"),
+                        value: String::from("1,5"),
+                    }
+                );
+                assert_eq!(
+                    Keyword::parse_line("#NA 1,5", &options()).unwrap(),
+                    Keyword::Device {
+                        name: String::from("NA"),
+                        value: String::from("1,5"),
+                    }
+                );
             }
-        }
 
-        #[test]
-        fn end() {
-            match Keyword::from_str("END") {
-                Ok(Keyword::End) => (),
-                e => panic!("{:?}", e),
+            #[test]
+            fn error_reports_original_line() {
+                match Keyword::parse_line("SEG 1,5E9 2,5E9 1,5", &options()) {
+                    Err(ParseError::BadKeyword(line)) => assert_eq!(line, "SEG 1,5E9 2,5E9 1,5"),
+                    e => panic!("{:?}", e),
+                }
+                let options = ReaderOptions {
+                    case_insensitive_keywords: true,
+                    ..options()
+                };
+                match Keyword::parse_line("seg 1,5E9 2,5E9 1,5", &options) {
+                    Err(ParseError::BadKeyword(line)) => assert_eq!(line, "seg 1,5E9 2,5E9 1,5"),
+                    e => panic!("{:?}", e),
+                }
             }
-        }
 
-        #[test]
-        fn comment() {
-            match Keyword::from_str("!DATE: 2019.11.01") {
-                Ok(Keyword::Comment(s)) => assert_eq!(s, "DATE: 2019.11.01"),
-                e => panic!("{:?}", e),
+            #[test]
+            fn read() {
+                let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1,5\n2,5\nVAR_LIST_END\nDATA S RI\nBEGIN\n0,5 -1,0\n1,0 0,0\nEND\n";
+                let record =
+                    Record::from_reader_with_options(&mut contents.as_bytes(), &options()).unwrap();
+                assert_array_relative_eq!(record.header.independent_variable.data, [1.5, 2.5]);
+                assert_complex_array_relative_eq!(
+                    record.data[0].samples,
+                    [Complex::new(0.5, -1.), Complex::new(1., 0.)]
+                );
             }
         }
     }
 
     #[cfg(test)]
-    mod test_try_from_string_slice {
+    mod test_from_str_slice {
         use super::*;
         use approx::*;
 
         #[test]
         fn fails_on_bad_string() {
-            match Keyword::try_from("this is a bad string") {
+            match Keyword::from_str("this is a bad string") {
                 Err(ParseError::BadKeyword(bad_string)) => {
                     assert_eq!(bad_string, "this is a bad string")
                 }
@@ -957,7 +1861,7 @@ mod test_keywords {
 
         #[test]
         fn citirecord_a_01_00() {
-            match Keyword::try_from("CITIFILE A.01.00") {
+            match Keyword::from_str("CITIFILE A.01.00") {
                 Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.00"),
                 e => panic!("{:?}", e),
             }
@@ -965,7 +1869,7 @@ mod test_keywords {
 
         #[test]
         fn citirecord_a_01_01() {
-            match Keyword::try_from("CITIFILE A.01.01") {
+            match Keyword::from_str("CITIFILE A.01.01") {
                 Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.01"),
                 e => panic!("{:?}", e),
             }
@@ -973,7 +1877,7 @@ mod test_keywords {
 
         #[test]
         fn name_cal_set() {
-            match Keyword::try_from("NAME CAL_SET") {
+            match Keyword::from_str("NAME CAL_SET") {
                 Ok(Keyword::Name(name)) => assert_eq!(name, "CAL_SET"),
                 e => panic!("{:?}", e),
             }
@@ -981,7 +1885,7 @@ mod test_keywords {
 
         #[test]
         fn name_raw_data() {
-            match Keyword::try_from("NAME RAW_DATA") {
+            match Keyword::from_str("NAME RAW_DATA") {
                 Ok(Keyword::Name(name)) => assert_eq!(name, "RAW_DATA"),
                 e => panic!("{:?}", e),
             }
@@ -989,7 +1893,7 @@ mod test_keywords {
 
         #[test]
         fn constant() {
-            match Keyword::try_from("CONSTANT A_CONSTANT 1.2345") {
+            match Keyword::from_str("CONSTANT A_CONSTANT 1.2345") {
                 Ok(Keyword::Constant { name, value }) => {
                     assert_eq!(name, "A_CONSTANT");
                     assert_eq!(value, "1.2345");
@@ -1000,7 +1904,7 @@ mod test_keywords {
 
         #[test]
         fn device() {
-            match Keyword::try_from("#NA REGISTER 1") {
+            match Keyword::from_str("#NA REGISTER 1") {
                 Ok(Keyword::Device { name, value }) => {
                     assert_eq!(name, "NA");
                     assert_eq!(value, "REGISTER 1");
@@ -1011,7 +1915,7 @@ mod test_keywords {
 
         #[test]
         fn device_number() {
-            match Keyword::try_from("#NA POWER2 1.0E1") {
+            match Keyword::from_str("#NA POWER2 1.0E1") {
                 Ok(Keyword::Device { name, value }) => {
                     assert_eq!(name, "NA");
                     assert_eq!(value, "POWER2 1.0E1");
@@ -1022,7 +1926,7 @@ mod test_keywords {
 
         #[test]
         fn device_name() {
-            match Keyword::try_from("#WVI A B") {
+            match Keyword::from_str("#WVI A B") {
                 Ok(Keyword::Device { name, value }) => {
                     assert_eq!(name, "WVI");
                     assert_eq!(value, "A B");
@@ -1033,7 +1937,7 @@ mod test_keywords {
 
         #[test]
         fn var() {
-            match Keyword::try_from("VAR FREQ MAG 201") {
+            match Keyword::from_str("VAR FREQ MAG 201") {
                 Ok(Keyword::Var {
                     name,
                     format,
@@ -1049,7 +1953,7 @@ mod test_keywords {
 
         #[test]
         fn seg_list_begin() {
-            match Keyword::try_from("SEG_LIST_BEGIN") {
+            match Keyword::from_str("SEG_LIST_BEGIN") {
                 Ok(Keyword::SegListBegin) => (),
                 e => panic!("{:?}", e),
             }
@@ -1057,11 +1961,12 @@ mod test_keywords {
 
         #[test]
         fn seg_item() {
-            match Keyword::try_from("SEG 1000000000 4000000000 10") {
+            match Keyword::from_str("SEG 1000000000 4000000000 10") {
                 Ok(Keyword::SegItem {
                     first,
                     last,
                     number,
+                    spacing: None,
                 }) => {
                     assert_relative_eq!(first, 1000000000.);
                     assert_relative_eq!(last, 4000000000.);
@@ -1071,13 +1976,48 @@ mod test_keywords {
             }
         }
 
+        #[test]
+        fn seg_item_linear() {
+            match Keyword::from_str("SEG LIN 1e9 4e9 3") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: Some(SegSpacing::Linear),
+                }) => {
+                    assert_relative_eq!(first, 1e9);
+                    assert_relative_eq!(last, 4e9);
+                    assert_eq!(number, 3);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn seg_item_logarithmic() {
+            match Keyword::from_str("SEG LOG 1e9 1e12 4") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: Some(SegSpacing::Logarithmic),
+                }) => {
+                    assert_relative_eq!(first, 1e9);
+                    assert_relative_eq!(last, 1e12);
+                    assert_eq!(number, 4);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
         #[test]
         fn seg_item_exponential() {
-            match Keyword::try_from("SEG 1e9 1E4 100") {
+            match Keyword::from_str("SEG 1e9 1E4 100") {
                 Ok(Keyword::SegItem {
                     first,
                     last,
                     number,
+                    spacing: None,
                 }) => {
                     assert_relative_eq!(first, 1e9);
                     assert_relative_eq!(last, 1e4);
@@ -1089,11 +2029,12 @@ mod test_keywords {
 
         #[test]
         fn seg_item_negative() {
-            match Keyword::try_from("SEG -1e9 1E-4 1") {
+            match Keyword::from_str("SEG -1e9 1E-4 1") {
                 Ok(Keyword::SegItem {
                     first,
                     last,
                     number,
+                    spacing: None,
                 }) => {
                     assert_relative_eq!(first, -1e9);
                     assert_relative_eq!(last, 1e-4);
@@ -1105,7 +2046,7 @@ mod test_keywords {
 
         #[test]
         fn seg_list_end() {
-            match Keyword::try_from("SEG_LIST_END") {
+            match Keyword::from_str("SEG_LIST_END") {
                 Ok(Keyword::SegListEnd) => (),
                 e => panic!("{:?}", e),
             }
@@ -1113,7 +2054,7 @@ mod test_keywords {
 
         #[test]
         fn var_list_begin() {
-            match Keyword::try_from("VAR_LIST_BEGIN") {
+            match Keyword::from_str("VAR_LIST_BEGIN") {
                 Ok(Keyword::VarListBegin) => (),
                 e => panic!("{:?}", e),
             }
@@ -1121,7 +2062,7 @@ mod test_keywords {
 
         #[test]
         fn var_item() {
-            match Keyword::try_from("100000") {
+            match Keyword::from_str("100000") {
                 Ok(Keyword::VarListItem(value)) => {
                     assert_relative_eq!(value, 100000.);
                 }
@@ -1131,7 +2072,7 @@ mod test_keywords {
 
         #[test]
         fn var_item_exponential() {
-            match Keyword::try_from("100E+6") {
+            match Keyword::from_str("100E+6") {
                 Ok(Keyword::VarListItem(value)) => {
                     assert_relative_eq!(value, 100E+6);
                 }
@@ -1141,7 +2082,7 @@ mod test_keywords {
 
         #[test]
         fn var_item_negative_exponential() {
-            match Keyword::try_from("-1e-2") {
+            match Keyword::from_str("-1e-2") {
                 Ok(Keyword::VarListItem(value)) => {
                     assert_relative_eq!(value, -1e-2);
                 }
@@ -1151,7 +2092,7 @@ mod test_keywords {
 
         #[test]
         fn var_item_negative() {
-            match Keyword::try_from("-100000") {
+            match Keyword::from_str("-100000") {
                 Ok(Keyword::VarListItem(value)) => {
                     assert_relative_eq!(value, -100000.);
                 }
@@ -1161,7 +2102,7 @@ mod test_keywords {
 
         #[test]
         fn var_list_end() {
-            match Keyword::try_from("VAR_LIST_END") {
+            match Keyword::from_str("VAR_LIST_END") {
                 Ok(Keyword::VarListEnd) => (),
                 e => panic!("{:?}", e),
             }
@@ -1169,7 +2110,7 @@ mod test_keywords {
 
         #[test]
         fn data_s11() {
-            match Keyword::try_from("DATA S[1,1] RI") {
+            match Keyword::from_str("DATA S[1,1] RI") {
                 Ok(Keyword::Data { name, format }) => {
                     assert_eq!(name, "S[1,1]");
                     assert_eq!(format, "RI");
@@ -1180,7 +2121,7 @@ mod test_keywords {
 
         #[test]
         fn data_e() {
-            match Keyword::try_from("DATA E RI") {
+            match Keyword::from_str("DATA E RI") {
                 Ok(Keyword::Data { name, format }) => {
                     assert_eq!(name, "E");
                     assert_eq!(format, "RI");
@@ -1191,7 +2132,7 @@ mod test_keywords {
 
         #[test]
         fn data_pair_simple() {
-            match Keyword::try_from("1E9,-1E9") {
+            match Keyword::from_str("1E9,-1E9") {
                 Ok(Keyword::DataPair { real, imag }) => {
                     assert_relative_eq!(real, 1e9);
                     assert_relative_eq!(imag, -1e9);
@@ -1202,7 +2143,7 @@ mod test_keywords {
 
         #[test]
         fn data_pair() {
-            match Keyword::try_from("8.6303E-2,-8.98651E-1") {
+            match Keyword::from_str("8.6303E-2,-8.98651E-1") {
                 Ok(Keyword::DataPair { real, imag }) => {
                     assert_relative_eq!(real, 0.86303e-1);
                     assert_relative_eq!(imag, -8.98651e-1);
@@ -1213,7 +2154,7 @@ mod test_keywords {
 
         #[test]
         fn data_pair_spaced() {
-            match Keyword::try_from("8.6303E-2, -8.98651E-1") {
+            match Keyword::from_str("8.6303E-2, -8.98651E-1") {
                 Ok(Keyword::DataPair { real, imag }) => {
                     assert_relative_eq!(real, 0.86303e-1);
                     assert_relative_eq!(imag, -8.98651e-1);
@@ -1224,7 +2165,7 @@ mod test_keywords {
 
         #[test]
         fn begin() {
-            match Keyword::try_from("BEGIN") {
+            match Keyword::from_str("BEGIN") {
                 Ok(Keyword::Begin) => (),
                 e => panic!("{:?}", e),
             }
@@ -1232,7 +2173,7 @@ mod test_keywords {
 
         #[test]
         fn end() {
-            match Keyword::try_from("END") {
+            match Keyword::from_str("END") {
                 Ok(Keyword::End) => (),
                 e => panic!("{:?}", e),
             }
@@ -1240,1725 +2181,9718 @@ mod test_keywords {
 
         #[test]
         fn comment() {
-            match Keyword::try_from("!DATE: 2019.11.01") {
+            match Keyword::from_str("!DATE: 2019.11.01") {
                 Ok(Keyword::Comment(s)) => assert_eq!(s, "DATE: 2019.11.01"),
                 e => panic!("{:?}", e),
             }
         }
-    }
-}
-
-/// Device-specific value.
-///
-/// This should be used over constants to conform to the standard.
-/// ```no_test
-/// #NA VERSION HP8510B.05.00
-/// ```
-#[derive(Debug, PartialEq, Clone)]
-pub struct Device {
-    pub name: String,
-    pub entries: Vec<String>,
-}
 
-impl Device {
-    pub fn new(name: &str) -> Device {
-        Device {
-            name: String::from(name),
-            entries: vec![],
+        #[test]
+        fn comment_keyword() {
+            match Keyword::from_str("COMMENT hello") {
+                Ok(keyword) => assert_eq!(keyword, Keyword::from_str("!hello").unwrap()),
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
-
-#[cfg(test)]
-mod test_device {
-    use super::*;
-
-    #[test]
-    fn test_new() {
-        let result = Device::new("A Name");
-        let expected = Device {
-            name: String::from("A Name"),
-            entries: vec![],
-        };
-        assert_eq!(result, expected);
-    }
-}
-
-/// The independent variable
-#[derive(Debug, PartialEq, Clone)]
-pub struct Var {
-    pub name: String,
-    pub format: String,
-    pub data: Vec<f64>,
-}
 
-impl Var {
-    fn blank() -> Var {
-        Var {
-            name: String::new(),
-            format: String::new(),
-            data: vec![],
+        #[test]
+        fn comment_keyword_empty() {
+            match Keyword::from_str("COMMENT ") {
+                Ok(Keyword::Comment(s)) => assert_eq!(s, ""),
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    pub fn new(name: &str, format: &str) -> Var {
-        Var {
-            name: String::from(name),
-            format: String::from(format),
-            data: vec![],
+        #[test]
+        fn comment_keyword_without_space() {
+            match Keyword::from_str("COMMENTS hello") {
+                Err(ParseError::BadKeyword(_)) => (),
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    pub fn push(&mut self, value: f64) {
-        self.data.push(value);
-    }
-
-    pub fn seq(&mut self, first: f64, last: f64, number: usize) {
-        match number {
-            0 => (),
-            1 => self.push(first),
-            _ => {
-                let delta = (last - first) / ((number - 1) as f64);
-                for i in 0..number {
-                    self.push(first + (i as f64) * delta);
+        #[test]
+        fn data_pair_infinite() {
+            match Keyword::from_str("INF,0") {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    assert_eq!(real, f64::INFINITY);
+                    assert_relative_eq!(imag, 0.);
                 }
+                e => panic!("{:?}", e),
             }
         }
-    }
-}
-
-#[cfg(test)]
-mod test_var {
-    use super::*;
-
-    #[test]
-    fn test_blank() {
-        let result = Var::blank();
-        let expected = Var {
-            name: String::new(),
-            format: String::new(),
-            data: vec![],
-        };
-        assert_eq!(result, expected);
-    }
-
-    #[test]
-    fn test_new() {
-        let result = Var::new("Name", "Format");
-        let expected = Var {
-            name: String::from("Name"),
-            format: String::from("Format"),
-            data: vec![],
-        };
-        assert_eq!(result, expected);
-    }
-
-    mod test_push {
-        use super::*;
 
         #[test]
-        fn empty() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.push(1.);
-            assert_eq!(vec![1.], var.data);
+        fn data_pair_nan() {
+            match Keyword::from_str("0,NAN") {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    assert_relative_eq!(real, 0.);
+                    assert!(imag.is_nan());
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn double() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.push(1.);
-            var.push(2.);
-            assert_eq!(vec![1., 2.], var.data);
+        fn data_pair_negative_zero() {
+            match Keyword::from_str("-0E0,0") {
+                Ok(Keyword::DataPair { real, .. }) => {
+                    assert_eq!(real, 0.);
+                    assert!(real.is_sign_negative());
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn existing() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![1.],
-            };
-            var.push(2.);
-            assert_eq!(vec![1., 2.], var.data);
+        fn var_item_infinite() {
+            match Keyword::from_str("-INF") {
+                Ok(Keyword::VarListItem(value)) => assert_eq!(value, f64::NEG_INFINITY),
+                e => panic!("{:?}", e),
+            }
         }
-    }
-
-    mod test_seq {
-        use super::*;
 
         #[test]
-        fn number_zero() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.seq(1., 2., 0);
-            assert_eq!(Vec::<f64>::new(), var.data);
+        fn var_item_nan() {
+            match Keyword::from_str("nan") {
+                Ok(Keyword::VarListItem(value)) => assert!(value.is_nan()),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn number_one() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.seq(10., 20., 1);
-            assert_eq!(vec![10.], var.data);
+        fn seg_item_infinite() {
+            match Keyword::from_str("SEG 10 inf 2") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: None,
+                }) => {
+                    assert_relative_eq!(first, 10.);
+                    assert_eq!(last, f64::INFINITY);
+                    assert_eq!(number, 2);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn simple() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.seq(1., 2., 2);
-            assert_eq!(vec![1., 2.], var.data);
+        fn non_finite_display_round_trip() {
+            let keywords = vec![
+                Keyword::DataPair {
+                    real: f64::NEG_INFINITY,
+                    imag: -0.,
+                },
+                Keyword::VarListItem(f64::INFINITY),
+            ];
+            for keyword in keywords {
+                assert_eq!(Keyword::from_str(&format!("{}", keyword)).unwrap(), keyword);
+            }
+            match Keyword::from_str(&format!("{}", Keyword::VarListItem(f64::NAN))) {
+                Ok(Keyword::VarListItem(value)) => assert!(value.is_nan()),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn triple() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.seq(2000000000., 3000000000., 3);
-            assert_eq!(vec![2000000000., 2500000000., 3000000000.], var.data);
+        fn var_tab_delimited() {
+            match Keyword::from_str("VAR\tFREQ\tMAG\t201") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "FREQ");
+                    assert_eq!(format, "MAG");
+                    assert_eq!(length, 201);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn reversed() {
-            let mut var = Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            };
-            var.seq(3000000000., 2000000000., 3);
-            assert_eq!(vec![3000000000., 2500000000., 2000000000.], var.data);
+        fn var_multi_word_format() {
+            match Keyword::from_str("VAR TIME SEC LIN 100") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "TIME");
+                    assert_eq!(format, "SEC LIN");
+                    assert_eq!(length, 100);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
 
-/// Define a constant in the file
-#[derive(Debug, PartialEq, Clone)]
-pub struct Constant {
-    pub name: String,
-    pub value: String,
-}
-
-impl Constant {
-    pub fn new(name: &str, value: &str) -> Constant {
-        Constant {
-            name: String::from(name),
-            value: String::from(value),
+        #[test]
+        fn var_multi_word_format_round_trip() {
+            let line = "VAR TIME SEC LIN 100";
+            assert_eq!(format!("{}", Keyword::from_str(line).unwrap()), line);
         }
-    }
-}
 
-#[cfg(test)]
-mod test_constant {
-    use super::*;
-
-    #[test]
-    fn test_new() {
-        let expected = Constant {
-            name: String::from("A_NAME"),
-            value: String::from("A_VALUE"),
-        };
-        let result = Constant::new("A_NAME", "A_VALUE");
-        assert_eq!(result, expected);
-    }
-}
-
-/// The file header
-///
-/// Note that the `DATA` keywords are not defined here.
-#[derive(Debug, PartialEq, Clone)]
-pub struct Header {
-    pub version: String,
-    pub name: String,
-    pub comments: Vec<String>,
-    pub devices: Vec<Device>,
-    pub independent_variable: Var,
-    pub constants: Vec<Constant>,
-}
-
-impl Default for Header {
-    fn default() -> Self {
-        Header {
-            version: String::from("A.01.00"),
-            name: String::new(),
-            comments: vec![],
-            devices: vec![],
-            independent_variable: Var::blank(),
-            constants: vec![],
+        #[test]
+        fn var_no_format() {
+            match Keyword::from_str("VAR FREQ 201") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "FREQ");
+                    assert_eq!(format, "");
+                    assert_eq!(length, 201);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
 
-impl Header {
-    pub fn new(version: &str, name: &str) -> Header {
-        Header {
-            version: String::from(version),
-            name: String::from(name),
-            comments: vec![],
-            devices: vec![],
-            independent_variable: Var::blank(),
-            constants: vec![],
+        #[test]
+        fn var_bracketed_name() {
+            match Keyword::from_str("VAR TIME[0] MAG 100") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "TIME[0]");
+                    assert_eq!(format, "MAG");
+                    assert_eq!(length, 100);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    fn blank() -> Header {
-        Header {
-            version: String::new(),
-            name: String::new(),
-            comments: vec![],
-            devices: vec![],
-            independent_variable: Var::blank(),
-            constants: vec![],
+        #[test]
+        fn var_bracketed_name_no_format() {
+            match Keyword::from_str("VAR TIME[0] 100") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "TIME[0]");
+                    assert_eq!(format, "");
+                    assert_eq!(length, 100);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    pub fn add_device(&mut self, device_name: &str, value: &str) {
-        self.create_device(device_name);
-        if let Some(i) = self.index_device(device_name) {
-            self.devices[i].entries.push(String::from(value));
+        #[test]
+        fn var_comma_name_no_format() {
+            match Keyword::from_str("VAR PORT[1,2] 3") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "PORT[1,2]");
+                    assert_eq!(format, "");
+                    assert_eq!(length, 3);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    /// If the device already exists, nothing happens
-    pub fn create_device(&mut self, device_name: &str) {
-        if self.get_device_by_name(device_name) == None {
-            self.devices.push(Device::new(device_name));
+        #[test]
+        fn var_bracketed_name_round_trip() {
+            for line in ["VAR TIME[0] 100", "VAR TIME[0] MAG 100"].iter() {
+                let keyword = Keyword::from_str(line).unwrap();
+                assert_eq!(&format!("{}", keyword), line);
+            }
         }
-    }
 
-    pub fn get_device_by_name(&self, device_name: &str) -> Option<&Device> {
-        self.devices.iter().find(|&x| x.name == device_name)
-    }
+        #[test]
+        fn data_tab_delimited() {
+            match Keyword::from_str("DATA\tS[1,1]\t\tRI") {
+                Ok(Keyword::Data { name, format }) => {
+                    assert_eq!(name, "S[1,1]");
+                    assert_eq!(format, "RI");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
 
-    pub fn index_device(&self, device_name: &str) -> Option<usize> {
-        self.devices.iter().position(|x| x.name == device_name)
-    }
-}
+        #[test]
+        fn constant_extra_whitespace() {
+            match Keyword::from_str("CONSTANT  A_CONSTANT \t1.2345 ") {
+                Ok(Keyword::Constant { name, value }) => {
+                    assert_eq!(name, "A_CONSTANT");
+                    assert_eq!(value, "1.2345");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
 
-#[cfg(test)]
-mod test_header {
-    use super::*;
+        #[test]
+        fn citifile_tab_delimited() {
+            match Keyword::from_str("CITIFILE\tA.01.00") {
+                Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.00"),
+                e => panic!("{:?}", e),
+            }
+        }
 
-    #[test]
-    fn test_default() {
-        let expected = Header {
-            version: String::from("A.01.00"),
-            name: String::new(),
-            comments: vec![],
-            devices: vec![],
-            independent_variable: Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            },
-            constants: vec![],
-        };
-        let result = Header::default();
-        assert_eq!(result, expected);
-    }
+        #[test]
+        fn name_tab_delimited() {
+            match Keyword::from_str("NAME\tCAL_SET") {
+                Ok(Keyword::Name(name)) => assert_eq!(name, "CAL_SET"),
+                e => panic!("{:?}", e),
+            }
+        }
 
-    #[test]
-    fn test_new() {
-        let expected = Header {
-            version: String::from("A.01.01"),
-            name: String::from("A_NAME"),
-            comments: vec![],
-            devices: vec![],
-            independent_variable: Var {
-                name: String::new(),
-                format: String::new(),
-                data: vec![],
-            },
-            constants: vec![],
-        };
-        let result = Header::new("A.01.01", "A_NAME");
-        assert_eq!(result, expected);
+        #[test]
+        fn seg_item_tab_delimited() {
+            match Keyword::from_str("SEG\t1e9\t1E4\t100") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: None,
+                }) => {
+                    assert_relative_eq!(first, 1e9);
+                    assert_relative_eq!(last, 1e4);
+                    assert_eq!(number, 100);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
     }
 
     #[cfg(test)]
-    mod test_devices {
+    mod test_try_from_string_slice {
         use super::*;
+        use approx::*;
 
-        #[cfg(test)]
-        mod test_add_device {
-            use super::*;
-
-            #[test]
-            fn empty() {
-                let expected = vec![Device {
-                    name: String::from("NA"),
-                    entries: vec![String::from("VERSION HP8510B.05.00")],
-                }];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.add_device("NA", "VERSION HP8510B.05.00");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn fails_on_bad_string() {
+            match Keyword::try_from("this is a bad string") {
+                Err(ParseError::BadKeyword(bad_string)) => {
+                    assert_eq!(bad_string, "this is a bad string")
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn double_add() {
-                let expected = vec![Device {
-                    name: String::from("NA"),
-                    entries: vec![
-                        String::from("VERSION HP8510B.05.00"),
-                        String::from("REGISTER 1"),
-                    ],
-                }];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.add_device("NA", "VERSION HP8510B.05.00");
-                header.add_device("NA", "REGISTER 1");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn citirecord_a_01_00() {
+            match Keyword::try_from("CITIFILE A.01.00") {
+                Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.00"),
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn add_two_devices() {
-                let expected = vec![
-                    Device {
-                        name: String::from("NA"),
-                        entries: vec![String::from("VERSION HP8510B.05.00")],
-                    },
-                    Device {
-                        name: String::from("WVI"),
-                        entries: vec![String::from("REGISTER 1")],
-                    },
-                ];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.add_device("NA", "VERSION HP8510B.05.00");
-                header.add_device("WVI", "REGISTER 1");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn citirecord_a_01_01() {
+            match Keyword::try_from("CITIFILE A.01.01") {
+                Ok(Keyword::CitiFile { version }) => assert_eq!(version, "A.01.01"),
+                e => panic!("{:?}", e),
             }
         }
 
-        #[cfg(test)]
-        mod test_create_device {
-            use super::*;
+        #[test]
+        fn name_cal_set() {
+            match Keyword::try_from("NAME CAL_SET") {
+                Ok(Keyword::Name(name)) => assert_eq!(name, "CAL_SET"),
+                e => panic!("{:?}", e),
+            }
+        }
 
-            #[test]
-            fn empty() {
-                let expected = vec![Device {
-                    name: String::from("A Name"),
-                    entries: vec![],
-                }];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn name_raw_data() {
+            match Keyword::try_from("NAME RAW_DATA") {
+                Ok(Keyword::Name(name)) => assert_eq!(name, "RAW_DATA"),
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn appends_device() {
-                let expected = vec![
-                    Device {
-                        name: String::from("Different Name"),
-                        entries: vec![],
-                    },
-                    Device {
-                        name: String::from("A Name"),
-                        entries: vec![],
-                    },
-                ];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("Different Name");
-                header.create_device("A Name");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn constant() {
+            match Keyword::try_from("CONSTANT A_CONSTANT 1.2345") {
+                Ok(Keyword::Constant { name, value }) => {
+                    assert_eq!(name, "A_CONSTANT");
+                    assert_eq!(value, "1.2345");
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn existing_device() {
-                let expected = vec![Device {
-                    name: String::from("A Name"),
-                    entries: vec![],
-                }];
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                header.create_device("A Name");
-                assert_eq!(header.devices, expected);
+        #[test]
+        fn device() {
+            match Keyword::try_from("#NA REGISTER 1") {
+                Ok(Keyword::Device { name, value }) => {
+                    assert_eq!(name, "NA");
+                    assert_eq!(value, "REGISTER 1");
+                }
+                e => panic!("{:?}", e),
             }
         }
 
-        #[cfg(test)]
-        mod test_index_device {
-            use super::*;
+        #[test]
+        fn device_number() {
+            match Keyword::try_from("#NA POWER2 1.0E1") {
+                Ok(Keyword::Device { name, value }) => {
+                    assert_eq!(name, "NA");
+                    assert_eq!(value, "POWER2 1.0E1");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
 
-            #[test]
-            fn empty() {
-                let header = Header::new("A.01.01", "A_NAME");
-                assert_eq!(header.index_device(""), None);
+        #[test]
+        fn device_name() {
+            match Keyword::try_from("#WVI A B") {
+                Ok(Keyword::Device { name, value }) => {
+                    assert_eq!(name, "WVI");
+                    assert_eq!(value, "A B");
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn no_device_found() {
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                assert_eq!(header.index_device(""), None);
+        #[test]
+        fn var() {
+            match Keyword::try_from("VAR FREQ MAG 201") {
+                Ok(Keyword::Var {
+                    name,
+                    format,
+                    length,
+                }) => {
+                    assert_eq!(name, "FREQ");
+                    assert_eq!(format, "MAG");
+                    assert_eq!(length, 201);
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn device_found() {
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                assert_eq!(header.index_device("A Name"), Some(0));
+        #[test]
+        fn seg_list_begin() {
+            match Keyword::try_from("SEG_LIST_BEGIN") {
+                Ok(Keyword::SegListBegin) => (),
+                e => panic!("{:?}", e),
             }
         }
 
-        #[cfg(test)]
-        mod test_get_device_by_name {
-            use super::*;
+        #[test]
+        fn seg_item() {
+            match Keyword::try_from("SEG 1000000000 4000000000 10") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: None,
+                }) => {
+                    assert_relative_eq!(first, 1000000000.);
+                    assert_relative_eq!(last, 4000000000.);
+                    assert_eq!(number, 10);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
 
-            #[test]
-            fn empty() {
-                let header = Header::new("A.01.01", "A_NAME");
-                assert_eq!(header.get_device_by_name(""), None);
+        #[test]
+        fn seg_item_exponential() {
+            match Keyword::try_from("SEG 1e9 1E4 100") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: None,
+                }) => {
+                    assert_relative_eq!(first, 1e9);
+                    assert_relative_eq!(last, 1e4);
+                    assert_eq!(number, 100);
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn no_device_found() {
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                assert_eq!(header.get_device_by_name(""), None);
+        #[test]
+        fn seg_item_negative() {
+            match Keyword::try_from("SEG -1e9 1E-4 1") {
+                Ok(Keyword::SegItem {
+                    first,
+                    last,
+                    number,
+                    spacing: None,
+                }) => {
+                    assert_relative_eq!(first, -1e9);
+                    assert_relative_eq!(last, 1e-4);
+                    assert_eq!(number, 1);
+                }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn device_found() {
-                let mut header = Header::new("A.01.01", "A_NAME");
-                header.create_device("A Name");
-                assert_eq!(
-                    header.get_device_by_name("A Name"),
-                    Some(&Device {
-                        name: String::from("A Name"),
-                        entries: vec![]
-                    })
-                );
+        #[test]
+        fn seg_list_end() {
+            match Keyword::try_from("SEG_LIST_END") {
+                Ok(Keyword::SegListEnd) => (),
+                e => panic!("{:?}", e),
             }
         }
-    }
-}
 
-/// A named, formatted, data array
-///
-/// Consistency of the format with the variable `samples` is not
-/// guaranteed and should be enforced by users of this code.
-#[derive(Debug, PartialEq, Clone)]
-pub struct DataArray {
-    pub name: String,
-    pub format: String,
-    pub samples: Vec<Complex<f64>>,
-}
+        #[test]
+        fn var_list_begin() {
+            match Keyword::try_from("VAR_LIST_BEGIN") {
+                Ok(Keyword::VarListBegin) => (),
+                e => panic!("{:?}", e),
+            }
+        }
 
-impl DataArray {
-    #[cfg(test)]
-    fn blank() -> DataArray {
-        DataArray {
-            name: String::new(),
-            format: String::new(),
-            samples: vec![],
+        #[test]
+        fn var_item() {
+            match Keyword::try_from("100000") {
+                Ok(Keyword::VarListItem(value)) => {
+                    assert_relative_eq!(value, 100000.);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    pub fn new(name: &str, format: &str) -> DataArray {
-        DataArray {
-            name: String::from(name),
-            format: String::from(format),
-            samples: vec![],
+        #[test]
+        fn var_item_exponential() {
+            match Keyword::try_from("100E+6") {
+                Ok(Keyword::VarListItem(value)) => {
+                    assert_relative_eq!(value, 100E+6);
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
 
-    pub fn add_sample(&mut self, real: f64, imag: f64) {
-        self.samples.push(Complex::<f64>::new(real, imag));
-    }
-}
-
-#[cfg(test)]
-mod test_data_array {
-    use super::*;
-
-    #[test]
-    fn test_blank() {
-        let expected = DataArray {
-            name: String::new(),
-            format: String::new(),
-            samples: vec![],
-        };
-        let result = DataArray::blank();
-        assert_eq!(result, expected);
-    }
-
-    #[test]
-    fn test_new() {
-        let expected = DataArray {
-            name: String::from("S"),
-            format: String::from("RI"),
-            samples: vec![],
-        };
-        let result = DataArray::new("S", "RI");
-        assert_eq!(result, expected);
-    }
-
-    #[cfg(test)]
-    mod test_add_sample {
-        use super::*;
+        #[test]
+        fn var_item_negative_exponential() {
+            match Keyword::try_from("-1e-2") {
+                Ok(Keyword::VarListItem(value)) => {
+                    assert_relative_eq!(value, -1e-2);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
 
         #[test]
-        fn empty() {
-            let mut result = DataArray {
-                name: String::new(),
-                format: String::new(),
-                samples: vec![],
-            };
-            result.add_sample(1., 2.);
-            assert_complex_array_relative_eq!(result.samples, vec![Complex { re: 1., im: 2. }]);
+        fn var_item_negative() {
+            match Keyword::try_from("-100000") {
+                Ok(Keyword::VarListItem(value)) => {
+                    assert_relative_eq!(value, -100000.);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn double() {
-            let mut result = DataArray {
-                name: String::new(),
-                format: String::new(),
-                samples: vec![],
-            };
-            result.add_sample(1., 2.);
-            result.add_sample(-1., -2.);
-            assert_complex_array_relative_eq!(
-                result.samples,
-                vec![Complex { re: 1., im: 2. }, Complex { re: -1., im: -2. }]
-            );
+        fn var_list_end() {
+            match Keyword::try_from("VAR_LIST_END") {
+                Ok(Keyword::VarListEnd) => (),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn existing() {
-            let mut result = DataArray {
-                name: String::new(),
-                format: String::new(),
-                samples: vec![Complex { re: 1., im: 2. }],
-            };
-            result.add_sample(3., 4.);
-            assert_complex_array_relative_eq!(
-                result.samples,
-                vec![Complex { re: 1., im: 2. }, Complex { re: 3., im: 4. }]
-            );
+        fn data_s11() {
+            match Keyword::try_from("DATA S[1,1] RI") {
+                Ok(Keyword::Data { name, format }) => {
+                    assert_eq!(name, "S[1,1]");
+                    assert_eq!(format, "RI");
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
-
-/// Representation of a file
-#[derive(Debug, PartialEq, Clone)]
-pub struct Record {
-    pub header: Header,
-    pub data: Vec<DataArray>,
-}
 
-impl Default for Record {
-    fn default() -> Self {
-        Record {
-            header: Header::default(),
-            data: vec![],
+        #[test]
+        fn data_e() {
+            match Keyword::try_from("DATA E RI") {
+                Ok(Keyword::Data { name, format }) => {
+                    assert_eq!(name, "E");
+                    assert_eq!(format, "RI");
+                }
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
-
-/// Error during writing
-#[derive(Error, Debug)]
-pub enum WriteError {
-    #[error("Version is not defined")]
-    NoVersion,
-    #[error("Name is not defined")]
-    NoName,
-    #[error("Data array {0} has no name")]
-    NoDataName(usize),
-    #[error("Data array {0} has no format")]
-    NoDataFormat(usize),
-    #[error("Writing error occured: {0}")]
-    WrittingError(std::io::Error),
-}
-type WriteResult<T> = std::result::Result<T, WriteError>;
-
-#[cfg(test)]
-mod test_write_result {
-    use super::*;
-
-    mod test_display {
-        use super::*;
 
         #[test]
-        fn no_version() {
-            let error = WriteError::NoVersion;
-            assert_eq!(format!("{}", error), "Version is not defined");
+        fn data_pair_simple() {
+            match Keyword::try_from("1E9,-1E9") {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    assert_relative_eq!(real, 1e9);
+                    assert_relative_eq!(imag, -1e9);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn no_name() {
-            let error = WriteError::NoName;
-            assert_eq!(format!("{}", error), "Name is not defined");
+        fn data_pair() {
+            match Keyword::try_from("8.6303E-2,-8.98651E-1") {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    assert_relative_eq!(real, 0.86303e-1);
+                    assert_relative_eq!(imag, -8.98651e-1);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn no_data_name() {
-            let error = WriteError::NoDataName(1);
-            assert_eq!(format!("{}", error), "Data array 1 has no name");
+        fn data_pair_spaced() {
+            match Keyword::try_from("8.6303E-2, -8.98651E-1") {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    assert_relative_eq!(real, 0.86303e-1);
+                    assert_relative_eq!(imag, -8.98651e-1);
+                }
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn no_data_name_second() {
-            let error = WriteError::NoDataName(2);
-            assert_eq!(format!("{}", error), "Data array 2 has no name");
+        fn data_pair_three_values() {
+            match Keyword::try_from("1E9,2E9,3E9") {
+                Err(ParseError::MalformedDataPair(s)) => assert_eq!(s, "1E9,2E9,3E9"),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn no_data_format() {
-            let error = WriteError::NoDataFormat(1);
-            assert_eq!(format!("{}", error), "Data array 1 has no format");
+        fn data_pair_empty_value() {
+            match Keyword::try_from("1E9,,3E9") {
+                Err(ParseError::MalformedDataPair(s)) => assert_eq!(s, "1E9,,3E9"),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn no_data_format_second() {
-            let error = WriteError::NoDataFormat(2);
-            assert_eq!(format!("{}", error), "Data array 2 has no format");
+        fn data_pair_unparseable() {
+            match Keyword::try_from("1E9,abc") {
+                Err(ParseError::NumberParseError(s)) => assert_eq!(s, "1E9,abc"),
+                e => panic!("{:?}", e),
+            }
         }
 
         #[test]
-        fn writting_error() {
-            let error = WriteError::WrittingError(std::io::ErrorKind::NotFound.into());
-            assert_eq!(
-                format!("{}", error),
-                "Writing error occured: entity not found"
-            );
+        fn begin() {
+            match Keyword::try_from("BEGIN") {
+                Ok(Keyword::Begin) => (),
+                e => panic!("{:?}", e),
+            }
         }
-    }
-}
 
-impl Record {
-    pub fn new(version: &str, name: &str) -> Record {
-        Record {
-            header: Header::new(version, name),
-            data: vec![],
+        #[test]
+        fn end() {
+            match Keyword::try_from("END") {
+                Ok(Keyword::End) => (),
+                e => panic!("{:?}", e),
+            }
         }
-    }
-
-    /// Read record
-    ///
-    /// Example usage:
-    /// ```no_run
-    /// use citi::Record;
-    /// use std::fs::File;
-    ///
-    /// let mut file = File::open("file.cti").unwrap();
-    /// let record = Record::from_reader(&mut file);
-    /// ```
-    pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Record> {
-        let mut state = RecordReaderState::new();
 
-        let buf_reader = std::io::BufReader::new(reader);
-        for (i, line) in buf_reader.lines().enumerate() {
-            let this_line = line.map_err(ReadError::ReadingError)?;
-            // Filter out new lines
-            if !this_line.trim().is_empty() {
-                let keyword =
-                    Keyword::from_str(&this_line).map_err(|e| ReadError::LineError(i, e))?;
-                state = state.process_keyword(keyword)?;
+        #[test]
+        fn comment() {
+            match Keyword::try_from("!DATE: 2019.11.01") {
+                Ok(Keyword::Comment(s)) => assert_eq!(s, "DATE: 2019.11.01"),
+                e => panic!("{:?}", e),
             }
         }
-
-        Ok(state.validate_record()?.record)
     }
+}
 
-    /// Write record
-    ///
-    /// Example usage:
-    /// ```no_run
-    /// use citi::Record;
-    /// use std::fs::File;
-    ///
-    /// let record = Record::default();
-    /// let mut file = File::create("file.cti").unwrap();
-    /// record.to_writer(&mut file);
-    /// ```
-    pub fn to_writer<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
-        let keywords = self.get_keywords()?;
+/// Device-specific value.
+///
+/// This should be used over constants to conform to the standard.
+/// ```no_test
+/// #NA VERSION HP8510B.05.00
+/// ```
+///
+/// Devices are ordered by name, then by entries.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Device {
+    pub name: String,
+    pub entries: Vec<String>,
+}
 
-        for keyword in keywords.iter() {
-            writeln!(writer, "{}", keyword).map_err(WriteError::WrittingError)?;
+impl Device {
+    pub fn new(name: &str) -> Device {
+        Device {
+            name: String::from(name),
+            entries: vec![],
         }
-
-        Ok(())
     }
+}
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_data_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        let mut keywords: Vec<Keyword> = vec![];
-
-        // Add each array
-        for array in self.data.iter() {
-            keywords.push(Keyword::Begin);
+#[cfg(test)]
+mod test_device {
+    use super::*;
 
-            for Complex { re: real, im: imag } in array.samples.iter() {
-                keywords.push(Keyword::DataPair {
-                    real: *real,
-                    imag: *imag,
-                });
-            }
-            keywords.push(Keyword::End);
-        }
+    #[test]
+    fn test_new() {
+        let result = Device::new("A Name");
+        let expected = Device {
+            name: String::from("A Name"),
+            entries: vec![],
+        };
+        assert_eq!(result, expected);
+    }
 
-        Ok(keywords)
+    #[test]
+    fn sort() {
+        let device = |name: &str, entries: &[&str]| Device {
+            name: String::from(name),
+            entries: entries.iter().map(|e| String::from(*e)).collect(),
+        };
+        let mut devices = vec![
+            device("NA", &["VERSION B"]),
+            device("CAL", &["TYPE 2"]),
+            device("NA", &["VERSION A", "REGISTER 1"]),
+            device("NA", &[]),
+        ];
+        devices.sort();
+        assert_eq!(
+            devices,
+            vec![
+                device("CAL", &["TYPE 2"]),
+                device("NA", &[]),
+                device("NA", &["VERSION A", "REGISTER 1"]),
+                device("NA", &["VERSION B"]),
+            ]
+        );
     }
+}
 
-    fn get_data_defines_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        let mut keywords: Vec<Keyword> = vec![];
+/// The independent variable
+#[derive(Debug, PartialEq, Clone)]
+pub struct Var {
+    pub name: String,
+    pub format: String,
+    pub data: Vec<f64>,
+}
 
-        for (i, array) in self.data.iter().enumerate() {
-            match (array.name.is_empty(), array.format.is_empty()) {
-                (true, _) => return Err(WriteError::NoDataName(i)),
-                (_, true) => return Err(WriteError::NoDataFormat(i)),
-                (_, _) => keywords.push(Keyword::Data {
-                    name: array.name.clone(),
-                    format: array.format.clone(),
-                }),
-            }
-        }
-        Ok(keywords)
+/// Point `i` of a `SEG` item of `number` points
+///
+/// The ends are kept exact.
+fn seg_point(first: f64, last: f64, number: usize, spacing: Option<SegSpacing>, i: usize) -> f64 {
+    if i == 0 {
+        return first;
     }
+    if i + 1 == number {
+        return last;
+    }
+    let t = (i as f64) / ((number - 1) as f64);
+    match spacing {
+        Some(SegSpacing::Logarithmic) => first * (last / first).powf(t),
+        _ => first * (1. - t) + last * t,
+    }
+}
 
-    fn get_version_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        match !self.header.version.is_empty() {
-            true => Ok(vec![Keyword::CitiFile {
-                version: self.header.version.clone(),
-            }]),
-            false => Err(WriteError::NoVersion),
+impl Var {
+    fn blank() -> Var {
+        Var {
+            name: String::new(),
+            format: String::new(),
+            data: vec![],
         }
     }
 
-    fn get_name_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        match !self.header.name.is_empty() {
-            true => Ok(vec![Keyword::Name(self.header.name.clone())]),
-            false => Err(WriteError::NoName),
+    pub fn new(name: &str, format: &str) -> Var {
+        Var {
+            name: String::from(name),
+            format: String::from(format),
+            data: vec![],
         }
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_comments_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        Ok(self
-            .header
-            .comments
-            .iter()
-            .map(|s| Keyword::Comment(s.clone()))
-            .collect())
+    pub fn push(&mut self, value: f64) {
+        self.data.push(value);
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_devices_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        let mut keywords: Vec<Keyword> = vec![];
-
-        for device in self.header.devices.iter() {
-            for entry in device.entries.iter() {
-                keywords.push(Keyword::Device {
-                    name: device.name.clone(),
-                    value: entry.clone(),
-                });
-            }
+    /// Append `number` evenly spaced points from `first` to `last`
+    ///
+    /// Each point is interpolated between the ends rather than accumulated
+    /// from a step, so `first` and `last` are exact.
+    pub fn seq(&mut self, first: f64, last: f64, number: usize) {
+        for i in 0..number {
+            self.push(seg_point(first, last, number, None, i));
         }
-
-        Ok(keywords)
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_independent_variable_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        Ok(vec![Keyword::Var {
-            name: self.header.independent_variable.name.clone(),
-            format: self.header.independent_variable.format.clone(),
-            length: self.header.independent_variable.data.len(),
-        }])
+    /// Append `number` points from `first` to `last`, evenly spaced on a
+    /// logarithmic scale
+    ///
+    /// The ends are kept exact. They must be non-zero with the same sign,
+    /// otherwise the points between are not finite; the reader rejects such
+    /// a `SEG LOG` item with [`ReadError::InvalidLogSegment`].
+    pub fn log_seq(&mut self, first: f64, last: f64, number: usize) {
+        let spacing = Some(SegSpacing::Logarithmic);
+        for i in 0..number {
+            self.push(seg_point(first, last, number, spacing, i));
+        }
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_var_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        let mut keywords: Vec<Keyword> = vec![];
-
-        // Do not set if length == 0
-        if !self.header.independent_variable.data.is_empty() {
-            keywords.push(Keyword::VarListBegin);
-            for &v in self.header.independent_variable.data.iter() {
-                keywords.push(Keyword::VarListItem(v));
-            }
-            keywords.push(Keyword::VarListEnd);
+    /// Append the points of a `SEG` item
+    fn push_seg(&mut self, first: f64, last: f64, number: usize, spacing: Option<SegSpacing>) {
+        for i in 0..number {
+            self.push(seg_point(first, last, number, spacing, i));
         }
-
-        Ok(keywords)
     }
 
-    #[allow(clippy::unnecessary_wraps)]
-    fn get_constants_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        Ok(self
-            .header
-            .constants
-            .iter()
-            .map(|c| Keyword::Constant {
-                name: c.name.clone(),
-                value: c.value.clone(),
-            })
-            .collect())
+    /// Points of the variable
+    pub fn values(&self) -> &[f64] {
+        &self.data
     }
 
-    fn get_keywords(&self) -> WriteResult<Vec<Keyword>> {
-        let mut keywords: Vec<Keyword> = vec![];
+    /// Mutable access to the points of the variable
+    pub fn values_mut(&mut self) -> &mut Vec<f64> {
+        &mut self.data
+    }
 
-        keywords.append(&mut self.get_version_keywords()?);
-        keywords.append(&mut self.get_name_keywords()?);
-        keywords.append(&mut self.get_independent_variable_keywords()?);
-        keywords.append(&mut self.get_var_keywords()?);
-        keywords.append(&mut self.get_constants_keywords()?);
-        keywords.append(&mut self.get_comments_keywords()?);
-        keywords.append(&mut self.get_devices_keywords()?);
-        keywords.append(&mut self.get_data_defines_keywords()?);
-        keywords.append(&mut self.get_data_keywords()?);
+    /// Multiply every point by `factor` e.g. `1e9` to convert GHz to Hz
+    pub fn scale(&mut self, factor: f64) {
+        for v in self.data.iter_mut() {
+            *v *= factor;
+        }
+    }
 
-        Ok(keywords)
+    /// Copy with every point multiplied by `factor`
+    pub fn scaled(&self, factor: f64) -> Var {
+        let mut var = self.clone();
+        var.scale(factor);
+        var
     }
 
-    #[cfg(test)]
-    fn blank() -> Record {
-        Record {
-            header: Header::blank(),
-            data: vec![],
+    /// Round every point to `digits` significant digits
+    ///
+    /// Clears float noise such as `2500000000.0000005` left by `SEG`
+    /// expansion. Rounding is decimal, as the value would be printed.
+    /// `digits` is clamped to between 1 and 17, which already leaves every
+    /// point unchanged. Infinite and NaN points are kept.
+    pub fn round_significant(&mut self, digits: usize) {
+        let precision = digits.clamp(1, MAX_SIGNIFICANT_DIGITS) - 1;
+        for v in self.data.iter_mut() {
+            if v.is_finite() {
+                *v = format!("{:.*e}", precision, v).parse().unwrap_or(*v);
+            }
         }
     }
 }
 
 #[cfg(test)]
-mod test_record {
+mod test_var {
     use super::*;
-    use tempfile::tempdir;
 
     #[test]
-    fn write_gives_error_on_bad_file() {
-        let record = Record::default();
-        let mut file = std::fs::File::create(tempdir().unwrap().path().join("temp.cti")).unwrap();
-        match record.to_writer(&mut file) {
-            Err(Error::WriteError(WriteError::NoName)) => (),
-            e => panic!("{:?}", e),
-        }
+    fn values() {
+        let mut var = Var::new("FREQ", "MAG");
+        var.data = vec![1., 2.];
+        assert_eq!(var.values(), &[1., 2.]);
+        var.values_mut().push(3.);
+        assert_eq!(var.data, vec![1., 2., 3.]);
+        var.values_mut()[0] = 0.;
+        assert_eq!(var.values(), &[0., 2., 3.]);
     }
 
-    mod test_write {
+    #[test]
+    fn test_blank() {
+        let result = Var::blank();
+        let expected = Var {
+            name: String::new(),
+            format: String::new(),
+            data: vec![],
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_new() {
+        let result = Var::new("Name", "Format");
+        let expected = Var {
+            name: String::from("Name"),
+            format: String::from("Format"),
+            data: vec![],
+        };
+        assert_eq!(result, expected);
+    }
+
+    mod test_push {
         use super::*;
 
         #[test]
-        fn get_keywords() {
-            let mut record = Record::default();
-            record.header.constants.push(Constant {
-                name: String::from("Const Name"),
-                value: String::from("Value"),
-            });
-            record.header.independent_variable = Var {
-                name: String::from("Var Name"),
-                format: String::from("Format"),
-                data: vec![1.],
+        fn empty() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
             };
-            record.header.devices.push(Device {
-                name: String::from("Name A"),
-                entries: vec![String::from("entry 1"), String::from("entry 2")],
-            });
-            record.header.comments.push(String::from("A Comment"));
-            record.header.name = String::from("Name");
-            record.header.version = String::from("A.01.00");
-            record.data.push(DataArray {
-                name: String::from("Data Name A"),
-                format: String::from("Format A"),
-                samples: vec![Complex { re: 1., im: 2. }],
-            });
-            record.data.push(DataArray {
-                name: String::from("Data Name B"),
-                format: String::from("Format B"),
-                samples: vec![Complex { re: 3., im: 5. }, Complex { re: 4., im: 6. }],
-            });
-
-            match record.get_keywords() {
-                Ok(v) => assert_eq!(
-                    v,
-                    vec![
-                        Keyword::CitiFile {
-                            version: String::from("A.01.00")
-                        },
-                        Keyword::Name(String::from("Name")),
-                        Keyword::Var {
-                            name: String::from("Var Name"),
-                            format: String::from("Format"),
-                            length: 1
-                        },
-                        Keyword::VarListBegin,
-                        Keyword::VarListItem(1.),
-                        Keyword::VarListEnd,
-                        Keyword::Constant {
-                            name: String::from("Const Name"),
-                            value: String::from("Value")
-                        },
-                        Keyword::Comment(String::from("A Comment")),
-                        Keyword::Device {
-                            name: String::from("Name A"),
-                            value: String::from("entry 1")
-                        },
-                        Keyword::Device {
-                            name: String::from("Name A"),
-                            value: String::from("entry 2")
-                        },
-                        Keyword::Data {
-                            name: String::from("Data Name A"),
-                            format: String::from("Format A")
-                        },
-                        Keyword::Data {
-                            name: String::from("Data Name B"),
-                            format: String::from("Format B")
-                        },
-                        Keyword::Begin,
-                        Keyword::DataPair { real: 1., imag: 2. },
-                        Keyword::End,
-                        Keyword::Begin,
-                        Keyword::DataPair { real: 3., imag: 5. },
-                        Keyword::DataPair { real: 4., imag: 6. },
-                        Keyword::End,
-                    ]
-                ),
-                e => panic!("{:?}", e),
-            }
+            var.push(1.);
+            assert_eq!(vec![1.], var.data);
         }
 
-        mod test_get_var_keywords {
-            use super::*;
-
+        #[test]
+        fn double() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.push(1.);
+            var.push(2.);
+            assert_eq!(vec![1., 2.], var.data);
+        }
+
+        #[test]
+        fn existing() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![1.],
+            };
+            var.push(2.);
+            assert_eq!(vec![1., 2.], var.data);
+        }
+    }
+
+    mod test_scale {
+        use super::*;
+
+        #[test]
+        fn empty() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.scale(1e9);
+            assert_eq!(Vec::<f64>::new(), var.data);
+        }
+
+        #[test]
+        fn ghz_to_hz() {
+            let mut var = Var {
+                name: String::from("FREQ"),
+                format: String::from("MAG"),
+                data: vec![1., 1.5, 2.],
+            };
+            var.scale(1e9);
+            assert_array_relative_eq!(var.data, [1e9, 1.5e9, 2e9]);
+            assert_eq!(var.name, "FREQ");
+            assert_eq!(var.format, "MAG");
+        }
+
+        #[test]
+        fn scaled() {
+            let var = Var {
+                name: String::from("FREQ"),
+                format: String::from("MAG"),
+                data: vec![1., 1.5, 2.],
+            };
+            let result = var.scaled(1e9);
+            assert_array_relative_eq!(result.data, [1e9, 1.5e9, 2e9]);
+            assert_eq!(result.name, "FREQ");
+            assert_eq!(result.format, "MAG");
+            assert_array_relative_eq!(var.data, [1., 1.5, 2.]);
+        }
+    }
+
+    mod test_round_significant {
+        use super::*;
+
+        #[test]
+        fn noisy_axis() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![
+                1000000000.0000001,
+                2500000000.0000005,
+                3999999999.9999995,
+                -1.2345649e-3,
+                0.,
+            ];
+            var.round_significant(6);
+            assert_eq!(var.data, vec![1e9, 2.5e9, 4e9, -1.23456e-3, 0.]);
+        }
+
+        #[test]
+        fn rounds_like_display() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![1234567., 1.25e3];
+            var.round_significant(3);
+            assert_eq!(var.data, vec![1.23e6, 1.25e3]);
+            var.round_significant(2);
+            assert_eq!(var.data, vec![1.2e6, 1.2e3]);
+        }
+
+        #[test]
+        fn zero_digits() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![1.6e9];
+            var.round_significant(0);
+            assert_eq!(var.data, vec![2e9]);
+        }
+
+        #[test]
+        fn many_digits() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![0.1 + 0.2, 2500000000.0000005, f64::MIN_POSITIVE];
+            let data = var.data.clone();
+            var.round_significant(17);
+            assert_eq!(var.data, data);
+            var.round_significant(usize::MAX);
+            assert_eq!(var.data, data);
+        }
+
+        #[test]
+        fn non_finite() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![f64::INFINITY, f64::NEG_INFINITY];
+            var.round_significant(3);
+            assert_eq!(var.data, vec![f64::INFINITY, f64::NEG_INFINITY]);
+            var.data = vec![f64::NAN];
+            var.round_significant(3);
+            assert!(var.data[0].is_nan());
+        }
+    }
+
+    mod test_seq {
+        use super::*;
+        use approx::assert_relative_eq;
+
+        #[test]
+        fn number_zero() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.seq(1., 2., 0);
+            assert_eq!(Vec::<f64>::new(), var.data);
+        }
+
+        #[test]
+        fn number_one() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.seq(10., 20., 1);
+            assert_eq!(vec![10.], var.data);
+        }
+
+        #[test]
+        fn simple() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.seq(1., 2., 2);
+            assert_eq!(vec![1., 2.], var.data);
+        }
+
+        #[test]
+        fn triple() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.seq(2000000000., 3000000000., 3);
+            assert_eq!(vec![2000000000., 2500000000., 3000000000.], var.data);
+        }
+
+        #[test]
+        fn reversed() {
+            let mut var = Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            };
+            var.seq(3000000000., 2000000000., 3);
+            assert_eq!(vec![3000000000., 2500000000., 2000000000.], var.data);
+        }
+
+        #[test]
+        fn exact_ends() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.seq(1e9, 4e9, 201);
+            assert_eq!(var.data.len(), 201);
+            assert_eq!(var.data[0], 1e9);
+            assert_eq!(var.data[200], 4e9);
+            assert_eq!(var.data[100], 2.5e9);
+            for (i, v) in var.data.iter().enumerate() {
+                assert_relative_eq!(*v, 1e9 + (i as f64) * 1.5e7, max_relative = 1e-15);
+            }
+        }
+
+        #[test]
+        fn exact_ends_awkward_step() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.seq(0.1, 0.7, 7);
+            assert_eq!(var.data[0], 0.1);
+            assert_eq!(var.data[6], 0.7);
+            assert!(var.data.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    mod test_log_seq {
+        use super::*;
+        use approx::assert_relative_eq;
+
+        #[test]
+        fn decades() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.log_seq(1e9, 1e12, 4);
+            assert_eq!(var.data.len(), 4);
+            assert_eq!(var.data[0], 1e9);
+            assert_relative_eq!(var.data[1], 1e10, max_relative = 1e-12);
+            assert_relative_eq!(var.data[2], 1e11, max_relative = 1e-12);
+            assert_eq!(var.data[3], 1e12);
+        }
+
+        #[test]
+        fn number_zero_and_one() {
+            let mut var = Var::new("FREQ", "MAG");
+            var.log_seq(1e9, 1e12, 0);
+            assert!(var.data.is_empty());
+            var.log_seq(1e9, 1e12, 1);
+            assert_eq!(var.data, vec![1e9]);
+        }
+    }
+}
+
+/// Define a constant in the file
+///
+/// Constants are ordered by name, then by value.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Constant {
+    pub name: String,
+    pub value: String,
+}
+
+impl Constant {
+    pub fn new(name: &str, value: &str) -> Constant {
+        Constant {
+            name: String::from(name),
+            value: String::from(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_constant {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let expected = Constant {
+            name: String::from("A_NAME"),
+            value: String::from("A_VALUE"),
+        };
+        let result = Constant::new("A_NAME", "A_VALUE");
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn sort() {
+        let mut constants = vec![
+            Constant::new("B", "1"),
+            Constant::new("A", "2"),
+            Constant::new("B", "0"),
+            Constant::new("A", "10"),
+        ];
+        constants.sort();
+        assert_eq!(
+            constants,
+            vec![
+                Constant::new("A", "10"),
+                Constant::new("A", "2"),
+                Constant::new("B", "0"),
+                Constant::new("B", "1"),
+            ]
+        );
+    }
+}
+
+/// Unit of a frequency axis
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrequencyUnit {
+    Hz,
+    KHz,
+    MHz,
+    GHz,
+}
+
+impl FrequencyUnit {
+    /// Multiplier converting a value in this unit to hertz e.g. `1e9` for GHz
+    pub fn to_hz(self) -> f64 {
+        match self {
+            FrequencyUnit::Hz => 1.,
+            FrequencyUnit::KHz => 1e3,
+            FrequencyUnit::MHz => 1e6,
+            FrequencyUnit::GHz => 1e9,
+        }
+    }
+
+    /// Parse a unit name in any case e.g. `GHZ` or `GHz`
+    fn from_name(name: &str) -> Option<FrequencyUnit> {
+        match name.to_ascii_uppercase().as_str() {
+            "HZ" => Some(FrequencyUnit::Hz),
+            "KHZ" => Some(FrequencyUnit::KHz),
+            "MHZ" => Some(FrequencyUnit::MHz),
+            "GHZ" => Some(FrequencyUnit::GHz),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_frequency_unit {
+    use super::*;
+
+    #[test]
+    fn to_hz() {
+        assert_eq!(FrequencyUnit::Hz.to_hz(), 1.);
+        assert_eq!(FrequencyUnit::KHz.to_hz(), 1e3);
+        assert_eq!(FrequencyUnit::MHz.to_hz(), 1e6);
+        assert_eq!(FrequencyUnit::GHz.to_hz(), 1e9);
+    }
+
+    #[test]
+    fn from_name() {
+        assert_eq!(FrequencyUnit::from_name("GHZ"), Some(FrequencyUnit::GHz));
+        assert_eq!(FrequencyUnit::from_name("khz"), Some(FrequencyUnit::KHz));
+        assert_eq!(FrequencyUnit::from_name("MHz"), Some(FrequencyUnit::MHz));
+        assert_eq!(FrequencyUnit::from_name("Hz"), Some(FrequencyUnit::Hz));
+        assert_eq!(FrequencyUnit::from_name("THz"), None);
+        assert_eq!(FrequencyUnit::from_name(""), None);
+    }
+}
+
+/// Where the independent variable values were read from
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum VarSource {
+    /// Listed point by point in a `VAR_LIST_BEGIN` block
+    VarList,
+    /// Generated from `SEG` items in a `SEG_LIST_BEGIN` block
+    SegList,
+    /// Only the length from the `VAR` keyword, without values
+    #[default]
+    LengthOnly,
+}
+
+/// The file header
+///
+/// Note that the `DATA` keywords are not defined here.
+///
+/// Besides the public fields, the header keeps private details of how it
+/// was read, such as [`Record::independent_variable_source`]. They are not
+/// compared by `==`, so the same contents read from a `VAR_LIST` or a
+/// `SEG_LIST` are equal. The private fields are a breaking change from
+/// 0.3: a `Header` can no longer be built with a struct literal, so start
+/// from [`Header::new`] or [`Header::default`] and set the fields instead.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub version: String,
+    pub name: String,
+    pub comments: Vec<String>,
+    pub devices: Vec<Device>,
+    pub independent_variable: Var,
+    pub constants: Vec<Constant>,
+    /// Unrecognised header lines, kept verbatim
+    ///
+    /// Only filled when reading with
+    /// [`ReaderOptions::preserve_unknown_keywords`]. Like the other header
+    /// lines, they are written in a fixed place rather than where they were
+    /// read: after the devices and before the `DATA` lines.
+    pub unknown_lines: Vec<String>,
+    /// Which list form the independent variable was read from
+    independent_variable_source: VarSource,
+    /// Length given on the `VAR` line, if read from a file
+    var_declared_length: Option<usize>,
+    /// `SEG` items the independent variable was read from
+    seg_items: Vec<Keyword>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            version: String::from("A.01.00"),
+            name: String::new(),
+            comments: vec![],
+            devices: vec![],
+            independent_variable: Var::blank(),
+            constants: vec![],
+            unknown_lines: vec![],
+            independent_variable_source: VarSource::LengthOnly,
+            var_declared_length: None,
+            seg_items: vec![],
+        }
+    }
+}
+
+/// Compares the public fields only
+impl PartialEq for Header {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.name == other.name
+            && self.comments == other.comments
+            && self.devices == other.devices
+            && self.independent_variable == other.independent_variable
+            && self.constants == other.constants
+            && self.unknown_lines == other.unknown_lines
+    }
+}
+
+impl Header {
+    pub fn new(version: &str, name: &str) -> Header {
+        Header {
+            version: String::from(version),
+            name: String::from(name),
+            comments: vec![],
+            devices: vec![],
+            independent_variable: Var::blank(),
+            constants: vec![],
+            unknown_lines: vec![],
+            independent_variable_source: VarSource::LengthOnly,
+            var_declared_length: None,
+            seg_items: vec![],
+        }
+    }
+
+    fn blank() -> Header {
+        Header {
+            version: String::new(),
+            name: String::new(),
+            comments: vec![],
+            devices: vec![],
+            independent_variable: Var::blank(),
+            constants: vec![],
+            unknown_lines: vec![],
+            independent_variable_source: VarSource::LengthOnly,
+            var_declared_length: None,
+            seg_items: vec![],
+        }
+    }
+
+    pub fn add_device(&mut self, device_name: &str, value: &str) {
+        self.create_device(device_name);
+        if let Some(i) = self.index_device(device_name) {
+            self.devices[i].entries.push(String::from(value));
+        }
+    }
+
+    /// Add `(device_name, value)` entries, grouped as with [`Header::add_device`]
+    pub fn add_devices<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        for (device_name, value) in iter {
+            self.add_device(&device_name, &value);
+        }
+    }
+
+    /// Add `(name, value)` constants in order
+    pub fn add_constants<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        self.constants.extend(
+            iter.into_iter()
+                .map(|(name, value)| Constant { name, value }),
+        );
+    }
+
+    /// Remove repeated entries within each device, keeping the first
+    ///
+    /// Entries are compared within a device only, so the same entry in two
+    /// devices is kept in both.
+    pub fn dedup_device_entries(&mut self) {
+        for device in self.devices.iter_mut() {
+            let mut seen = alloc::collections::BTreeSet::new();
+            device.entries.retain(|entry| seen.insert(entry.clone()));
+        }
+    }
+
+    /// Merge devices with the same name into the first of them
+    ///
+    /// Entries of later devices are appended in order. Repeated entries are
+    /// kept; follow with [`Header::dedup_device_entries`] to remove them.
+    pub fn dedup_devices(&mut self) {
+        let mut merged: Vec<Device> = Vec::with_capacity(self.devices.len());
+        for device in self.devices.drain(..) {
+            match merged.iter_mut().find(|d| d.name == device.name) {
+                Some(first) => first.entries.extend(device.entries),
+                None => merged.push(device),
+            }
+        }
+        self.devices = merged;
+    }
+
+    /// If the device already exists, nothing happens
+    pub fn create_device(&mut self, device_name: &str) {
+        if self.get_device_by_name(device_name).is_none() {
+            self.devices.push(Device::new(device_name));
+        }
+    }
+
+    pub fn get_device_by_name(&self, device_name: &str) -> Option<&Device> {
+        self.devices.iter().find(|&x| x.name == device_name)
+    }
+
+    pub fn index_device(&self, device_name: &str) -> Option<usize> {
+        self.devices.iter().position(|x| x.name == device_name)
+    }
+
+    /// Remove every comment from the header
+    pub fn clear_comments(&mut self) {
+        self.comments.clear();
+    }
+
+    /// True if any comment contains `substr`
+    pub fn has_comment(&self, substr: &str) -> bool {
+        self.comments.iter().any(|c| c.contains(substr))
+    }
+
+    /// First comment starting with `prefix` e.g. `DATE:`
+    pub fn find_comment(&self, prefix: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|c| c.starts_with(prefix))
+            .map(|c| c.as_str())
+    }
+
+    /// Comments of the form `KEY: value` as a map from key to value
+    ///
+    /// The key is the text before the first colon and must be a single
+    /// word. Both are trimmed. Other comments are left out, and the last
+    /// value wins if a key is repeated.
+    #[cfg(feature = "std")]
+    pub fn comment_metadata(&self) -> std::collections::HashMap<String, String> {
+        self.comment_entries()
+            .map(|(key, value)| (String::from(key), String::from(value)))
+            .collect()
+    }
+
+    /// `(key, value)` of every `KEY: value` comment, in order
+    fn comment_entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.comments
+            .iter()
+            .filter_map(|c| c.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .filter(|(key, _)| !key.is_empty() && !key.contains(char::is_whitespace))
+    }
+
+    /// Version as `(letter, major, minor)` e.g. `A.01.01` is `('A', 1, 1)`
+    ///
+    /// `None` if the version is not of the form `L.NN.NN`.
+    pub fn version_tuple(&self) -> Option<(char, u16, u16)> {
+        let mut parts = self.version.split('.');
+        let letter = parts.next()?;
+        let major = parts.next()?;
+        let minor = parts.next()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let mut chars = letter.chars();
+        let letter = match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => c,
+            _ => return None,
+        };
+        let number = |s: &str| match s.chars().all(|c| c.is_ascii_digit()) {
+            true => s.parse::<u16>().ok(),
+            false => None,
+        };
+        Some((letter, number(major)?, number(minor)?))
+    }
+}
+
+#[cfg(test)]
+mod test_header {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let expected = Header {
+            version: String::from("A.01.00"),
+            name: String::new(),
+            comments: vec![],
+            devices: vec![],
+            independent_variable: Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            },
+            constants: vec![],
+            unknown_lines: vec![],
+            independent_variable_source: VarSource::LengthOnly,
+            var_declared_length: None,
+            seg_items: vec![],
+        };
+        let result = Header::default();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_new() {
+        let expected = Header {
+            version: String::from("A.01.01"),
+            name: String::from("A_NAME"),
+            comments: vec![],
+            devices: vec![],
+            independent_variable: Var {
+                name: String::new(),
+                format: String::new(),
+                data: vec![],
+            },
+            constants: vec![],
+            unknown_lines: vec![],
+            independent_variable_source: VarSource::LengthOnly,
+            var_declared_length: None,
+            seg_items: vec![],
+        };
+        let result = Header::new("A.01.01", "A_NAME");
+        assert_eq!(result, expected);
+    }
+
+    mod test_version_tuple {
+        use super::*;
+
+        #[test]
+        fn a_01_00() {
+            let header = Header::new("A.01.00", "CAL_SET");
+            assert_eq!(header.version_tuple(), Some(('A', 1, 0)));
+        }
+
+        #[test]
+        fn a_01_01() {
+            let header = Header::new("A.01.01", "CAL_SET");
+            assert_eq!(header.version_tuple(), Some(('A', 1, 1)));
+        }
+
+        #[test]
+        fn ordering() {
+            let old = Header::new("A.01.00", "CAL_SET").version_tuple();
+            let new = Header::new("A.01.01", "CAL_SET").version_tuple();
+            assert!(old < new);
+        }
+
+        #[test]
+        fn malformed() {
+            for version in [
+                "",
+                "A",
+                "A.01",
+                "A.01.01.01",
+                "AB.01.01",
+                "1.01.01",
+                "A.x1.01",
+                "A.01.+1",
+            ]
+            .iter()
+            {
+                let header = Header::new(version, "CAL_SET");
+                assert_eq!(header.version_tuple(), None, "{}", version);
+            }
+        }
+    }
+
+    mod test_comments {
+        use super::*;
+
+        fn setup() -> Header {
+            let mut header = Header::new("A.01.00", "MEMORY");
+            header.comments.push(String::from("SOURCE: VNA"));
+            header.comments.push(String::from("DATE: 2019.11.01"));
+            header
+        }
+
+        #[test]
+        fn clear_comments() {
+            let mut header = setup();
+            header.clear_comments();
+            assert_eq!(header.comments, Vec::<String>::new());
+        }
+
+        #[test]
+        fn clear_comments_empty() {
+            let mut header = Header::new("A.01.00", "MEMORY");
+            header.clear_comments();
+            assert_eq!(header.comments, Vec::<String>::new());
+        }
+
+        #[test]
+        fn has_comment() {
+            assert!(setup().has_comment("2019"));
+        }
+
+        #[test]
+        fn has_comment_missing() {
+            assert!(!setup().has_comment("TIME"));
+        }
+
+        #[test]
+        fn find_comment() {
+            assert_eq!(setup().find_comment("DATE:"), Some("DATE: 2019.11.01"));
+        }
+
+        #[test]
+        fn find_comment_prefix_only() {
+            assert_eq!(setup().find_comment("2019"), None);
+        }
+
+        #[test]
+        fn find_comment_empty() {
+            let header = Header::new("A.01.00", "MEMORY");
+            assert_eq!(header.find_comment("DATE:"), None);
+        }
+
+        #[test]
+        fn comment_metadata() {
+            let mut header = setup();
+            header.comments.push(String::from("Calibrated before use"));
+            header.comments.push(String::from("OPERATOR:jdoe "));
+            header
+                .comments
+                .push(String::from("Note to self: check port 2"));
+            header.comments.push(String::from(": no key"));
+            header.comments.push(String::from("TIME: 12:30"));
+            let metadata = header.comment_metadata();
+            assert_eq!(metadata.len(), 4);
+            assert_eq!(metadata["DATE"], "2019.11.01");
+            assert_eq!(metadata["OPERATOR"], "jdoe");
+            assert_eq!(metadata["SOURCE"], "VNA");
+            assert_eq!(metadata["TIME"], "12:30");
+        }
+
+        #[test]
+        fn comment_metadata_last_wins() {
+            let mut header = setup();
+            header.comments.push(String::from("DATE: 2020.01.01"));
+            assert_eq!(header.comment_metadata()["DATE"], "2020.01.01");
+        }
+
+        #[test]
+        fn comment_metadata_empty() {
+            let header = Header::new("A.01.00", "MEMORY");
+            assert!(header.comment_metadata().is_empty());
+        }
+    }
+
+    mod test_add_constants {
+        use super::*;
+
+        #[test]
+        fn several() {
+            let mut header = Header::new("A.01.01", "A_NAME");
+            header.constants.push(Constant::new("A", "1"));
+            header.add_constants(vec![
+                (String::from("B"), String::from("2")),
+                (String::from("C"), String::from("3")),
+            ]);
+            assert_eq!(
+                header.constants,
+                vec![
+                    Constant::new("A", "1"),
+                    Constant::new("B", "2"),
+                    Constant::new("C", "3")
+                ]
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod test_devices {
+        use super::*;
+
+        #[cfg(test)]
+        mod test_add_device {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let expected = vec![Device {
+                    name: String::from("NA"),
+                    entries: vec![String::from("VERSION HP8510B.05.00")],
+                }];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                assert_eq!(header.devices, expected);
+            }
+
+            #[test]
+            fn double_add() {
+                let expected = vec![Device {
+                    name: String::from("NA"),
+                    entries: vec![
+                        String::from("VERSION HP8510B.05.00"),
+                        String::from("REGISTER 1"),
+                    ],
+                }];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                header.add_device("NA", "REGISTER 1");
+                assert_eq!(header.devices, expected);
+            }
+
+            #[test]
+            fn add_two_devices() {
+                let expected = vec![
+                    Device {
+                        name: String::from("NA"),
+                        entries: vec![String::from("VERSION HP8510B.05.00")],
+                    },
+                    Device {
+                        name: String::from("WVI"),
+                        entries: vec![String::from("REGISTER 1")],
+                    },
+                ];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                header.add_device("WVI", "REGISTER 1");
+                assert_eq!(header.devices, expected);
+            }
+        }
+
+        mod test_add_devices {
+            use super::*;
+
+            #[test]
+            fn several() {
+                let expected = vec![
+                    Device {
+                        name: String::from("NA"),
+                        entries: vec![
+                            String::from("VERSION HP8510B.05.00"),
+                            String::from("REGISTER 1"),
+                        ],
+                    },
+                    Device {
+                        name: String::from("WVI"),
+                        entries: vec![String::from("A B")],
+                    },
+                ];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_devices(vec![
+                    (String::from("NA"), String::from("VERSION HP8510B.05.00")),
+                    (String::from("WVI"), String::from("A B")),
+                    (String::from("NA"), String::from("REGISTER 1")),
+                ]);
+                assert_eq!(header.devices, expected);
+            }
+
+            #[test]
+            fn existing_device() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                header.add_devices(vec![(String::from("NA"), String::from("REGISTER 1"))]);
+                assert_eq!(header.devices.len(), 1);
+                assert_eq!(header.devices[0].entries.len(), 2);
+            }
+
+            #[test]
+            fn none() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_devices(vec![]);
+                assert!(header.devices.is_empty());
+            }
+        }
+
+        #[cfg(test)]
+        mod test_create_device {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let expected = vec![Device {
+                    name: String::from("A Name"),
+                    entries: vec![],
+                }];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                assert_eq!(header.devices, expected);
+            }
+
+            #[test]
+            fn appends_device() {
+                let expected = vec![
+                    Device {
+                        name: String::from("Different Name"),
+                        entries: vec![],
+                    },
+                    Device {
+                        name: String::from("A Name"),
+                        entries: vec![],
+                    },
+                ];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("Different Name");
+                header.create_device("A Name");
+                assert_eq!(header.devices, expected);
+            }
+
+            #[test]
+            fn existing_device() {
+                let expected = vec![Device {
+                    name: String::from("A Name"),
+                    entries: vec![],
+                }];
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                header.create_device("A Name");
+                assert_eq!(header.devices, expected);
+            }
+        }
+
+        #[cfg(test)]
+        mod test_index_device {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let header = Header::new("A.01.01", "A_NAME");
+                assert_eq!(header.index_device(""), None);
+            }
+
+            #[test]
+            fn no_device_found() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                assert_eq!(header.index_device(""), None);
+            }
+
+            #[test]
+            fn device_found() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                assert_eq!(header.index_device("A Name"), Some(0));
+            }
+        }
+
+        #[cfg(test)]
+        mod test_get_device_by_name {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let header = Header::new("A.01.01", "A_NAME");
+                assert_eq!(header.get_device_by_name(""), None);
+            }
+
+            #[test]
+            fn no_device_found() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                assert_eq!(header.get_device_by_name(""), None);
+            }
+
+            #[test]
+            fn device_found() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.create_device("A Name");
+                assert_eq!(
+                    header.get_device_by_name("A Name"),
+                    Some(&Device {
+                        name: String::from("A Name"),
+                        entries: vec![]
+                    })
+                );
+            }
+        }
+
+        mod test_dedup {
+            use super::*;
+
+            fn device(name: &str, entries: &[&str]) -> Device {
+                Device {
+                    name: String::from(name),
+                    entries: entries.iter().map(|e| String::from(*e)).collect(),
+                }
+            }
+
+            #[test]
+            fn entries() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                header.add_device("NA", "REGISTER 1");
+                header.add_device("NA", "VERSION HP8510B.05.00");
+                header.add_device("WVI", "REGISTER 1");
+                header.dedup_device_entries();
+                assert_eq!(
+                    header.devices,
+                    vec![
+                        device("NA", &["VERSION HP8510B.05.00", "REGISTER 1"]),
+                        device("WVI", &["REGISTER 1"]),
+                    ]
+                );
+            }
+
+            #[test]
+            fn devices() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.devices = vec![
+                    device("NA", &["VERSION HP8510B.05.00"]),
+                    device("WVI", &["A"]),
+                    device("NA", &["REGISTER 1", "VERSION HP8510B.05.00"]),
+                ];
+                header.dedup_devices();
+                assert_eq!(
+                    header.devices,
+                    vec![
+                        device(
+                            "NA",
+                            &[
+                                "VERSION HP8510B.05.00",
+                                "REGISTER 1",
+                                "VERSION HP8510B.05.00"
+                            ]
+                        ),
+                        device("WVI", &["A"]),
+                    ]
+                );
+
+                header.dedup_device_entries();
+                assert_eq!(
+                    header.devices[0],
+                    device("NA", &["VERSION HP8510B.05.00", "REGISTER 1"])
+                );
+            }
+
+            #[test]
+            fn empty() {
+                let mut header = Header::new("A.01.01", "A_NAME");
+                header.dedup_devices();
+                header.dedup_device_entries();
+                assert!(header.devices.is_empty());
+            }
+        }
+    }
+}
+
+/// Unit of the angle in `MAGANGLE` and `DBANGLE` pairs
+///
+/// The standard uses degrees, but some files are written in radians.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum AngleUnit {
+    #[default]
+    Degrees,
+    Radians,
+}
+
+impl AngleUnit {
+    /// Convert an angle in this unit to radians
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => angle.to_radians(),
+            AngleUnit::Radians => angle,
+        }
+    }
+
+    /// Convert an angle in radians to this unit
+    pub fn from_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => angle.to_degrees(),
+            AngleUnit::Radians => angle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_angle_unit {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_default() {
+        assert_eq!(AngleUnit::default(), AngleUnit::Degrees);
+    }
+
+    #[test]
+    fn degrees() {
+        assert_relative_eq!(AngleUnit::Degrees.to_radians(180.), std::f64::consts::PI);
+        assert_relative_eq!(AngleUnit::Degrees.from_radians(std::f64::consts::PI), 180.);
+    }
+
+    #[test]
+    fn radians() {
+        assert_relative_eq!(AngleUnit::Radians.to_radians(1.5), 1.5);
+        assert_relative_eq!(AngleUnit::Radians.from_radians(1.5), 1.5);
+    }
+}
+
+/// Interpretation of a data array format
+///
+/// Each `DATA` keyword declares how its pairs are to be read. Angles are
+/// in the given [`AngleUnit`], degrees in the standard. Formats outside of
+/// the standard are treated as real, imaginary pairs so that they pass
+/// through unchanged.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DataFormat {
+    /// `RI`: real and imaginary. `COMPLEX` and `COMPLEX64` are accepted as aliases.
+    RealImaginary,
+    /// `MAGANGLE`: linear magnitude and angle
+    MagnitudeAngle,
+    /// `DBANGLE`: magnitude in decibels and angle
+    DecibelAngle,
+    /// `MAG`: linear magnitude with no phase
+    Magnitude,
+    /// `DB`: magnitude in decibels with no phase
+    Decibel,
+    /// Any other format
+    Unknown,
+}
+
+impl From<&str> for DataFormat {
+    /// Classify a format, ignoring a trailing annotation such as `[50]` in `RI[50]`
+    fn from(format: &str) -> Self {
+        let (format, _) = split_format_annotation(format);
+        match format.to_ascii_uppercase().as_str() {
+            "RI" | "COMPLEX" | "COMPLEX64" => DataFormat::RealImaginary,
+            "MAGANGLE" => DataFormat::MagnitudeAngle,
+            "DBANGLE" => DataFormat::DecibelAngle,
+            "MAG" => DataFormat::Magnitude,
+            "DB" => DataFormat::Decibel,
+            _ => DataFormat::Unknown,
+        }
+    }
+}
+
+/// Split a format such as `RI[50]` into `RI` and the annotation `50`
+fn split_format_annotation(format: &str) -> (&str, Option<&str>) {
+    let annotation = format
+        .strip_suffix(']')
+        .and_then(|rest| rest.split_once('['))
+        .filter(|(kind, _)| !kind.is_empty());
+    match annotation {
+        Some((kind, annotation)) => (kind, Some(annotation)),
+        None => (format, None),
+    }
+}
+
+impl DataFormat {
+    /// Convert a pair as written in a record, with angles in `unit`, to a
+    /// complex sample
+    pub fn to_complex(self, first: f64, second: f64, unit: AngleUnit) -> Complex<f64> {
+        match self {
+            DataFormat::RealImaginary | DataFormat::Unknown => Complex::new(first, second),
+            DataFormat::MagnitudeAngle => Complex::from_polar(first, unit.to_radians(second)),
+            DataFormat::DecibelAngle => {
+                Complex::from_polar(10_f64.powf(first / 20.), unit.to_radians(second))
+            }
+            DataFormat::Magnitude => Complex::new(first, 0.),
+            DataFormat::Decibel => Complex::new(10_f64.powf(first / 20.), 0.),
+        }
+    }
+
+    /// True if the format has a phase or imaginary part
+    ///
+    /// Such formats cannot be stored in the real valued independent variable.
+    pub fn has_phase(self) -> bool {
+        matches!(
+            self,
+            DataFormat::RealImaginary | DataFormat::MagnitudeAngle | DataFormat::DecibelAngle
+        )
+    }
+
+    /// Compare two format strings
+    ///
+    /// Known formats are compared by kind, others must match exactly.
+    pub fn same_format(lhs: &str, rhs: &str) -> bool {
+        match (DataFormat::from(lhs), DataFormat::from(rhs)) {
+            (DataFormat::Unknown, _) | (_, DataFormat::Unknown) => lhs == rhs,
+            (l, r) => l == r,
+        }
+    }
+
+    /// Convert a complex sample to a pair as written in a record, with
+    /// angles in `unit`
+    ///
+    /// A real sample in `MAG` keeps its sign. A sample the format cannot
+    /// represent, see [`DataFormat::represents`], is written as its
+    /// magnitude, and a zero magnitude in decibels as `-inf`.
+    pub fn from_complex(self, value: &Complex<f64>, unit: AngleUnit) -> (f64, f64) {
+        match self {
+            DataFormat::RealImaginary | DataFormat::Unknown => (value.re, value.im),
+            DataFormat::MagnitudeAngle => (value.norm(), unit.from_radians(value.arg())),
+            DataFormat::DecibelAngle => (decibels(value.norm()), unit.from_radians(value.arg())),
+            DataFormat::Magnitude if value.im == 0. => (value.re, 0.),
+            DataFormat::Magnitude => (value.norm(), 0.),
+            DataFormat::Decibel => (decibels(value.norm()), 0.),
+        }
+    }
+
+    /// True if `value` written in this format reads back unchanged, up to
+    /// rounding
+    ///
+    /// `MAG` and `DB` have no phase, so only hold real samples, which must
+    /// be positive in decibels. `DBANGLE` cannot hold a zero magnitude.
+    pub fn represents(self, value: &Complex<f64>) -> bool {
+        match self {
+            DataFormat::RealImaginary | DataFormat::Unknown | DataFormat::MagnitudeAngle => true,
+            DataFormat::DecibelAngle => value.norm() != 0.,
+            DataFormat::Magnitude => value.im == 0.,
+            DataFormat::Decibel => value.im == 0. && value.re > 0.,
+        }
+    }
+}
+
+/// Magnitude in decibels, `-inf` for zero
+fn decibels(magnitude: f64) -> f64 {
+    match magnitude == 0. {
+        true => f64::NEG_INFINITY,
+        false => 20. * magnitude.log10(),
+    }
+}
+
+#[cfg(test)]
+mod test_data_format {
+    use super::*;
+    use approx::*;
+
+    mod test_split_format_annotation {
+        use super::*;
+
+        #[test]
+        fn annotated() {
+            assert_eq!(split_format_annotation("RI[50]"), ("RI", Some("50")));
+        }
+
+        #[test]
+        fn empty_annotation() {
+            assert_eq!(split_format_annotation("RI[]"), ("RI", Some("")));
+        }
+
+        #[test]
+        fn plain() {
+            assert_eq!(split_format_annotation("RI"), ("RI", None));
+        }
+
+        #[test]
+        fn only_brackets() {
+            assert_eq!(split_format_annotation("[50]"), ("[50]", None));
+        }
+
+        #[test]
+        fn unclosed() {
+            assert_eq!(split_format_annotation("RI[50"), ("RI[50", None));
+        }
+    }
+
+    mod test_from_str {
+        use super::*;
+
+        #[test]
+        fn real_imaginary_annotated() {
+            assert_eq!(DataFormat::from("RI[50]"), DataFormat::RealImaginary);
+        }
+
+        #[test]
+        fn magnitude_angle_annotated() {
+            assert_eq!(DataFormat::from("MAGANGLE[75]"), DataFormat::MagnitudeAngle);
+        }
+
+        #[test]
+        fn real_imaginary() {
+            assert_eq!(DataFormat::from("RI"), DataFormat::RealImaginary);
+        }
+
+        #[test]
+        fn complex() {
+            assert_eq!(DataFormat::from("COMPLEX"), DataFormat::RealImaginary);
+        }
+
+        #[test]
+        fn complex64() {
+            assert_eq!(DataFormat::from("COMPLEX64"), DataFormat::RealImaginary);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            assert_eq!(DataFormat::from("MAGANGLE"), DataFormat::MagnitudeAngle);
+        }
+
+        #[test]
+        fn decibel_angle() {
+            assert_eq!(DataFormat::from("DBANGLE"), DataFormat::DecibelAngle);
+        }
+
+        #[test]
+        fn magnitude() {
+            assert_eq!(DataFormat::from("MAG"), DataFormat::Magnitude);
+        }
+
+        #[test]
+        fn decibel() {
+            assert_eq!(DataFormat::from("DB"), DataFormat::Decibel);
+        }
+
+        #[test]
+        fn lowercase() {
+            assert_eq!(DataFormat::from("magangle"), DataFormat::MagnitudeAngle);
+        }
+
+        #[test]
+        fn unknown() {
+            assert_eq!(DataFormat::from("Format A"), DataFormat::Unknown);
+        }
+
+        #[test]
+        fn empty() {
+            assert_eq!(DataFormat::from(""), DataFormat::Unknown);
+        }
+    }
+
+    mod test_same_format {
+        use super::*;
+
+        #[test]
+        fn same_known() {
+            assert!(DataFormat::same_format("RI", "RI"));
+        }
+
+        #[test]
+        fn alias() {
+            assert!(DataFormat::same_format("COMPLEX", "RI"));
+        }
+
+        #[test]
+        fn different_known() {
+            assert!(!DataFormat::same_format("MAGANGLE", "RI"));
+        }
+
+        #[test]
+        fn same_unknown() {
+            assert!(DataFormat::same_format("Format A", "Format A"));
+        }
+
+        #[test]
+        fn different_unknown() {
+            assert!(!DataFormat::same_format("Format A", "Format B"));
+        }
+
+        #[test]
+        fn unknown_and_known() {
+            assert!(!DataFormat::same_format("Format A", "RI"));
+        }
+    }
+
+    #[test]
+    fn represents() {
+        let zero = Complex::new(0., 0.);
+        let negative = Complex::new(-2., 0.);
+        let complex = Complex::new(1., 1.);
+        for format in [DataFormat::RealImaginary, DataFormat::MagnitudeAngle].iter() {
+            assert!(format.represents(&zero));
+            assert!(format.represents(&negative));
+            assert!(format.represents(&complex));
+        }
+        assert!(!DataFormat::DecibelAngle.represents(&zero));
+        assert!(DataFormat::DecibelAngle.represents(&negative));
+        assert!(DataFormat::Magnitude.represents(&zero));
+        assert!(DataFormat::Magnitude.represents(&negative));
+        assert!(!DataFormat::Magnitude.represents(&complex));
+        assert!(!DataFormat::Decibel.represents(&zero));
+        assert!(!DataFormat::Decibel.represents(&negative));
+        assert!(DataFormat::Decibel.represents(&Complex::new(0.1, 0.)));
+    }
+
+    #[test]
+    fn has_phase() {
+        assert!(DataFormat::RealImaginary.has_phase());
+        assert!(DataFormat::MagnitudeAngle.has_phase());
+        assert!(DataFormat::DecibelAngle.has_phase());
+        assert!(!DataFormat::Magnitude.has_phase());
+        assert!(!DataFormat::Decibel.has_phase());
+        assert!(!DataFormat::Unknown.has_phase());
+    }
+
+    mod test_to_complex {
+        use super::*;
+
+        #[test]
+        fn real_imaginary() {
+            let result = DataFormat::RealImaginary.to_complex(1., -2., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, 1.);
+            assert_relative_eq!(result.im, -2.);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            let result = DataFormat::MagnitudeAngle.to_complex(2., 90., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, 0., epsilon = 1e-12);
+            assert_relative_eq!(result.im, 2.);
+        }
+
+        #[test]
+        fn decibel_angle() {
+            let result = DataFormat::DecibelAngle.to_complex(20., 180., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, -10.);
+            assert_relative_eq!(result.im, 0., epsilon = 1e-12);
+        }
+
+        #[test]
+        fn magnitude() {
+            let result = DataFormat::Magnitude.to_complex(3., 5., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, 3.);
+            assert_relative_eq!(result.im, 0.);
+        }
+
+        #[test]
+        fn decibel() {
+            let result = DataFormat::Decibel.to_complex(-20., 0., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, 0.1);
+            assert_relative_eq!(result.im, 0.);
+        }
+
+        #[test]
+        fn magnitude_angle_degrees() {
+            let result = DataFormat::MagnitudeAngle.to_complex(1., 90., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, Complex::<f64>::i().re, epsilon = 1e-12);
+            assert_relative_eq!(result.im, Complex::<f64>::i().im);
+        }
+
+        #[test]
+        fn magnitude_angle_radians() {
+            let result = DataFormat::MagnitudeAngle.to_complex(
+                1.,
+                std::f64::consts::FRAC_PI_2,
+                AngleUnit::Radians,
+            );
+            assert_relative_eq!(result.re, Complex::<f64>::i().re, epsilon = 1e-12);
+            assert_relative_eq!(result.im, Complex::<f64>::i().im);
+        }
+
+        #[test]
+        fn decibel_angle_radians() {
+            let result =
+                DataFormat::DecibelAngle.to_complex(20., std::f64::consts::PI, AngleUnit::Radians);
+            assert_relative_eq!(result.re, -10.);
+            assert_relative_eq!(result.im, 0., epsilon = 1e-12);
+        }
+
+        #[test]
+        fn unknown() {
+            let result = DataFormat::Unknown.to_complex(1., -2., AngleUnit::Degrees);
+            assert_relative_eq!(result.re, 1.);
+            assert_relative_eq!(result.im, -2.);
+        }
+    }
+
+    mod test_from_complex {
+        use super::*;
+
+        #[test]
+        fn real_imaginary() {
+            let (first, second) =
+                DataFormat::RealImaginary.from_complex(&Complex::new(1., -2.), AngleUnit::Degrees);
+            assert_relative_eq!(first, 1.);
+            assert_relative_eq!(second, -2.);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            let (first, second) =
+                DataFormat::MagnitudeAngle.from_complex(&Complex::new(0., 2.), AngleUnit::Degrees);
+            assert_relative_eq!(first, 2.);
+            assert_relative_eq!(second, 90.);
+        }
+
+        #[test]
+        fn decibel_angle() {
+            let (first, second) =
+                DataFormat::DecibelAngle.from_complex(&Complex::new(-10., 0.), AngleUnit::Degrees);
+            assert_relative_eq!(first, 20.);
+            assert_relative_eq!(second, 180.);
+        }
+
+        #[test]
+        fn magnitude() {
+            let (first, second) =
+                DataFormat::Magnitude.from_complex(&Complex::new(3., 4.), AngleUnit::Degrees);
+            assert_relative_eq!(first, 5.);
+            assert_relative_eq!(second, 0.);
+        }
+
+        #[test]
+        fn magnitude_negative() {
+            let (first, second) =
+                DataFormat::Magnitude.from_complex(&Complex::new(-3., 0.), AngleUnit::Degrees);
+            assert_relative_eq!(first, -3.);
+            assert_relative_eq!(second, 0.);
+        }
+
+        #[test]
+        fn decibel() {
+            let (first, second) =
+                DataFormat::Decibel.from_complex(&Complex::new(0.1, 0.), AngleUnit::Degrees);
+            assert_relative_eq!(first, -20.);
+            assert_relative_eq!(second, 0.);
+        }
+
+        #[test]
+        fn decibel_zero() {
+            let (first, _) =
+                DataFormat::Decibel.from_complex(&Complex::new(0., 0.), AngleUnit::Degrees);
+            assert_eq!(first, f64::NEG_INFINITY);
+            let (first, _) =
+                DataFormat::DecibelAngle.from_complex(&Complex::new(0., 0.), AngleUnit::Degrees);
+            assert_eq!(first, f64::NEG_INFINITY);
+        }
+
+        #[test]
+        fn magnitude_angle_radians() {
+            let (first, second) =
+                DataFormat::MagnitudeAngle.from_complex(&Complex::new(0., 2.), AngleUnit::Radians);
+            assert_relative_eq!(first, 2.);
+            assert_relative_eq!(second, std::f64::consts::FRAC_PI_2);
+        }
+
+        #[test]
+        fn round_trip_magnitude_angle() {
+            let value = Complex::new(0.3, -0.4);
+            let (first, second) =
+                DataFormat::MagnitudeAngle.from_complex(&value, AngleUnit::Degrees);
+            let result = DataFormat::MagnitudeAngle.to_complex(first, second, AngleUnit::Degrees);
+            assert_relative_eq!(result.re, value.re);
+            assert_relative_eq!(result.im, value.im);
+        }
+    }
+}
+
+/// A named, formatted, data array
+///
+/// Consistency of the format with the variable `samples` is not
+/// guaranteed and should be enforced by users of this code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DataArray {
+    pub name: String,
+    pub format: String,
+    pub samples: Vec<Complex<f64>>,
+}
+
+impl DataArray {
+    #[cfg(test)]
+    fn blank() -> DataArray {
+        DataArray {
+            name: String::new(),
+            format: String::new(),
+            samples: vec![],
+        }
+    }
+
+    pub fn new(name: &str, format: &str) -> DataArray {
+        DataArray {
+            name: String::from(name),
+            format: String::from(format),
+            samples: vec![],
+        }
+    }
+
+    /// New array with room for `n` samples
+    pub fn with_capacity(name: &str, format: &str, n: usize) -> DataArray {
+        DataArray {
+            name: String::from(name),
+            format: String::from(format),
+            samples: Vec::with_capacity(n),
+        }
+    }
+
+    /// Annotation at the end of the format e.g. `50` in `RI[50]`
+    pub fn format_annotation(&self) -> Option<&str> {
+        split_format_annotation(&self.format).1
+    }
+
+    /// Samples of the array
+    pub fn values(&self) -> &[Complex<f64>] {
+        &self.samples
+    }
+
+    /// Mutable access to the samples of the array
+    pub fn values_mut(&mut self) -> &mut Vec<Complex<f64>> {
+        &mut self.samples
+    }
+
+    /// Samples as `(re, im)` tuples, whatever the array format
+    ///
+    /// Unlike [`DataArray::pairs`], no conversion is made.
+    pub fn as_tuples(&self) -> Vec<(f64, f64)> {
+        self.samples.iter().map(|c| (c.re, c.im)).collect()
+    }
+
+    /// Phase of every sample in degrees, without jumps at ±180°
+    ///
+    /// Whenever the phase changes by more than 180° from one sample to the
+    /// next, whole turns are added or removed so it stays continuous. The
+    /// first sample keeps its phase in `(-180, 180]`.
+    pub fn phase_degrees_unwrapped(&self) -> Vec<f64> {
+        let mut unwrapped: Vec<f64> = Vec::with_capacity(self.samples.len());
+        let mut offset = 0.;
+        let mut previous: Option<f64> = None;
+        for sample in self.samples.iter() {
+            let phase = sample.arg().to_degrees();
+            if let Some(previous) = previous {
+                offset -= 360. * ((phase - previous) / 360.).round();
+            }
+            previous = Some(phase);
+            unwrapped.push(phase + offset);
+        }
+        unwrapped
+    }
+
+    /// New array with samples from `(re, im)` tuples
+    pub fn from_tuples<I: IntoIterator<Item = (f64, f64)>>(
+        name: &str,
+        format: &str,
+        tuples: I,
+    ) -> DataArray {
+        DataArray {
+            name: String::from(name),
+            format: String::from(format),
+            samples: tuples
+                .into_iter()
+                .map(|(re, im)| Complex::new(re, im))
+                .collect(),
+        }
+    }
+
+    pub fn add_sample(&mut self, real: f64, imag: f64) {
+        self.samples.push(Complex::<f64>::new(real, imag));
+    }
+
+    /// Add a pair as written in the record, with angles in `unit`,
+    /// interpreted with the array format
+    pub fn add_pair(&mut self, first: f64, second: f64, unit: AngleUnit) {
+        let sample = DataFormat::from(self.format.as_str()).to_complex(first, second, unit);
+        self.samples.push(sample);
+    }
+
+    /// Samples as pairs in the array format with angles in `unit`
+    pub fn pairs(&self, unit: AngleUnit) -> Vec<(f64, f64)> {
+        let format = DataFormat::from(self.format.as_str());
+        self.samples
+            .iter()
+            .map(|c| format.from_complex(c, unit))
+            .collect()
+    }
+
+    /// Element-wise sum with another array of the same length
+    pub fn add(&self, other: &DataArray) -> Result<DataArray> {
+        Ok(self.zip_with(other, |a, b| a + b)?)
+    }
+
+    /// Element-wise difference with another array of the same length
+    pub fn sub(&self, other: &DataArray) -> Result<DataArray> {
+        Ok(self.zip_with(other, |a, b| a - b)?)
+    }
+
+    /// Element-wise product with another array of the same length
+    pub fn mul(&self, other: &DataArray) -> Result<DataArray> {
+        Ok(self.zip_with(other, |a, b| a * b)?)
+    }
+
+    /// Element-wise quotient with another array of the same length
+    ///
+    /// Division by zero gives non-finite samples rather than an error.
+    pub fn div(&self, other: &DataArray) -> Result<DataArray> {
+        Ok(self.zip_with(other, |a, b| a / b)?)
+    }
+
+    /// Copy with every sample multiplied by `factor`
+    pub fn scale(&self, factor: Complex<f64>) -> DataArray {
+        self.map_samples(|c| c * factor)
+    }
+
+    /// Copy with every sample inverted e.g. admittance from impedance
+    ///
+    /// Zero samples give non-finite samples rather than an error.
+    pub fn reciprocal(&self) -> DataArray {
+        self.map_samples(|c| c.inv())
+    }
+
+    fn map_samples<F: Fn(Complex<f64>) -> Complex<f64>>(&self, f: F) -> DataArray {
+        DataArray {
+            name: self.name.clone(),
+            format: self.format.clone(),
+            samples: self.samples.iter().map(|c| f(*c)).collect(),
+        }
+    }
+
+    fn zip_with<F: Fn(Complex<f64>, Complex<f64>) -> Complex<f64>>(
+        &self,
+        other: &DataArray,
+        f: F,
+    ) -> RecordResult<DataArray> {
+        if self.samples.len() != other.samples.len() {
+            return Err(RecordError::DataArrayLengthsDiffer(
+                self.samples.len(),
+                other.samples.len(),
+            ));
+        }
+        Ok(DataArray {
+            name: self.name.clone(),
+            format: self.format.clone(),
+            samples: self
+                .samples
+                .iter()
+                .zip(other.samples.iter())
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+        })
+    }
+
+    /// Convert to single precision samples
+    ///
+    /// Values outside of the `f32` range saturate to infinity.
+    pub fn to_f32(&self) -> DataArrayF32 {
+        DataArrayF32 {
+            name: self.name.clone(),
+            format: self.format.clone(),
+            samples: self
+                .samples
+                .iter()
+                .map(|c| Complex::<f32>::new(c.re as f32, c.im as f32))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_data_array {
+    use super::*;
+
+    #[test]
+    fn format_annotation() {
+        let array = DataArray::new("S[1,1]", "RI[50]");
+        assert_eq!(array.format_annotation(), Some("50"));
+        assert_eq!(DataArray::new("S[1,1]", "RI").format_annotation(), None);
+    }
+
+    #[test]
+    fn values() {
+        let mut array = DataArray::new("S", "RI");
+        array.add_sample(1., 2.);
+        assert_complex_array_relative_eq!(array.values(), [Complex::new(1., 2.)]);
+        array.values_mut().push(Complex::new(3., 4.));
+        assert_complex_array_relative_eq!(
+            array.samples,
+            [Complex::new(1., 2.), Complex::new(3., 4.)]
+        );
+    }
+
+    #[test]
+    fn test_blank() {
+        let expected = DataArray {
+            name: String::new(),
+            format: String::new(),
+            samples: vec![],
+        };
+        let result = DataArray::blank();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_new() {
+        let expected = DataArray {
+            name: String::from("S"),
+            format: String::from("RI"),
+            samples: vec![],
+        };
+        let result = DataArray::new("S", "RI");
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let result = DataArray::with_capacity("S", "RI", 10);
+        assert_eq!(result, DataArray::new("S", "RI"));
+        assert!(result.samples.capacity() >= 10);
+    }
+
+    #[cfg(test)]
+    mod test_add_sample {
+        use super::*;
+
+        #[test]
+        fn empty() {
+            let mut result = DataArray {
+                name: String::new(),
+                format: String::new(),
+                samples: vec![],
+            };
+            result.add_sample(1., 2.);
+            assert_complex_array_relative_eq!(result.samples, [Complex { re: 1., im: 2. }]);
+        }
+
+        #[test]
+        fn double() {
+            let mut result = DataArray {
+                name: String::new(),
+                format: String::new(),
+                samples: vec![],
+            };
+            result.add_sample(1., 2.);
+            result.add_sample(-1., -2.);
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex { re: 1., im: 2. }, Complex { re: -1., im: -2. }]
+            );
+        }
+
+        #[test]
+        fn existing() {
+            let mut result = DataArray {
+                name: String::new(),
+                format: String::new(),
+                samples: vec![Complex { re: 1., im: 2. }],
+            };
+            result.add_sample(3., 4.);
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex { re: 1., im: 2. }, Complex { re: 3., im: 4. }]
+            );
+        }
+    }
+
+    mod test_add_pair {
+        use super::*;
+
+        #[test]
+        fn real_imaginary() {
+            let mut result = DataArray::new("S", "RI");
+            result.add_pair(1., 2., AngleUnit::Degrees);
+            assert_complex_array_relative_eq!(result.samples, [Complex { re: 1., im: 2. }]);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            let mut result = DataArray::new("S", "MAGANGLE");
+            result.add_pair(2., 180., AngleUnit::Degrees);
+            approx::assert_relative_eq!(result.samples[0].re, -2.);
+            approx::assert_relative_eq!(result.samples[0].im, 0., epsilon = 1e-12);
+        }
+
+        #[test]
+        fn magnitude_angle_radians() {
+            let mut result = DataArray::new("S", "MAGANGLE");
+            result.add_pair(2., std::f64::consts::PI, AngleUnit::Radians);
+            approx::assert_relative_eq!(result.samples[0].re, -2.);
+            approx::assert_relative_eq!(result.samples[0].im, 0., epsilon = 1e-12);
+        }
+
+        #[test]
+        fn unknown() {
+            let mut result = DataArray::new("S", "Format A");
+            result.add_pair(1., 2., AngleUnit::Degrees);
+            assert_complex_array_relative_eq!(result.samples, [Complex { re: 1., im: 2. }]);
+        }
+    }
+
+    mod test_pairs {
+        use super::*;
+
+        #[test]
+        fn empty() {
+            let result = DataArray::new("S", "RI");
+            assert_eq!(result.pairs(AngleUnit::Degrees), vec![]);
+        }
+
+        #[test]
+        fn real_imaginary() {
+            let mut result = DataArray::new("S", "RI");
+            result.add_sample(1., 2.);
+            assert_eq!(result.pairs(AngleUnit::Degrees), vec![(1., 2.)]);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            let mut result = DataArray::new("S", "MAGANGLE");
+            result.add_sample(0., -3.);
+            let pairs = result.pairs(AngleUnit::Degrees);
+            approx::assert_relative_eq!(pairs[0].0, 3.);
+            approx::assert_relative_eq!(pairs[0].1, -90.);
+        }
+
+        #[test]
+        fn magnitude_angle_radians() {
+            let mut result = DataArray::new("S", "MAGANGLE");
+            result.add_sample(0., -3.);
+            let pairs = result.pairs(AngleUnit::Radians);
+            approx::assert_relative_eq!(pairs[0].0, 3.);
+            approx::assert_relative_eq!(pairs[0].1, -std::f64::consts::FRAC_PI_2);
+        }
+    }
+
+    mod test_phase_degrees_unwrapped {
+        use super::*;
+        use approx::assert_relative_eq;
+
+        fn array(degrees: &[f64]) -> DataArray {
+            let mut array = DataArray::new("S[2,1]", "RI");
+            array.samples = degrees
+                .iter()
+                .map(|d| Complex::from_polar(1., d.to_radians()))
+                .collect();
+            array
+        }
+
+        #[test]
+        fn wraps_forward() {
+            let result = array(&[150., 170., -170., -150., -130.]).phase_degrees_unwrapped();
+            for (v, expected) in result.iter().zip([150., 170., 190., 210., 230.].iter()) {
+                assert_relative_eq!(*v, *expected, epsilon = 1e-9);
+            }
+        }
+
+        #[test]
+        fn wraps_backward_several_turns() {
+            // Phase falling by 100° per sample
+            let degrees: Vec<f64> = (0..10).map(|i| -100. * i as f64).collect();
+            let result = array(&degrees).phase_degrees_unwrapped();
+            for (v, expected) in result.iter().zip(degrees.iter()) {
+                assert_relative_eq!(*v, *expected, epsilon = 1e-9);
+            }
+            assert!(result.windows(2).all(|w| (w[1] - w[0]).abs() <= 180.));
+        }
+
+        #[test]
+        fn empty() {
+            assert!(DataArray::new("S", "RI")
+                .phase_degrees_unwrapped()
+                .is_empty());
+        }
+    }
+
+    mod test_tuples {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let tuples = vec![(1., 2.), (-3., 0.5), (0., -1e-9)];
+            let array = DataArray::from_tuples("S[1,1]", "RI", tuples.clone());
+            assert_eq!(array.name, "S[1,1]");
+            assert_eq!(array.format, "RI");
+            assert_eq!(array.samples[1], Complex::new(-3., 0.5));
+            assert_eq!(array.as_tuples(), tuples);
+        }
+
+        #[test]
+        fn not_converted() {
+            let mut array = DataArray::new("S", "MAGANGLE");
+            array.add_sample(0., -3.);
+            assert_eq!(array.as_tuples(), vec![(0., -3.)]);
+        }
+
+        #[test]
+        fn empty() {
+            let array = DataArray::from_tuples("S", "RI", vec![]);
+            assert!(array.as_tuples().is_empty());
+        }
+    }
+
+    mod test_arithmetic {
+        use super::*;
+
+        fn setup() -> (DataArray, DataArray) {
+            let mut lhs = DataArray::new("Z", "RI");
+            lhs.add_sample(1., 2.);
+            lhs.add_sample(3., -1.);
+            let mut rhs = DataArray::new("Y", "MAGANGLE");
+            rhs.add_sample(0., 1.);
+            rhs.add_sample(2., 0.);
+            (lhs, rhs)
+        }
+
+        #[test]
+        fn add() {
+            let (lhs, rhs) = setup();
+            let result = lhs.add(&rhs).unwrap();
+            assert_eq!(result.name, "Z");
+            assert_eq!(result.format, "RI");
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(1., 3.), Complex::new(5., -1.)]
+            );
+        }
+
+        #[test]
+        fn sub() {
+            let (lhs, rhs) = setup();
+            let result = lhs.sub(&rhs).unwrap();
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(1., 1.), Complex::new(1., -1.)]
+            );
+        }
+
+        #[test]
+        fn mul() {
+            let (lhs, rhs) = setup();
+            let result = lhs.mul(&rhs).unwrap();
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(-2., 1.), Complex::new(6., -2.)]
+            );
+        }
+
+        #[test]
+        fn div() {
+            let (lhs, rhs) = setup();
+            let result = lhs.div(&rhs).unwrap();
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(2., -1.), Complex::new(1.5, -0.5)]
+            );
+        }
+
+        #[test]
+        fn div_by_zero() {
+            let (lhs, _) = setup();
+            let zeros = DataArray {
+                name: String::from("zeros"),
+                format: String::from("RI"),
+                samples: vec![Complex::new(0., 0.); 2],
+            };
+            let result = lhs.div(&zeros).unwrap();
+            assert!(result.samples.iter().all(|c| !c.is_finite()));
+        }
+
+        #[test]
+        fn different_lengths() {
+            let (lhs, mut rhs) = setup();
+            rhs.add_sample(1., 1.);
+            match lhs.mul(&rhs) {
+                Err(Error::RecordError(RecordError::DataArrayLengthsDiffer(2, 3))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn scale() {
+            let (lhs, _) = setup();
+            let result = lhs.scale(Complex::new(0., 1.));
+            assert_eq!(result.name, "Z");
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(-2., 1.), Complex::new(1., 3.)]
+            );
+        }
+
+        #[test]
+        fn reciprocal() {
+            let mut array = DataArray::new("Z", "RI");
+            array.add_sample(0., 2.);
+            array.add_sample(4., 0.);
+            let result = array.reciprocal();
+            assert_eq!(result.format, "RI");
+            assert_complex_array_relative_eq!(
+                result.samples,
+                [Complex::new(0., -0.5), Complex::new(0.25, 0.)]
+            );
+        }
+
+        #[test]
+        fn reciprocal_of_zero() {
+            let mut array = DataArray::new("Z", "RI");
+            array.add_sample(0., 0.);
+            let result = array.reciprocal();
+            assert!(!result.samples[0].is_finite());
+        }
+    }
+
+    #[test]
+    fn test_to_f32() {
+        let mut array = DataArray::new("S", "RI");
+        array.add_sample(1.5, -2.25);
+        array.add_sample(0.1234567, 1e-3);
+        let result = array.to_f32();
+        assert_eq!(result.name, "S");
+        assert_eq!(result.format, "RI");
+        assert_eq!(result.samples.len(), 2);
+        approx::assert_relative_eq!(result.samples[0].re, 1.5_f32);
+        approx::assert_relative_eq!(result.samples[0].im, -2.25_f32);
+        approx::assert_relative_eq!(result.samples[1].re, 0.1234567_f32);
+        approx::assert_relative_eq!(result.samples[1].im, 1e-3_f32);
+    }
+}
+
+/// A data array stored in single precision
+///
+/// Records are always read and written in double precision. This type
+/// exists to reduce the in-memory footprint of large records downstream
+/// and is created explicitly with [`DataArray::to_f32`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct DataArrayF32 {
+    pub name: String,
+    pub format: String,
+    pub samples: Vec<Complex<f32>>,
+}
+
+/// Representation of a file
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Record {
+    pub header: Header,
+    pub data: Vec<DataArray>,
+}
+
+/// Error during writing
+#[derive(Error, Debug)]
+pub enum WriteError {
+    #[error("Version is not defined")]
+    NoVersion,
+    #[error("Name is not defined")]
+    NoName,
+    #[error("Data array {0} has no name")]
+    NoDataName(usize),
+    #[error("Data array {0} has no format")]
+    NoDataFormat(usize),
+    #[cfg(feature = "std")]
+    #[error("Writing error occured: {0}")]
+    WrittingError(#[source] std::io::Error),
+    #[error("Name {0:?} contains a control character")]
+    ControlCharacterInName(String),
+    #[error("Text {0:?} contains a control character")]
+    ControlCharacterInText(String),
+    #[error("`{0}` has unknown format `{1}`")]
+    UnknownFormat(String, String),
+    #[error("Data array `{0}` sample {1} cannot be written as `{2}` without loss")]
+    LossySample(String, usize, String),
+    #[error("Formatting error occured: {0}")]
+    FormattingError(#[from] fmt::Error),
+}
+type WriteResult<T> = core::result::Result<T, WriteError>;
+
+/// Options controlling how a record is written
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Write every float in exponential format with a mantissa in `[1, 10)`
+    ///
+    /// Data pairs are always written this way. When set, the independent
+    /// variable list is as well, so the output does not depend on the
+    /// magnitude of the values.
+    pub normalize_exponent: bool,
+    /// Unit of the angle written in `MAGANGLE` and `DBANGLE` pairs
+    pub angle_unit: AngleUnit,
+    /// Write known `VAR` and `DATA` formats in uppercase e.g. `ri` as `RI`
+    ///
+    /// Unknown formats are written unchanged.
+    pub normalize_formats: bool,
+    /// Pad the real part of data pairs so the imaginary parts line up
+    ///
+    /// The padding is written after the comma, so the output still reads
+    /// back as regular data pairs.
+    pub align_data: bool,
+    /// Keep names and comments from breaking the line structure
+    ///
+    /// Comments with line breaks are written as one `!` line per line.
+    /// Names of the record, independent variable, data arrays, constants and
+    /// devices containing control characters are rejected with
+    /// [`WriteError::ControlCharacterInName`]. So is any other text written
+    /// as is, that is the version, formats, constant values, device entries
+    /// and unknown lines, with [`WriteError::ControlCharacterInText`].
+    pub strict_text: bool,
+    /// Form of comment lines, `!` by default
+    pub comment_keyword_style: CommentStyle,
+    /// Reject `VAR` and `DATA` formats outside of the standard with
+    /// [`WriteError::UnknownFormat`]
+    ///
+    /// Known formats are `RI`, `MAG`, `DB`, `MAGANGLE`, `DBANGLE`, `PHASE`
+    /// and `COMPLEX`, in any case and with or without an annotation such as
+    /// `RI[50]`. A blank `VAR` format is allowed.
+    pub validate_formats: bool,
+    /// Form of the numbers in data pairs and `VAR_LIST` items
+    ///
+    /// Other than [`ExponentStyle::Rust`], this takes precedence over
+    /// `normalize_exponent`.
+    pub exponent_style: ExponentStyle,
+    /// Arrangement of the data blocks, one per array by default
+    pub block_layout: BlockLayout,
+    /// Form of the `VAR_LIST` items, independent of the data pairs
+    ///
+    /// By default the items follow `normalize_exponent` and
+    /// `exponent_style`.
+    pub var_list_style: VarListStyle,
+}
+
+/// Form of the numbers in a `VAR_LIST`
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum VarListStyle {
+    /// Shortest decimal form e.g. `1000000000`, unless `normalize_exponent`
+    /// or an `exponent_style` other than [`ExponentStyle::Rust`] is set
+    #[default]
+    Inherit,
+    /// Exponential, in the same `exponent_style` as data pairs e.g. `1E9`
+    Exponential,
+    /// Fixed point with `decimals` digits after the point e.g. `1000000000.000`
+    Fixed { decimals: usize },
+}
+
+impl VarListStyle {
+    /// Format `value` in this style, with `exponent_style` for exponents
+    fn format(self, value: f64, exponent_style: ExponentStyle) -> String {
+        match self {
+            VarListStyle::Inherit => format!("{}", value),
+            VarListStyle::Exponential => exponent_style.format(value),
+            VarListStyle::Fixed { decimals } => format!("{:.*}", decimals, value),
+        }
+    }
+}
+
+/// Arrangement of data pairs in `BEGIN`/`END` blocks
+///
+/// Only [`BlockLayout::PerArray`] is described by the standard and read
+/// back by this crate. Reading [`BlockLayout::Interleaved`] needs a reader
+/// that knows the number of arrays from the `DATA` keywords.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum BlockLayout {
+    /// One block per data array
+    #[default]
+    PerArray,
+    /// One block with the arrays interleaved point by point
+    ///
+    /// Each point holds one pair per array, in the order of the `DATA`
+    /// keywords. Every array must have the same number of samples.
+    Interleaved,
+}
+
+/// Form of numbers in exponential format
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ExponentStyle {
+    /// Shortest form that reads back exactly e.g. `1.23456E9`
+    #[default]
+    Rust,
+    /// Fixed digits and a signed three digit exponent e.g. `1.23456E+009`
+    ///
+    /// As written by some instruments. `mantissa_digits` is the number of
+    /// digits after the decimal point, so values may be rounded.
+    Engineering { mantissa_digits: usize },
+}
+
+impl ExponentStyle {
+    /// Format `value` in this style
+    fn format(self, value: f64) -> String {
+        match self {
+            ExponentStyle::Rust => format!("{:E}", value),
+            ExponentStyle::Engineering { mantissa_digits } => {
+                let formatted = format!("{:.*E}", mantissa_digits, value);
+                match formatted.split_once('E') {
+                    Some((mantissa, exponent)) => {
+                        let (sign, digits) = match exponent.strip_prefix('-') {
+                            Some(digits) => ('-', digits),
+                            None => ('+', exponent),
+                        };
+                        format!("{}E{}{:0>3}", mantissa, sign, digits)
+                    }
+                    // Infinite and NaN
+                    None => formatted,
+                }
+            }
+        }
+    }
+
+    /// Longest value written in this style
+    fn max_width(self) -> usize {
+        match self {
+            // `-2.2250738585072014E-308`
+            ExponentStyle::Rust => ALIGNED_REAL_WIDTH - 1,
+            // Sign, digit, point, digits then `E+000`
+            ExponentStyle::Engineering { mantissa_digits } => mantissa_digits + 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_exponent_style {
+    use super::*;
+
+    #[test]
+    fn rust() {
+        assert_eq!(ExponentStyle::Rust.format(1.23456e9), "1.23456E9");
+        assert_eq!(ExponentStyle::Rust.format(-1e-5), "-1E-5");
+    }
+
+    #[test]
+    fn engineering() {
+        let style = ExponentStyle::Engineering { mantissa_digits: 5 };
+        assert_eq!(style.format(1.23456e9), "1.23456E+009");
+        assert_eq!(style.format(-1.5e-3), "-1.50000E-003");
+        assert_eq!(style.format(0.), "0.00000E+000");
+        assert_eq!(style.format(1.5e300), "1.50000E+300");
+        assert_eq!(style.format(1.234567e9), "1.23457E+009");
+    }
+
+    #[test]
+    fn engineering_no_digits() {
+        let style = ExponentStyle::Engineering { mantissa_digits: 0 };
+        assert_eq!(style.format(2e9), "2E+009");
+    }
+
+    #[test]
+    fn engineering_non_finite() {
+        let style = ExponentStyle::Engineering { mantissa_digits: 5 };
+        assert_eq!(style.format(f64::INFINITY), "inf");
+        assert_eq!(style.format(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn max_width() {
+        let values = [
+            -2.2250738585072014E-308,
+            -f64::MAX,
+            -f64::MIN_POSITIVE,
+            -5e-324,
+            0.,
+        ];
+        for style in [
+            ExponentStyle::Rust,
+            ExponentStyle::Engineering { mantissa_digits: 0 },
+            ExponentStyle::Engineering {
+                mantissa_digits: 17,
+            },
+        ] {
+            for value in values.iter() {
+                assert!(style.format(*value).len() <= style.max_width());
+            }
+        }
+    }
+}
+
+/// Form of comment lines
+///
+/// Both forms are read. The standard only describes `!`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CommentStyle {
+    /// `!text`
+    #[default]
+    Exclamation,
+    /// `COMMENT text`
+    Keyword,
+}
+
+impl CommentStyle {
+    /// Text written before the comment
+    fn prefix(self) -> &'static str {
+        match self {
+            CommentStyle::Exclamation => "!",
+            CommentStyle::Keyword => "COMMENT ",
+        }
+    }
+}
+
+/// Lines of a comment, split on `\n`, `\r\n` and lone `\r`
+fn comment_lines(comment: &str) -> impl Iterator<Item = &str> {
+    comment
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .flat_map(|line| line.split('\r'))
+}
+
+#[cfg(test)]
+mod test_comment_lines {
+    use super::*;
+
+    #[test]
+    fn single_line() {
+        assert_eq!(
+            comment_lines("a comment").collect::<Vec<_>>(),
+            ["a comment"]
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        let lines: Vec<&str> = comment_lines("a\nb\r\nc\rd").collect();
+        assert_eq!(lines, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn empty_lines() {
+        let lines: Vec<&str> = comment_lines("a\n\nb\n").collect();
+        assert_eq!(lines, ["a", "", "b", ""]);
+    }
+}
+
+/// Width of `real,` in aligned data pairs
+///
+/// Fits the longest `{:E}` rendering of an `f64` e.g.
+/// `-2.2250738585072014E-308` and the comma.
+const ALIGNED_REAL_WIDTH: usize = 25;
+
+/// Significant digits that tell every `f64` apart
+///
+/// Asking for more only adds decimal noise to a formatted value.
+const MAX_SIGNIFICANT_DIGITS: usize = 17;
+
+/// Format in the standard, in any case and with or without an annotation
+///
+/// The [`DataFormat`] formats and their aliases, plus `PHASE`.
+fn is_known_format(format: &str) -> bool {
+    DataFormat::from(format) != DataFormat::Unknown
+        || split_format_annotation(format)
+            .0
+            .eq_ignore_ascii_case("PHASE")
+}
+
+#[cfg(test)]
+mod test_is_known_format {
+    use super::*;
+
+    #[test]
+    fn known() {
+        let formats = [
+            "RI",
+            "MAG",
+            "DB",
+            "MAGANGLE",
+            "DBANGLE",
+            "PHASE",
+            "COMPLEX",
+            "complex64",
+            "ri",
+        ];
+        for format in formats.iter() {
+            assert!(is_known_format(format), "{}", format);
+        }
+    }
+
+    #[test]
+    fn annotated() {
+        assert!(is_known_format("RI[50]"));
+        assert!(is_known_format("PHASE[deg]"));
+    }
+
+    #[test]
+    fn unknown() {
+        for format in ["BOGUS", "", "RI2", "[50]", "PHASE]"].iter() {
+            assert!(!is_known_format(format), "{}", format);
+        }
+    }
+}
+
+/// Uppercase a known format, leaving others untouched
+fn normalize_format(format: &str) -> String {
+    match is_known_format(format) {
+        true => format.to_ascii_uppercase(),
+        false => String::from(format),
+    }
+}
+
+#[cfg(test)]
+mod test_normalize_format {
+    use super::*;
+
+    #[test]
+    fn known() {
+        for format in ["ri", "Mag", "db", "magangle", "DBAngle", "phase", "complex"].iter() {
+            assert_eq!(normalize_format(format), format.to_ascii_uppercase());
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(normalize_format("Format A"), "Format A");
+        assert_eq!(normalize_format("ri2"), "ri2");
+        assert_eq!(normalize_format(""), "");
+    }
+}
+
+#[cfg(test)]
+mod test_write_options {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let expected = WriteOptions {
+            normalize_exponent: false,
+            angle_unit: AngleUnit::Degrees,
+            normalize_formats: false,
+            align_data: false,
+            strict_text: false,
+            comment_keyword_style: CommentStyle::Exclamation,
+            validate_formats: false,
+            exponent_style: ExponentStyle::Rust,
+            block_layout: BlockLayout::PerArray,
+            var_list_style: VarListStyle::Inherit,
+        };
+        assert_eq!(WriteOptions::default(), expected);
+    }
+}
+
+#[cfg(test)]
+mod test_write_result {
+    use super::*;
+
+    #[test]
+    fn source() {
+        use std::error::Error;
+        let error = WriteError::WrittingError(std::io::Error::from(std::io::ErrorKind::Other));
+        assert!(error.source().is_some());
+        assert!(WriteError::NoVersion.source().is_none());
+    }
+
+    #[test]
+    fn from_fmt_error() {
+        match WriteError::from(fmt::Error) {
+            WriteError::FormattingError(fmt::Error) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    mod test_display {
+        use super::*;
+
+        #[test]
+        fn unknown_format() {
+            let error = WriteError::UnknownFormat(String::from("S[1,1]"), String::from("BOGUS"));
+            assert_eq!(format!("{}", error), "`S[1,1]` has unknown format `BOGUS`");
+        }
+
+        #[test]
+        fn lossy_sample() {
+            let error = WriteError::LossySample(String::from("S[1,1]"), 2, String::from("DB"));
+            assert_eq!(
+                format!("{}", error),
+                "Data array `S[1,1]` sample 2 cannot be written as `DB` without loss"
+            );
+        }
+
+        #[test]
+        fn control_character_in_text() {
+            let error = WriteError::ControlCharacterInText(String::from("1\n2"));
+            assert_eq!(
+                format!("{}", error),
+                "Text \"1\\n2\" contains a control character"
+            );
+        }
+
+        #[test]
+        fn control_character_in_name() {
+            let error = WriteError::ControlCharacterInName(String::from("A\u{7}"));
+            assert_eq!(
+                format!("{}", error),
+                "Name \"A\\u{7}\" contains a control character"
+            );
+        }
+
+        #[test]
+        fn no_version() {
+            let error = WriteError::NoVersion;
+            assert_eq!(format!("{}", error), "Version is not defined");
+        }
+
+        #[test]
+        fn no_name() {
+            let error = WriteError::NoName;
+            assert_eq!(format!("{}", error), "Name is not defined");
+        }
+
+        #[test]
+        fn no_data_name() {
+            let error = WriteError::NoDataName(1);
+            assert_eq!(format!("{}", error), "Data array 1 has no name");
+        }
+
+        #[test]
+        fn no_data_name_second() {
+            let error = WriteError::NoDataName(2);
+            assert_eq!(format!("{}", error), "Data array 2 has no name");
+        }
+
+        #[test]
+        fn no_data_format() {
+            let error = WriteError::NoDataFormat(1);
+            assert_eq!(format!("{}", error), "Data array 1 has no format");
+        }
+
+        #[test]
+        fn no_data_format_second() {
+            let error = WriteError::NoDataFormat(2);
+            assert_eq!(format!("{}", error), "Data array 2 has no format");
+        }
+
+        #[test]
+        fn writting_error() {
+            let error = WriteError::WrittingError(std::io::ErrorKind::NotFound.into());
+            assert_eq!(
+                format!("{}", error),
+                "Writing error occured: entity not found"
+            );
+        }
+    }
+}
+
+/// Read a record with the default reader options
+///
+/// See [`Record::from_str_with_options`].
+impl FromStr for Record {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Record::from_str_with_options(s, &ReaderOptions::default())
+    }
+}
+
+impl<'a> IntoIterator for &'a Record {
+    type Item = &'a DataArray;
+    type IntoIter = core::slice::Iter<'a, DataArray>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+/// What to do with points outside of the range when interpolating
+///
+/// See [`Record::interpolate_to_with_extrapolation`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Extrapolation {
+    /// Fail with [`RecordError::OutOfInterpolationRange`]
+    #[default]
+    Error,
+    /// Use the value at the closest end of the range
+    Clamp,
+}
+
+/// Error while modifying a record in memory
+#[derive(Error, Debug)]
+pub enum RecordError {
+    #[error("Expected {0} samples, one per data array, found {1}")]
+    WrongNumberOfSamples(usize, usize),
+    #[error("Data array `{0}` has {2} samples but the independent variable has {1}")]
+    ArrayLengthMismatch(String, usize, usize),
+    #[error("Data arrays have different lengths ({0} != {1})")]
+    DataArrayLengthsDiffer(usize, usize),
+    #[error("Independent variable is not strictly monotonic at point {0}")]
+    NotMonotonic(usize),
+    #[error("Cannot interpolate at {0}, outside of the independent variable range")]
+    OutOfInterpolationRange(f64),
+    #[error("Cannot extend the independent variable, data array `{0}` already has {1} samples")]
+    VarBehindData(String, usize),
+}
+type RecordResult<T> = core::result::Result<T, RecordError>;
+
+#[cfg(test)]
+mod test_record_error {
+    use super::*;
+
+    mod test_display {
+        use super::*;
+
+        #[test]
+        fn var_behind_data() {
+            let error = RecordError::VarBehindData(String::from("S"), 3);
+            assert_eq!(
+                format!("{}", error),
+                "Cannot extend the independent variable, data array `S` already has 3 samples"
+            );
+        }
+
+        #[test]
+        fn wrong_number_of_samples() {
+            let error = RecordError::WrongNumberOfSamples(3, 4);
+            assert_eq!(
+                format!("{}", error),
+                "Expected 3 samples, one per data array, found 4"
+            );
+        }
+
+        #[test]
+        fn array_length_mismatch() {
+            let error = RecordError::ArrayLengthMismatch(String::from("S[1,1]"), 3, 4);
+            assert_eq!(
+                format!("{}", error),
+                "Data array `S[1,1]` has 4 samples but the independent variable has 3"
+            );
+        }
+
+        #[test]
+        fn data_array_lengths_differ() {
+            let error = RecordError::DataArrayLengthsDiffer(3, 4);
+            assert_eq!(
+                format!("{}", error),
+                "Data arrays have different lengths (3 != 4)"
+            );
+        }
+
+        #[test]
+        fn not_monotonic() {
+            let error = RecordError::NotMonotonic(3);
+            assert_eq!(
+                format!("{}", error),
+                "Independent variable is not strictly monotonic at point 3"
+            );
+        }
+
+        #[test]
+        fn out_of_interpolation_range() {
+            let error = RecordError::OutOfInterpolationRange(1.5);
+            assert_eq!(
+                format!("{}", error),
+                "Cannot interpolate at 1.5, outside of the independent variable range"
+            );
+        }
+    }
+}
+
+impl Record {
+    pub fn new(version: &str, name: &str) -> Record {
+        Record {
+            header: Header::new(version, name),
+            data: vec![],
+        }
+    }
+
+    /// Build a record from the independent variable and data array columns
+    ///
+    /// Each array is given as `(name, format, samples)` and must have one
+    /// sample per independent variable point.
+    ///
+    /// Example usage:
+    /// ```
+    /// use citi::Record;
+    /// use num_complex::Complex;
+    ///
+    /// let record = Record::from_columns(
+    ///     "A.01.00",
+    ///     "MEMORY",
+    ///     "FREQ",
+    ///     "MAG",
+    ///     vec![1e9, 2e9],
+    ///     vec![(
+    ///         String::from("S[1,1]"),
+    ///         String::from("RI"),
+    ///         vec![Complex::new(1., 2.), Complex::new(3., 4.)],
+    ///     )],
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn from_columns(
+        version: &str,
+        name: &str,
+        var_name: &str,
+        var_format: &str,
+        var: Vec<f64>,
+        arrays: Vec<(String, String, Vec<Complex<f64>>)>,
+    ) -> Result<Record> {
+        let mut record = Record::new(version, name);
+        record.header.independent_variable = Var {
+            name: String::from(var_name),
+            format: String::from(var_format),
+            data: var,
+        };
+
+        let length = record.header.independent_variable.data.len();
+        for (name, format, samples) in arrays {
+            if samples.len() != length {
+                return Err(RecordError::ArrayLengthMismatch(name, length, samples.len()).into());
+            }
+            record.data.push(DataArray {
+                name,
+                format,
+                samples,
+            });
+        }
+        Ok(record)
+    }
+
+    /// Record with room for `num_points` in the independent variable and
+    /// in each of `num_arrays` new data arrays
+    ///
+    /// The arrays are created with empty names and formats, as placeholders
+    /// to set before writing.
+    pub fn with_capacity(num_points: usize, num_arrays: usize) -> Record {
+        let mut record = Record {
+            header: Header::default(),
+            data: (0..num_arrays)
+                .map(|_| DataArray::with_capacity("", "", num_points))
+                .collect(),
+        };
+        record.header.independent_variable.data.reserve(num_points);
+        record
+    }
+
+    /// Record with `array` appended to the data arrays
+    ///
+    /// Example usage:
+    /// ```
+    /// use citi::{DataArray, Record};
+    ///
+    /// let record = Record::new("A.01.00", "MEMORY")
+    ///     .with_comment("Calibrated")
+    ///     .with_data_array(DataArray::new("S[1,1]", "RI"))
+    ///     .with_data_array(DataArray::new("S[2,1]", "RI"));
+    /// assert_eq!(record.data.len(), 2);
+    /// ```
+    pub fn with_data_array(mut self, array: DataArray) -> Self {
+        self.data.push(array);
+        self
+    }
+
+    /// Record with `comment` appended to the comments
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.header.comments.push(String::from(comment));
+        self
+    }
+
+    /// Record with a constant appended
+    pub fn with_constant(mut self, name: &str, value: &str) -> Self {
+        self.header.constants.push(Constant::new(name, value));
+        self
+    }
+
+    /// Record with a device entry added, as with [`Header::add_device`]
+    pub fn with_device(mut self, device_name: &str, value: &str) -> Self {
+        self.header.add_device(device_name, value);
+        self
+    }
+
+    /// Read record
+    ///
+    /// Uses [`ReaderOptions::default`], which rejects an independent
+    /// variable or data array of more than 2^24 points with
+    /// [`ReadError::TooManyPoints`]. Set [`ReaderOptions::max_points`] with
+    /// [`Record::from_reader_with_options`] to read larger records.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    /// use std::fs::File;
+    ///
+    /// let mut file = File::open("file.cti").unwrap();
+    /// let record = Record::from_reader(&mut file);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Record> {
+        Record::from_reader_with_options(reader, &ReaderOptions::default())
+    }
+
+    /// Read record with non-default reader options
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::{ReaderOptions, Record};
+    /// use std::fs::File;
+    ///
+    /// let options = ReaderOptions {
+    ///     allow_unterminated_final_block: true,
+    ///     ..ReaderOptions::default()
+    /// };
+    /// let mut file = File::open("file.cti").unwrap();
+    /// let record = Record::from_reader_with_options(&mut file, &options);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_reader_with_options<R: std::io::Read>(
+        reader: &mut R,
+        options: &ReaderOptions,
+    ) -> Result<Record> {
+        Record::from_buf_reader_with_options(std::io::BufReader::new(reader), options)
+    }
+
+    /// Read record from a reader that is already buffered
+    ///
+    /// Unlike [`Record::from_reader`], no extra buffer is added.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    ///
+    /// let reader = BufReader::new(File::open("file.cti").unwrap());
+    /// let record = Record::from_buf_reader(reader);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_buf_reader<B: BufRead>(reader: B) -> Result<Record> {
+        Record::from_buf_reader_with_options(reader, &ReaderOptions::default())
+    }
+
+    /// Read record from standard input
+    ///
+    /// Standard input is locked for the whole read, and its own buffer is
+    /// used. Suits command line tools reading a piped record.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    ///
+    /// let record = Record::read_stdin().unwrap();
+    /// record.write_stdout().unwrap();
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_stdin() -> Result<Record> {
+        Record::from_buf_reader(std::io::stdin().lock())
+    }
+
+    /// Read record from a reader that is already buffered with non-default
+    /// reader options
+    #[cfg(feature = "std")]
+    pub fn from_buf_reader_with_options<B: BufRead>(
+        reader: B,
+        options: &ReaderOptions,
+    ) -> Result<Record> {
+        let mut parser = ParserState::new(*options);
+        for_each_line(reader, options, |line| parser.feed_line(line))?;
+        parser.finish()
+    }
+
+    /// Read the header, stopping at the first `BEGIN`
+    ///
+    /// The data is neither read nor parsed, so this is quick even for
+    /// large files. The `BEGIN` line is consumed and `reader` is left at
+    /// the first data pair. The version, name and independent variable
+    /// must be defined, but nothing else is checked.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    ///
+    /// let mut reader = BufReader::new(File::open("file.cti").unwrap());
+    /// let header = Record::read_header_only(&mut reader).unwrap();
+    /// println!("{}", header.name);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_header_only<R: BufRead>(reader: &mut R) -> Result<Header> {
+        let options = ReaderOptions::default();
+        let mut parser = ParserState::new(options);
+        let mut i = 0;
+        while let Some(line) = read_line_limited(reader, i, None, false)? {
+            if let Ok(Keyword::Begin) = Keyword::parse_line(&line, &options) {
+                break;
+            }
+            parser.feed_line(&line)?;
+            i += 1;
+        }
+        parser.finish_header()
+    }
+
+    /// Read record, skipping lines that cannot be parsed and collecting
+    /// every error
+    ///
+    /// Parsing errors ([`ReadError::LineError`]) are recorded and the line
+    /// is skipped. Any other error ends the read, and is returned last
+    /// with no record. Skipping a line may cause further errors, such as
+    /// a data block that is then too short.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::{ReaderOptions, Record};
+    /// use std::fs::File;
+    ///
+    /// let mut file = File::open("file.cti").unwrap();
+    /// let (record, errors) = Record::read_collecting_errors(&mut file, &ReaderOptions::default());
+    /// for error in errors.iter() {
+    ///     println!("{}", error);
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_collecting_errors<R: std::io::Read>(
+        reader: &mut R,
+        options: &ReaderOptions,
+    ) -> (Option<Record>, Vec<Error>) {
+        let mut parser = ParserState::new(*options);
+        let mut errors = vec![];
+
+        let result = for_each_line(
+            std::io::BufReader::new(reader),
+            options,
+            |line| match parser.feed_line(line) {
+                Err(e @ Error::ReadError(ReadError::LineError(_, _))) => {
+                    errors.push(e);
+                    Ok(())
+                }
+                result => result,
+            },
+        );
+        if let Err(e) = result {
+            errors.push(e);
+            return (None, errors);
+        }
+
+        match parser.finish() {
+            Ok(record) => (Some(record), errors),
+            Err(e) => {
+                errors.push(e);
+                (None, errors)
+            }
+        }
+    }
+
+    /// Read record from a file mapped into memory
+    ///
+    /// The mapped bytes are parsed in place rather than copied through a
+    /// read buffer, which helps when scanning large files repeatedly.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other
+    /// process, until the call returns. The map is only read during the
+    /// call, but the operating system does not prevent such changes, and
+    /// they are undefined behaviour, as described for
+    /// [`memmap2::Mmap::map`]. Use [`Record::from_reader`] on a
+    /// [`std::fs::File`] when this cannot be guaranteed.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    ///
+    /// // Safety: nothing else writes to `file.cti`
+    /// let record = unsafe { Record::read_mmap("file.cti") };
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub unsafe fn read_mmap<P: AsRef<std::path::Path>>(path: P) -> Result<Record> {
+        let file = std::fs::File::open(path).map_err(ReadError::ReadingError)?;
+        // Safety: the caller guarantees the file is not changed while mapped
+        let mmap = memmap2::Mmap::map(&file).map_err(ReadError::ReadingError)?;
+        Record::from_buf_reader(&mmap[..])
+    }
+
+    /// Read record held in a string
+    ///
+    /// Needs no `std` feature. Lines are split and checked against
+    /// [`ReaderOptions::max_line_bytes`] as with
+    /// [`Record::from_reader_with_options`].
+    ///
+    /// Example usage:
+    /// ```
+    /// use citi::{ReaderOptions, Record};
+    ///
+    /// let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1,2\nEND\n";
+    /// let record = Record::from_str_with_options(contents, &ReaderOptions::default()).unwrap();
+    /// assert_eq!(record.data[0].samples.len(), 1);
+    /// ```
+    pub fn from_str_with_options(contents: &str, options: &ReaderOptions) -> Result<Record> {
+        let mut parser = ParserState::new(*options);
+        for (i, line) in contents.lines().enumerate() {
+            if let Some(limit) = options.max_line_bytes.filter(|&limit| line.len() > limit) {
+                return Err(ReadError::LineTooLong(i, limit).into());
+            }
+            parser.feed_line(line)?;
+        }
+        parser.finish()
+    }
+
+    /// Build a record from keywords that are already parsed
+    ///
+    /// The keywords go through the same reader and validation as
+    /// [`Record::from_reader`], so they must appear in file order. This
+    /// suits keywords built in code, or filtered from another record.
+    ///
+    /// Example usage:
+    /// ```
+    /// use citi::{Keyword, Record};
+    ///
+    /// let keywords = vec![
+    ///     Keyword::CitiFile { version: String::from("A.01.00") },
+    ///     Keyword::Name(String::from("MEMORY")),
+    ///     Keyword::Var { name: String::from("FREQ"), format: String::from("MAG"), length: 1 },
+    ///     Keyword::Data { name: String::from("S"), format: String::from("RI") },
+    ///     Keyword::Begin,
+    ///     Keyword::DataPair { real: 1., imag: 2. },
+    ///     Keyword::End,
+    /// ];
+    /// let record = Record::from_keywords(keywords).unwrap();
+    /// assert_eq!(record.data[0].samples.len(), 1);
+    /// ```
+    pub fn from_keywords<I: IntoIterator<Item = Keyword>>(keywords: I) -> Result<Record> {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        for keyword in keywords {
+            parser.feed_keyword(keyword)?;
+        }
+        parser.finish()
+    }
+
+    /// Panic unless the record can be written and read back
+    ///
+    /// Meant for tests. The record is written to keywords and read with
+    /// [`Record::from_keywords`], so it is held to the same checks as a
+    /// file, and the panic message holds the first error found.
+    pub fn assert_valid(&self) {
+        let result = self
+            .get_keywords(AngleUnit::default(), BlockLayout::PerArray)
+            .map_err(Error::from)
+            .and_then(Record::from_keywords);
+        if let Err(error) = result {
+            panic!("Invalid record: {}", error);
+        }
+    }
+
+    /// Every check made when reading, with all failures
+    ///
+    /// Reading stops at the first failure. Here errors are returned in the
+    /// same order the reader checks: name, version, independent variable,
+    /// data, then one [`ReadError::VarAndDataDifferentLengths`] per data
+    /// array whose length differs.
+    pub fn validate_all(&self) -> Vec<ReadError> {
+        let mut errors = vec![];
+        if self.header.name.is_empty() {
+            errors.push(ReadError::NoName);
+        }
+        if self.header.version.is_empty() {
+            errors.push(ReadError::NoVersion);
+        }
+        if self.header.independent_variable.name.is_empty() {
+            errors.push(ReadError::NoIndependentVariable);
+        }
+        if self.data.is_empty() {
+            errors.push(ReadError::NoData);
+        }
+
+        // Zero length var adopts the length of the first array
+        let mut n = self.header.independent_variable.data.len();
+        for (i, array) in self.data.iter().enumerate() {
+            let k = array.samples.len();
+            if n == 0 {
+                n = k
+            } else if n != k {
+                errors.push(ReadError::VarAndDataDifferentLengths(n, k, i));
+            }
+        }
+        errors
+    }
+
+    /// Read record, reporting the number of bytes read to `progress`
+    ///
+    /// `progress` is called each time another percent of `total_bytes` is
+    /// read, or after every read from `reader` if the total is not known,
+    /// and once more at the end of the input.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    /// use std::fs::File;
+    ///
+    /// let mut file = File::open("file.cti").unwrap();
+    /// let total_bytes = file.metadata().unwrap().len();
+    /// let record = Record::from_reader_with_progress(&mut file, Some(total_bytes), |bytes| {
+    ///     println!("{:.0}%", 100. * bytes as f64 / total_bytes as f64)
+    /// });
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_reader_with_progress<R: std::io::Read, F: FnMut(u64)>(
+        reader: &mut R,
+        total_bytes: Option<u64>,
+        progress: F,
+    ) -> Result<Record> {
+        let mut reader = ProgressReader {
+            inner: reader,
+            step: total_bytes.map_or(1, |total| (total / 100).max(1)),
+            read: 0,
+            reported: 0,
+            progress,
+        };
+        Record::from_reader(&mut reader)
+    }
+
+    /// Write record
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::Record;
+    /// use std::fs::File;
+    ///
+    /// let record = Record::default();
+    /// let mut file = File::create("file.cti").unwrap();
+    /// record.to_writer(&mut file);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn to_writer<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        self.to_writer_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Write record to standard output
+    ///
+    /// Standard output is locked for the whole write and buffered, as it
+    /// is otherwise flushed on every line.
+    #[cfg(feature = "std")]
+    pub fn write_stdout(&self) -> Result<()> {
+        self.write_buffered(std::io::stdout().lock())
+    }
+
+    /// Write record through a buffer, flushing at the end
+    #[cfg(feature = "std")]
+    fn write_buffered<W: std::io::Write>(&self, writer: W) -> Result<()> {
+        let mut writer = std::io::BufWriter::new(writer);
+        self.to_writer(&mut writer)?;
+        std::io::Write::flush(&mut writer).map_err(WriteError::WrittingError)?;
+        Ok(())
+    }
+
+    /// Write record with non-default writer options
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::{Record, WriteOptions};
+    /// use std::fs::File;
+    ///
+    /// let options = WriteOptions {
+    ///     normalize_exponent: true,
+    ///     ..WriteOptions::default()
+    /// };
+    /// let record = Record::default();
+    /// let mut file = File::create("file.cti").unwrap();
+    /// record.to_writer_with_options(&mut file, &options);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn to_writer_with_options<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        self.to_writer_with_progress(writer, options, |_| ())
+    }
+
+    /// Write record, reporting the number of keywords written to `progress`
+    ///
+    /// `progress` is called every 1024 keywords and once at the end.
+    #[cfg(feature = "std")]
+    pub fn to_writer_with_progress<W: std::io::Write, F: FnMut(u64)>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+        mut progress: F,
+    ) -> Result<()> {
+        self.check_block_layout(options.block_layout)?;
+        let keywords = self.get_keywords(options.angle_unit, options.block_layout)?;
+
+        for (i, keyword) in keywords.iter().enumerate() {
+            Record::write_keyword(writer, keyword, options)?;
+            if (i + 1) % 1024 == 0 {
+                progress((i + 1) as u64);
+            }
+        }
+        if keywords.len() % 1024 != 0 {
+            progress(keywords.len() as u64);
+        }
+
+        Ok(())
+    }
+
+    /// Write record to a string
+    ///
+    /// Needs no `std` feature. The output is identical to
+    /// [`Record::to_writer_with_options`].
+    ///
+    /// Example usage:
+    /// ```
+    /// use citi::{Record, WriteOptions};
+    ///
+    /// let record = Record::new("A.01.00", "MEMORY");
+    /// let written = record.to_string_with_options(&WriteOptions::default()).unwrap();
+    /// assert!(written.starts_with("CITIFILE A.01.00\nNAME MEMORY\n"));
+    /// ```
+    pub fn to_string_with_options(&self, options: &WriteOptions) -> Result<String> {
+        self.check_block_layout(options.block_layout)?;
+        let keywords = self.get_keywords(options.angle_unit, options.block_layout)?;
+
+        let mut written = String::new();
+        for keyword in keywords.iter() {
+            written.push_str(&Record::format_keyword(keyword, options)?);
+        }
+        Ok(written)
+    }
+
+    /// Write record without first building every keyword in memory
+    ///
+    /// The output is identical to [`Record::to_writer_with_options`], but
+    /// the independent variable and data pairs are written one at a time,
+    /// so memory use does not grow with the size of the record. The header
+    /// is checked before anything is written.
+    ///
+    /// Example usage:
+    /// ```no_run
+    /// use citi::{Record, WriteOptions};
+    /// use std::fs::File;
+    /// use std::io::BufWriter;
+    ///
+    /// let record = Record::default();
+    /// let mut writer = BufWriter::new(File::create("file.cti").unwrap());
+    /// record.write_streaming(&mut writer, &WriteOptions::default());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_streaming<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        self.check_block_layout(options.block_layout)?;
+        let mut leading = self.get_version_keywords()?;
+        leading.append(&mut self.get_name_keywords()?);
+        leading.append(&mut self.get_independent_variable_keywords()?);
+
+        let mut trailing = self.get_constants_keywords()?;
+        trailing.append(&mut self.get_comments_keywords()?);
+        trailing.append(&mut self.get_devices_keywords()?);
+        trailing.append(&mut self.get_unknown_keywords()?);
+        trailing.append(&mut self.get_data_defines_keywords()?);
+
+        for keyword in leading.iter() {
+            Record::write_keyword(writer, keyword, options)?;
+        }
+
+        // Same layout as get_var_keywords
+        if self.seg_list_matches() {
+            Record::write_keyword(writer, &Keyword::SegListBegin, options)?;
+            for keyword in self.header.seg_items.iter() {
+                Record::write_keyword(writer, keyword, options)?;
+            }
+            Record::write_keyword(writer, &Keyword::SegListEnd, options)?;
+        } else if !self.header.independent_variable.data.is_empty() {
+            Record::write_keyword(writer, &Keyword::VarListBegin, options)?;
+            for &v in self.header.independent_variable.data.iter() {
+                Record::write_keyword(writer, &Keyword::VarListItem(v), options)?;
+            }
+            Record::write_keyword(writer, &Keyword::VarListEnd, options)?;
+        }
+
+        for keyword in trailing.iter() {
+            Record::write_keyword(writer, keyword, options)?;
+        }
+
+        // Same layout as get_interleaved_data_keywords
+        if options.block_layout == BlockLayout::Interleaved {
+            if self.data.is_empty() {
+                return Ok(());
+            }
+            let formats: Vec<DataFormat> = self
+                .data
+                .iter()
+                .map(|array| DataFormat::from(array.format.as_str()))
+                .collect();
+            let points = self.data.iter().map(|a| a.samples.len()).min().unwrap_or(0);
+            Record::write_keyword(writer, &Keyword::Begin, options)?;
+            for i in 0..points {
+                for (array, format) in self.data.iter().zip(formats.iter()) {
+                    let (real, imag) = format.from_complex(&array.samples[i], options.angle_unit);
+                    Record::write_keyword(writer, &Keyword::DataPair { real, imag }, options)?;
+                }
+            }
+            Record::write_keyword(writer, &Keyword::End, options)?;
+            return Ok(());
+        }
+
+        // Same layout as get_data_keywords
+        for array in self.data.iter() {
+            let format = DataFormat::from(array.format.as_str());
+            Record::write_keyword(writer, &Keyword::Begin, options)?;
+            for sample in array.samples.iter() {
+                let (real, imag) = format.from_complex(sample, options.angle_unit);
+                Record::write_keyword(writer, &Keyword::DataPair { real, imag }, options)?;
+            }
+            Record::write_keyword(writer, &Keyword::End, options)?;
+        }
+
+        Ok(())
+    }
+
+    /// Upper estimate of the number of bytes written with `options`
+    ///
+    /// Header lines are measured exactly. Each data pair is counted at the
+    /// longest line it could produce, so the estimate is suited to
+    /// reserving a buffer before writing.
+    pub fn estimated_write_size(&self, options: &WriteOptions) -> usize {
+        let header = [
+            self.get_version_keywords(),
+            self.get_name_keywords(),
+            self.get_independent_variable_keywords(),
+            self.get_var_keywords(),
+            self.get_constants_keywords(),
+            self.get_comments_keywords(),
+            self.get_devices_keywords(),
+            self.get_unknown_keywords(),
+            self.get_data_defines_keywords(),
+        ];
+
+        // A keyword that cannot be written is not counted
+        let lines: usize = header
+            .iter()
+            .flatten()
+            .flatten()
+            .filter_map(|keyword| Record::format_keyword(keyword, options).ok())
+            .map(|line| line.len())
+            .sum();
+
+        // `BEGIN` and `END` lines, then `real,imag` at full width
+        let width = options.exponent_style.max_width();
+        let pair = ALIGNED_REAL_WIDTH.max(width + 1) + width + 1;
+        let data: usize = self
+            .data
+            .iter()
+            .map(|array| "BEGIN\nEND\n".len() + array.samples.len() * pair)
+            .sum();
+
+        lines + data
+    }
+
+    /// Number of samples across every data array
+    pub fn total_samples(&self) -> usize {
+        self.data.iter().map(|array| array.samples.len()).sum()
+    }
+
+    /// Rough estimate of the heap memory held by the record
+    ///
+    /// Counts 16 bytes per sample, 8 bytes per independent variable point
+    /// and the length of every string, ignoring spare capacity and
+    /// allocator overhead. Large values suggest reading and writing with
+    /// the streaming functions instead.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let header = &self.header;
+        let strings = header.version.len()
+            + header.name.len()
+            + header.comments.iter().map(String::len).sum::<usize>()
+            + header
+                .devices
+                .iter()
+                .map(|d| d.name.len() + d.entries.iter().map(String::len).sum::<usize>())
+                .sum::<usize>()
+            + header.independent_variable.name.len()
+            + header.independent_variable.format.len()
+            + header
+                .constants
+                .iter()
+                .map(|c| c.name.len() + c.value.len())
+                .sum::<usize>()
+            + header.unknown_lines.iter().map(String::len).sum::<usize>()
+            + self
+                .data
+                .iter()
+                .map(|a| a.name.len() + a.format.len())
+                .sum::<usize>();
+
+        self.total_samples() * core::mem::size_of::<Complex<f64>>()
+            + header.independent_variable.data.len() * core::mem::size_of::<f64>()
+            + strings
+    }
+
+    /// Write `keyword` as a line
+    ///
+    /// The line is formatted in full first, so a formatting error writes
+    /// nothing.
+    #[cfg(feature = "std")]
+    fn write_keyword<W: std::io::Write>(
+        writer: &mut W,
+        keyword: &Keyword,
+        options: &WriteOptions,
+    ) -> WriteResult<()> {
+        let formatted = Record::format_keyword(keyword, options)?;
+        writer
+            .write_all(formatted.as_bytes())
+            .map_err(WriteError::WrittingError)
+    }
+
+    /// Line written for `keyword`, including the line ending
+    fn format_keyword(keyword: &Keyword, options: &WriteOptions) -> WriteResult<String> {
+        use core::fmt::Write as _;
+
+        if options.strict_text {
+            if let Keyword::Name(name)
+            | Keyword::Var { name, .. }
+            | Keyword::Data { name, .. }
+            | Keyword::Constant { name, .. }
+            | Keyword::Device { name, .. } = keyword
+            {
+                if name.contains(char::is_control) {
+                    return Err(WriteError::ControlCharacterInName(name.clone()));
+                }
+            }
+            if let Keyword::CitiFile { version: text }
+            | Keyword::Var { format: text, .. }
+            | Keyword::Data { format: text, .. }
+            | Keyword::Constant { value: text, .. }
+            | Keyword::Device { value: text, .. }
+            | Keyword::Unknown(text) = keyword
+            {
+                if text.contains(char::is_control) {
+                    return Err(WriteError::ControlCharacterInText(text.clone()));
+                }
+            }
+        }
+        if options.validate_formats {
+            if let Keyword::Var { name, format, .. } | Keyword::Data { name, format } = keyword {
+                // The independent variable format is optional
+                let optional = matches!(keyword, Keyword::Var { .. }) && format.is_empty();
+                if !optional && !is_known_format(format) {
+                    return Err(WriteError::UnknownFormat(name.clone(), format.clone()));
+                }
+            }
+        }
+
+        let mut formatted = String::new();
+        match keyword {
+            Keyword::Comment(comment) if options.strict_text => {
+                let prefix = options.comment_keyword_style.prefix();
+                comment_lines(comment)
+                    .try_for_each(|line| writeln!(formatted, "{}{}", prefix, line))
+            }
+            Keyword::Comment(comment) => {
+                let prefix = options.comment_keyword_style.prefix();
+                writeln!(formatted, "{}{}", prefix, comment)
+            }
+            Keyword::VarListItem(n) if options.var_list_style != VarListStyle::Inherit => {
+                let item = options.var_list_style.format(*n, options.exponent_style);
+                writeln!(formatted, "{}", item)
+            }
+            Keyword::VarListItem(n) if options.exponent_style != ExponentStyle::Rust => {
+                writeln!(formatted, "{}", options.exponent_style.format(*n))
+            }
+            Keyword::VarListItem(n) if options.normalize_exponent => writeln!(formatted, "{:E}", n),
+            Keyword::Var {
+                name,
+                format,
+                length,
+            } if options.normalize_formats => {
+                let keyword = Keyword::Var {
+                    name: name.clone(),
+                    format: normalize_format(format),
+                    length: *length,
+                };
+                writeln!(formatted, "{}", keyword)
+            }
+            Keyword::Data { name, format } if options.normalize_formats => {
+                let keyword = Keyword::Data {
+                    name: name.clone(),
+                    format: normalize_format(format),
+                };
+                writeln!(formatted, "{}", keyword)
+            }
+            Keyword::DataPair { real, imag } if options.align_data => {
+                let real = format!("{},", options.exponent_style.format(*real));
+                writeln!(
+                    formatted,
+                    "{:<width$}{}",
+                    real,
+                    options.exponent_style.format(*imag),
+                    width = ALIGNED_REAL_WIDTH
+                )
+            }
+            Keyword::DataPair { real, imag } if options.exponent_style != ExponentStyle::Rust => {
+                let style = options.exponent_style;
+                writeln!(formatted, "{},{}", style.format(*real), style.format(*imag))
+            }
+            // Exponential like the data, since the reader needs more than a
+            // single digit, e.g. `0E0` rather than `0`
+            Keyword::SegItem {
+                first,
+                last,
+                number,
+                spacing,
+            } => {
+                let style = options.exponent_style;
+                let (first, last) = (style.format(*first), style.format(*last));
+                match spacing {
+                    Some(spacing) => {
+                        writeln!(formatted, "SEG {} {} {} {}", spacing, first, last, number)
+                    }
+                    None => writeln!(formatted, "SEG {} {} {}", first, last, number),
+                }
+            }
+            _ => writeln!(formatted, "{}", keyword),
+        }?;
+        Ok(formatted)
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_data_keywords(&self, angle_unit: AngleUnit) -> WriteResult<Vec<Keyword>> {
+        let mut keywords: Vec<Keyword> = vec![];
+
+        // Add each array
+        for array in self.data.iter() {
+            keywords.push(Keyword::Begin);
+
+            for (real, imag) in array.pairs(angle_unit) {
+                keywords.push(Keyword::DataPair { real, imag });
+            }
+            keywords.push(Keyword::End);
+        }
+
+        Ok(keywords)
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_interleaved_data_keywords(&self, angle_unit: AngleUnit) -> WriteResult<Vec<Keyword>> {
+        if self.data.is_empty() {
+            return Ok(vec![]);
+        }
+        let pairs: Vec<Vec<(f64, f64)>> = self
+            .data
+            .iter()
+            .map(|array| array.pairs(angle_unit))
+            .collect();
+        let points = pairs.iter().map(Vec::len).min().unwrap_or(0);
+
+        let mut keywords = vec![Keyword::Begin];
+        for i in 0..points {
+            for array in pairs.iter() {
+                let (real, imag) = array[i];
+                keywords.push(Keyword::DataPair { real, imag });
+            }
+        }
+        keywords.push(Keyword::End);
+
+        Ok(keywords)
+    }
+
+    /// Check the data arrays can be written in `layout`
+    fn check_block_layout(&self, layout: BlockLayout) -> RecordResult<()> {
+        if layout == BlockLayout::Interleaved {
+            if let Some(first) = self.data.first() {
+                if let Some(array) = self
+                    .data
+                    .iter()
+                    .find(|a| a.samples.len() != first.samples.len())
+                {
+                    return Err(RecordError::DataArrayLengthsDiffer(
+                        first.samples.len(),
+                        array.samples.len(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn get_data_defines_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        let mut keywords: Vec<Keyword> = vec![];
+
+        for (i, array) in self.data.iter().enumerate() {
+            match (array.name.is_empty(), array.format.is_empty()) {
+                (true, _) => return Err(WriteError::NoDataName(i)),
+                (_, true) => return Err(WriteError::NoDataFormat(i)),
+                (_, _) => keywords.push(Keyword::Data {
+                    name: array.name.clone(),
+                    format: array.format.clone(),
+                }),
+            }
+            let format = DataFormat::from(array.format.as_str());
+            if let Some(j) = array.samples.iter().position(|s| !format.represents(s)) {
+                return Err(WriteError::LossySample(
+                    array.name.clone(),
+                    j,
+                    array.format.clone(),
+                ));
+            }
+        }
+        Ok(keywords)
+    }
+
+    fn get_version_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        match !self.header.version.is_empty() {
+            true => Ok(vec![Keyword::CitiFile {
+                version: self.header.version.clone(),
+            }]),
+            false => Err(WriteError::NoVersion),
+        }
+    }
+
+    fn get_name_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        match !self.header.name.is_empty() {
+            true => Ok(vec![Keyword::Name(self.header.name.clone())]),
+            false => Err(WriteError::NoName),
+        }
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_comments_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        Ok(self
+            .header
+            .comments
+            .iter()
+            .map(|s| Keyword::Comment(s.clone()))
+            .collect())
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_unknown_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        Ok(self
+            .header
+            .unknown_lines
+            .iter()
+            .map(|s| Keyword::Unknown(s.clone()))
+            .collect())
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_devices_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        let mut keywords: Vec<Keyword> = vec![];
+
+        for device in self.header.devices.iter() {
+            for entry in device.entries.iter() {
+                keywords.push(Keyword::Device {
+                    name: device.name.clone(),
+                    value: entry.clone(),
+                });
+            }
+        }
+
+        Ok(keywords)
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_independent_variable_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        // Without values, keep the length the record was read with, unless
+        // the values it was read with have since been cleared
+        let header = &self.header;
+        let length = match header.independent_variable.data.len() {
+            0 if header.independent_variable_source == VarSource::LengthOnly => {
+                header.var_declared_length.unwrap_or(0)
+            }
+            n => n,
+        };
+        Ok(vec![Keyword::Var {
+            name: self.header.independent_variable.name.clone(),
+            format: self.header.independent_variable.format.clone(),
+            length,
+        }])
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_var_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        let mut keywords: Vec<Keyword> = vec![];
+
+        if self.seg_list_matches() {
+            keywords.push(Keyword::SegListBegin);
+            keywords.extend(self.header.seg_items.iter().cloned());
+            keywords.push(Keyword::SegListEnd);
+            return Ok(keywords);
+        }
+
+        // Do not set if length == 0
+        if !self.header.independent_variable.data.is_empty() {
+            keywords.push(Keyword::VarListBegin);
+            for &v in self.header.independent_variable.data.iter() {
+                keywords.push(Keyword::VarListItem(v));
+            }
+            keywords.push(Keyword::VarListEnd);
+        }
+
+        Ok(keywords)
+    }
+
+    /// Whether the `SEG_LIST` the record was read from still reproduces the
+    /// independent variable
+    ///
+    /// Compares point by point without building the variable, and only
+    /// once the number of points matches.
+    fn seg_list_matches(&self) -> bool {
+        let data = &self.header.independent_variable.data;
+        let items = &self.header.seg_items;
+        let number_of = |item: &Keyword| match *item {
+            Keyword::SegItem { number, .. } => number,
+            _ => 0,
+        };
+        let total = items
+            .iter()
+            .fold(0usize, |total, item| total.saturating_add(number_of(item)));
+        if items.is_empty() || data.is_empty() || total != data.len() {
+            return false;
+        }
+
+        let mut points = data.iter();
+        items.iter().all(|item| match *item {
+            Keyword::SegItem {
+                first,
+                last,
+                number,
+                spacing,
+            } => (0..number)
+                .all(|i| points.next() == Some(&seg_point(first, last, number, spacing, i))),
+            _ => true,
+        })
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn get_constants_keywords(&self) -> WriteResult<Vec<Keyword>> {
+        Ok(self
+            .header
+            .constants
+            .iter()
+            .map(|c| Keyword::Constant {
+                name: c.name.clone(),
+                value: c.value.clone(),
+            })
+            .collect())
+    }
+
+    fn get_keywords(
+        &self,
+        angle_unit: AngleUnit,
+        layout: BlockLayout,
+    ) -> WriteResult<Vec<Keyword>> {
+        let mut keywords: Vec<Keyword> = vec![];
+
+        keywords.append(&mut self.get_version_keywords()?);
+        keywords.append(&mut self.get_name_keywords()?);
+        keywords.append(&mut self.get_independent_variable_keywords()?);
+        keywords.append(&mut self.get_var_keywords()?);
+        keywords.append(&mut self.get_constants_keywords()?);
+        keywords.append(&mut self.get_comments_keywords()?);
+        keywords.append(&mut self.get_devices_keywords()?);
+        keywords.append(&mut self.get_unknown_keywords()?);
+        keywords.append(&mut self.get_data_defines_keywords()?);
+        keywords.append(&mut match layout {
+            BlockLayout::PerArray => self.get_data_keywords(angle_unit)?,
+            BlockLayout::Interleaved => self.get_interleaved_data_keywords(angle_unit)?,
+        });
+
+        Ok(keywords)
+    }
+
+    /// Append one point to the independent variable and every data array
+    ///
+    /// `samples` holds one sample per data array, in order. Nothing is
+    /// appended if the number of samples does not match.
+    pub fn append_point(&mut self, var_value: f64, samples: &[Complex<f64>]) -> Result<()> {
+        self.check_samples_per_array(samples)?;
+
+        self.header.independent_variable.push(var_value);
+        self.header.var_declared_length = None;
+        for (array, sample) in self.data.iter_mut().zip(samples.iter()) {
+            array.samples.push(*sample);
+        }
+        Ok(())
+    }
+
+    /// Append one point to the independent variable only
+    ///
+    /// Enforces building the independent variable ahead of the data:
+    /// arrays may already be declared, but once any array holds as many
+    /// samples as the variable has points, [`RecordError::VarBehindData`]
+    /// is returned and nothing is appended.
+    pub fn push_var_point(&mut self, value: f64) -> Result<()> {
+        let length = self.header.independent_variable.data.len();
+        if let Some(array) = self
+            .data
+            .iter()
+            .find(|a| !a.samples.is_empty() && a.samples.len() >= length)
+        {
+            return Err(RecordError::VarBehindData(array.name.clone(), array.samples.len()).into());
+        }
+        self.header.independent_variable.push(value);
+        self.header.var_declared_length = None;
+        Ok(())
+    }
+
+    /// Add a comment at the end of the header comments
+    pub fn append_comment(&mut self, text: &str) {
+        self.header.comments.push(String::from(text));
+    }
+
+    /// Add a comment prefixed with the current UTC time in ISO-8601
+    ///
+    /// For example `2024-05-01T12:00:00Z processed by mytool`.
+    #[cfg(feature = "timestamp")]
+    pub fn append_timestamped_comment(&mut self, text: &str) {
+        self.append_comment(&timestamped_comment(chrono::Utc::now(), text));
+    }
+
+    /// Name of the independent variable, `None` if blank
+    pub fn var_name(&self) -> Option<&str> {
+        Some(self.header.independent_variable.name.as_str()).filter(|s| !s.is_empty())
+    }
+
+    /// Format of the independent variable, `None` if blank
+    pub fn var_format(&self) -> Option<&str> {
+        Some(self.header.independent_variable.format.as_str()).filter(|s| !s.is_empty())
+    }
+
+    /// Rename the independent variable
+    pub fn set_var_name(&mut self, name: &str) {
+        self.header.independent_variable.name = name.to_string();
+    }
+
+    /// Set the format of the independent variable
+    pub fn set_var_format(&mut self, format: &str) {
+        self.header.independent_variable.format = format.to_string();
+    }
+
+    /// Frequency unit given by a `FREQ_UNIT` comment or constant
+    ///
+    /// Comments such as `!FREQ_UNIT: GHZ` are read as in
+    /// [`Header::comment_metadata`], then constants such as
+    /// `CONSTANT FREQ_UNIT GHZ`. The unit may be in any case. `None` if
+    /// there is no such hint or the unit is not recognised.
+    pub fn frequency_unit(&self) -> Option<FrequencyUnit> {
+        let from_comments = self
+            .header
+            .comment_entries()
+            .filter(|(key, _)| *key == "FREQ_UNIT")
+            .map(|(_, value)| value)
+            .last();
+        let from_constants = self
+            .header
+            .constants
+            .iter()
+            .find(|c| c.name == "FREQ_UNIT")
+            .map(|c| c.value.as_str());
+        FrequencyUnit::from_name(from_comments.or(from_constants)?)
+    }
+
+    /// Which list form the independent variable was read from
+    ///
+    /// A `SEG_LIST` is written back only while its items still produce the
+    /// independent variable; once the values change, or boundaries were
+    /// merged with [`ReaderOptions::dedup_seg_boundaries`], a `VAR_LIST` is
+    /// written instead.
+    pub fn independent_variable_source(&self) -> VarSource {
+        self.header.independent_variable_source
+    }
+
+    /// Length given on the `VAR` line when the record was read
+    ///
+    /// A file may declare a length of zero and let the data arrays define
+    /// the number of points. The declared length is written back when the
+    /// independent variable has no values. `None` for records built in
+    /// code, and once the independent variable is replaced or changed
+    /// through a `Record` method.
+    pub fn var_declared_length(&self) -> Option<usize> {
+        self.header.var_declared_length
+    }
+
+    /// Replace the independent variable
+    ///
+    /// The new variable must have as many points as every data array.
+    /// The record is left untouched on error.
+    pub fn set_independent_variable(&mut self, var: Var) -> Result<()> {
+        for array in self.data.iter() {
+            if array.samples.len() != var.data.len() {
+                return Err(RecordError::ArrayLengthMismatch(
+                    array.name.clone(),
+                    var.data.len(),
+                    array.samples.len(),
+                )
+                .into());
+            }
+        }
+        self.header.independent_variable = var;
+        self.header.var_declared_length = None;
+        Ok(())
+    }
+
+    /// Apply `f` to every point of the independent variable
+    ///
+    /// For example, `|f| f + 10e6` corrects for a local oscillator offset.
+    /// The data arrays are untouched.
+    pub fn map_independent_variable<F: Fn(f64) -> f64>(&mut self, f: F) {
+        for v in self.header.independent_variable.data.iter_mut() {
+            *v = f(*v);
+        }
+        self.header.var_declared_length = None;
+    }
+
+    /// Bring the record to a canonical form
+    ///
+    /// In order, this checks every data array has as many points as the
+    /// independent variable, validates the record as with
+    /// [`Record::validate_all`], sorts the points by independent variable,
+    /// uppercases known formats, and removes repeated identical devices and
+    /// constants. Calling it again has no effect. The record is left
+    /// untouched on error, with the first failure returned.
+    ///
+    /// Exponents are out of scope: values are held as numbers, so their
+    /// written form is set with [`WriteOptions::normalize_exponent`].
+    pub fn canonicalize(&mut self) -> Result<()> {
+        let length = self.header.independent_variable.data.len();
+        for array in self.data.iter() {
+            if array.samples.len() != length {
+                return Err(RecordError::ArrayLengthMismatch(
+                    array.name.clone(),
+                    length,
+                    array.samples.len(),
+                )
+                .into());
+            }
+        }
+        if let Some(error) = self.validate_all().into_iter().next() {
+            return Err(error.into());
+        }
+
+        let var = &self.header.independent_variable.data;
+        let mut order: Vec<usize> = (0..length).collect();
+        order.sort_by(|&a, &b| var[a].total_cmp(&var[b]));
+        let var: Vec<f64> = order.iter().map(|&i| var[i]).collect();
+        self.header.independent_variable.data = var;
+        self.header.var_declared_length = None;
+        for array in self.data.iter_mut() {
+            array.samples = order.iter().map(|&i| array.samples[i]).collect();
+        }
+
+        let var = &mut self.header.independent_variable;
+        var.format = normalize_format(&var.format);
+        for array in self.data.iter_mut() {
+            array.format = normalize_format(&array.format);
+        }
+
+        dedup_unordered(&mut self.header.devices);
+        dedup_unordered(&mut self.header.constants);
+        Ok(())
+    }
+
+    /// Linearly interpolate every data array onto the points of `new_var`
+    ///
+    /// Points outside of the current range are an error; see
+    /// [`Record::interpolate_to_with_extrapolation`] to clamp them instead.
+    pub fn interpolate_to(&self, new_var: &Var) -> Result<Record> {
+        self.interpolate_to_with_extrapolation(new_var, Extrapolation::Error)
+    }
+
+    /// Linearly interpolate every data array onto the points of `new_var`
+    ///
+    /// The current independent variable must be strictly increasing or
+    /// strictly decreasing. Real and imaginary parts are interpolated
+    /// independently. The copy has `new_var` as independent variable.
+    pub fn interpolate_to_with_extrapolation(
+        &self,
+        new_var: &Var,
+        extrapolation: Extrapolation,
+    ) -> Result<Record> {
+        let x = &self.header.independent_variable.data;
+        for array in self.data.iter() {
+            if array.samples.len() != x.len() {
+                return Err(RecordError::ArrayLengthMismatch(
+                    array.name.clone(),
+                    x.len(),
+                    array.samples.len(),
+                )
+                .into());
+            }
+        }
+        let increasing = x.len() < 2 || x[0] < x[1];
+        for i in 1..x.len() {
+            let ordered = match increasing {
+                true => x[i - 1] < x[i],
+                false => x[i - 1] > x[i],
+            };
+            if !ordered {
+                return Err(RecordError::NotMonotonic(i).into());
+            }
+        }
+
+        let weights = new_var
+            .data
+            .iter()
+            .map(|&v| interpolation_weight(x, increasing, v, extrapolation))
+            .collect::<RecordResult<Vec<(usize, f64)>>>()?;
+
+        let mut header = self.header.clone();
+        header.independent_variable = new_var.clone();
+        header.var_declared_length = None;
+        let data = self
+            .data
+            .iter()
+            .map(|array| DataArray {
+                name: array.name.clone(),
+                format: array.format.clone(),
+                samples: weights
+                    .iter()
+                    .map(|&(i, w)| match w == 0. {
+                        true => array.samples[i],
+                        false => array.samples[i] * (1. - w) + array.samples[i + 1] * w,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Record { header, data })
+    }
+
+    /// Copy of the header and data array definitions without any samples
+    ///
+    /// Each data array keeps its name and format, ready to be filled with
+    /// new data. The independent variable keeps its points; to clear them
+    /// too, follow with [`Record::set_independent_variable`] and an empty
+    /// [`Var`].
+    pub fn clone_structure(&self) -> Record {
+        Record {
+            header: self.header.clone(),
+            data: self
+                .data
+                .iter()
+                .map(|array| DataArray::new(&array.name, &array.format))
+                .collect(),
+        }
+    }
+
+    /// One record per data array, each with a copy of the header
+    ///
+    /// For tools expecting a single parameter per file.
+    pub fn split_by_data_array(&self) -> Vec<Record> {
+        self.data
+            .iter()
+            .map(|array| Record {
+                header: self.header.clone(),
+                data: vec![array.clone()],
+            })
+            .collect()
+    }
+
+    /// Copy restricted to the points with index in `[start_index, end_index)`
+    ///
+    /// Indices past the end are clamped, so the result may be shorter.
+    /// Points beyond the shortest data array are dropped, so the independent
+    /// variable and data arrays of the result have the same length.
+    pub fn subset_points(&self, start_index: usize, end_index: usize) -> Record {
+        self.subset_where(|i, _| start_index <= i && i < end_index)
+    }
+
+    /// Copy restricted to the points with independent variable in `[lo, hi]`
+    ///
+    /// As with [`Record::subset_points`], points beyond the shortest data
+    /// array are dropped.
+    pub fn subset_var_range(&self, lo: f64, hi: f64) -> Record {
+        self.subset_where(|_, v| lo <= v && v <= hi)
+    }
+
+    fn subset_where<F: Fn(usize, f64) -> bool>(&self, keep: F) -> Record {
+        let var = &self.header.independent_variable.data;
+        let n = self
+            .data
+            .iter()
+            .map(|array| array.samples.len())
+            .fold(var.len(), usize::min);
+        let indices: Vec<usize> = var[..n]
+            .iter()
+            .enumerate()
+            .filter(|(i, v)| keep(*i, **v))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut record = self.clone();
+        record.header.independent_variable.data = indices
+            .iter()
+            .map(|&i| self.header.independent_variable.data[i])
+            .collect();
+        record.header.var_declared_length = None;
+        for (subset, array) in record.data.iter_mut().zip(self.data.iter()) {
+            subset.samples = indices.iter().map(|&i| array.samples[i]).collect();
+        }
+        record
+    }
+
+    /// Hash of the information in the record, see [`Record::content_hash_with_digits`]
+    ///
+    /// Floats are rounded to 12 significant digits.
+    pub fn content_hash(&self) -> u64 {
+        self.content_hash_with_digits(12)
+    }
+
+    /// Hash of the information in the record with floats rounded to `digits`
+    /// significant digits
+    ///
+    /// The hash does not depend on how the record was written: floats are
+    /// rounded so that formatting or small drift does not change it, and
+    /// constants and devices are hashed regardless of their order. It is
+    /// stable across runs and platforms, so it can be stored. `digits` is
+    /// clamped to between 1 and 17, which already tells every `f64` apart.
+    pub fn content_hash_with_digits(&self, digits: usize) -> u64 {
+        let precision = digits.clamp(1, MAX_SIGNIFICANT_DIGITS) - 1;
+        let round = |value: f64| -> String {
+            match value == 0. {
+                true => String::from("0"),
+                false => format!("{:.*e}", precision, value),
+            }
+        };
+
+        let mut hasher = ContentHasher::new();
+        hasher.write_str(&self.header.version);
+        hasher.write_str(&self.header.name);
+        hasher.write_strs(self.header.comments.iter());
+
+        let mut constants: Vec<(&str, &str)> = self
+            .header
+            .constants
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        constants.sort_unstable();
+        hasher.write_usize(constants.len());
+        for (name, value) in constants {
+            hasher.write_str(name);
+            hasher.write_str(value);
+        }
+
+        let mut devices: Vec<&Device> = self.header.devices.iter().collect();
+        devices.sort_unstable_by(|a, b| (&a.name, &a.entries).cmp(&(&b.name, &b.entries)));
+        hasher.write_usize(devices.len());
+        for device in devices {
+            hasher.write_str(&device.name);
+            hasher.write_strs(device.entries.iter());
+        }
+
+        hasher.write_strs(self.header.unknown_lines.iter());
+
+        let var = &self.header.independent_variable;
+        hasher.write_str(&var.name);
+        hasher.write_str(&var.format);
+        hasher.write_strs(var.data.iter().map(|&v| round(v)));
+
+        hasher.write_usize(self.data.len());
+        for array in self.data.iter() {
+            hasher.write_str(&array.name);
+            hasher.write_str(&array.format);
+            hasher.write_usize(array.samples.len());
+            for sample in array.samples.iter() {
+                hasher.write_str(&round(sample.re));
+                hasher.write_str(&round(sample.im));
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Compare against another record
+    ///
+    /// Data arrays are matched by name. Unmatched arrays at the same
+    /// position are treated as renamed. Samples and independent variable
+    /// points are equal if they differ by no more than `epsilon`.
+    pub fn diff(&self, other: &Record, epsilon: f64) -> RecordDiff {
+        let mut diff = RecordDiff {
+            header: self.header_differences(other, epsilon),
+            ..RecordDiff::default()
+        };
+
+        let find = |record: &Record, name: &str| record.data.iter().any(|a| a.name == name);
+        let mut pairs: Vec<(&DataArray, &DataArray)> = vec![];
+        for (i, array) in self.data.iter().enumerate() {
+            if let Some(other_array) = other.data.iter().find(|a| a.name == array.name) {
+                pairs.push((array, other_array));
+            } else {
+                match other.data.get(i) {
+                    Some(other_array) if !find(self, &other_array.name) => {
+                        diff.renamed_arrays
+                            .push((array.name.clone(), other_array.name.clone()));
+                        pairs.push((array, other_array));
+                    }
+                    _ => diff.removed_arrays.push(array.name.clone()),
+                }
+            }
+        }
+        for other_array in other.data.iter() {
+            let matched = pairs.iter().any(|(_, o)| core::ptr::eq(*o, other_array));
+            if !matched {
+                diff.added_arrays.push(other_array.name.clone());
+            }
+        }
+
+        for (array, other_array) in pairs {
+            if array.format != other_array.format {
+                diff.format_changes.push(array.name.clone());
+            }
+            if array.samples.len() != other_array.samples.len() {
+                diff.length_changes.push((
+                    array.name.clone(),
+                    array.samples.len(),
+                    other_array.samples.len(),
+                ));
+            }
+            for (index, (a, b)) in array
+                .samples
+                .iter()
+                .zip(other_array.samples.iter())
+                .enumerate()
+            {
+                let delta = (a - b).norm();
+                if delta > epsilon || delta.is_nan() {
+                    diff.sample_differences.push(SampleDifference {
+                        array: array.name.clone(),
+                        index,
+                        delta,
+                    });
+                }
+            }
+        }
+
+        diff
+    }
+
+    fn header_differences(&self, other: &Record, epsilon: f64) -> Vec<String> {
+        let lhs = &self.header;
+        let rhs = &other.header;
+        let lhs_var = &lhs.independent_variable;
+        let rhs_var = &rhs.independent_variable;
+
+        let var_equal = lhs_var.name == rhs_var.name
+            && lhs_var.format == rhs_var.format
+            && lhs_var.data.len() == rhs_var.data.len()
+            && lhs_var
+                .data
+                .iter()
+                .zip(rhs_var.data.iter())
+                .all(|(a, b)| (a - b).abs() <= epsilon);
+
+        let mut fields = vec![];
+        if lhs.version != rhs.version {
+            fields.push(String::from("version"));
+        }
+        if lhs.name != rhs.name {
+            fields.push(String::from("name"));
+        }
+        if lhs.comments != rhs.comments {
+            fields.push(String::from("comments"));
+        }
+        if lhs.devices != rhs.devices {
+            fields.push(String::from("devices"));
+        }
+        if !var_equal {
+            fields.push(String::from("independent_variable"));
+        }
+        if lhs.constants != rhs.constants {
+            fields.push(String::from("constants"));
+        }
+        if lhs.unknown_lines != rhs.unknown_lines {
+            fields.push(String::from("unknown_lines"));
+        }
+        fields
+    }
+
+    /// Iterate over the data arrays
+    pub fn iter(&self) -> core::slice::Iter<'_, DataArray> {
+        self.data.iter()
+    }
+
+    /// Keep only the data arrays for which `f` returns `true`
+    ///
+    /// Same as [`Vec::retain`] on [`Record::data`].
+    pub fn retain_data_arrays<F: FnMut(&DataArray) -> bool>(&mut self, f: F) {
+        self.data.retain(f);
+    }
+
+    /// Reverse the order of the points, e.g. to flip the sweep direction
+    ///
+    /// The independent variable and every data array are reversed in
+    /// place, so they stay aligned.
+    pub fn reverse(&mut self) {
+        self.header.independent_variable.data.reverse();
+        self.header.var_declared_length = None;
+        for array in self.data.iter_mut() {
+            array.samples.reverse();
+        }
+    }
+
+    /// Extend every data array to the length of the longest with `fill`
+    ///
+    /// A recovery step for arrays missing trailing samples, e.g. a dropped
+    /// final point. The independent variable is not changed. Returns the
+    /// number of samples added.
+    pub fn pad_data_arrays(&mut self, fill: Complex<f64>) -> usize {
+        let length = self.data.iter().map(|a| a.samples.len()).max().unwrap_or(0);
+        let mut added = 0;
+        for array in self.data.iter_mut() {
+            added += length - array.samples.len();
+            array.samples.resize(length, fill);
+        }
+        added
+    }
+
+    /// Iterate over the data arrays with their index
+    pub fn enumerate_data(&self) -> impl Iterator<Item = (usize, &DataArray)> {
+        self.data.iter().enumerate()
+    }
+
+    /// Data array at `index`, `None` if out of range
+    pub fn data_array(&self, index: usize) -> Option<&DataArray> {
+        self.data.get(index)
+    }
+
+    /// Mutable data array at `index`, `None` if out of range
+    pub fn data_array_mut(&mut self, index: usize) -> Option<&mut DataArray> {
+        self.data.get_mut(index)
+    }
+
+    /// Group delay in seconds, `-dφ/dω`, of the first data array named
+    /// `array_name`
+    ///
+    /// The independent variable is taken as frequency in Hz. The phase is
+    /// unwrapped with [`DataArray::phase_degrees_unwrapped`] and
+    /// differentiated with central differences, one-sided at the ends, so
+    /// there is one value per point. `None` if there is no such array, or
+    /// the independent variable has fewer than two points, a different
+    /// length to the array, or is not strictly monotonic, as a repeated
+    /// frequency would divide by zero.
+    pub fn group_delay(&self, array_name: &str) -> Option<Vec<f64>> {
+        let array = self.data.iter().find(|array| array.name == array_name)?;
+        let frequency = &self.header.independent_variable.data;
+        let n = frequency.len();
+        if n < 2 || array.samples.len() != n {
+            return None;
+        }
+        let increasing = frequency[0] < frequency[1];
+        let monotonic = frequency.windows(2).all(|w| match increasing {
+            true => w[0] < w[1],
+            false => w[0] > w[1],
+        });
+        if !monotonic {
+            return None;
+        }
+
+        let phase: Vec<f64> = array
+            .phase_degrees_unwrapped()
+            .iter()
+            .map(|p| p.to_radians())
+            .collect();
+        let delay = |i: usize, j: usize| {
+            -(phase[j] - phase[i]) / (2. * core::f64::consts::PI * (frequency[j] - frequency[i]))
+        };
+        Some(
+            (0..n)
+                .map(|i| delay(i.saturating_sub(1), (i + 1).min(n - 1)))
+                .collect(),
+        )
+    }
+
+    /// Format of the first data array named `name`
+    pub fn data_array_format(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|array| array.name == name)
+            .map(|array| array.format.as_str())
+    }
+
+    /// Set the format of every data array
+    ///
+    /// Only the format is changed, not the samples. As samples are stored
+    /// as complex numbers, this selects how they are written.
+    pub fn set_all_data_formats(&mut self, format: &str) {
+        for array in self.data.iter_mut() {
+            array.format = String::from(format);
+        }
+    }
+
+    /// Data array for the scattering parameter `S[i,j]`
+    ///
+    /// Names are matched on their indices, so `S[1,2]` and `S[ 1, 2 ]`
+    /// are the same parameter.
+    pub fn s_parameter(&self, i: usize, j: usize) -> Option<&DataArray> {
+        self.data
+            .iter()
+            .find(|array| parse_s_parameter_indices(&array.name) == Some((i, j)))
+    }
+
+    /// Number of ports, from the largest index in `S[i,j]` array names
+    ///
+    /// Assumes a full square matrix. Returns `None` if there are no arrays
+    /// or any array name is not of the form `S[i,j]`.
+    pub fn num_ports(&self) -> Option<usize> {
+        let mut ports = None;
+        for array in self.data.iter() {
+            let (i, j) = parse_s_parameter_indices(&array.name)?;
+            ports = Some(ports.unwrap_or(0).max(i).max(j));
+        }
+        ports
+    }
+
+    /// Typed view of the four scattering parameters of a 2-port
+    ///
+    /// Returns `None` if any of `S[1,1]`, `S[1,2]`, `S[2,1]` or `S[2,2]`
+    /// is missing.
+    pub fn as_two_port(&self) -> Option<TwoPort<'_>> {
+        Some(TwoPort {
+            s11: &self.s_parameter(1, 1)?.samples,
+            s12: &self.s_parameter(1, 2)?.samples,
+            s21: &self.s_parameter(2, 1)?.samples,
+            s22: &self.s_parameter(2, 2)?.samples,
+        })
+    }
+
+    /// Data arrays stored in `format`
+    ///
+    /// Known formats are compared by kind, so an array in `COMPLEX` is
+    /// in `RI`. Other formats must match exactly.
+    pub fn arrays_with_format(&self, format: &str) -> Vec<&DataArray> {
+        self.data
+            .iter()
+            .filter(|array| DataFormat::same_format(&array.format, format))
+            .collect()
+    }
+
+    /// Names of the data arrays not stored in `format`
+    ///
+    /// See [`Record::arrays_with_format`] for how formats are compared.
+    pub fn arrays_not_in_format(&self, format: &str) -> Vec<&str> {
+        self.data
+            .iter()
+            .filter(|array| !DataFormat::same_format(&array.format, format))
+            .map(|array| array.name.as_str())
+            .collect()
+    }
+
+    fn check_samples_per_array(&self, samples: &[Complex<f64>]) -> RecordResult<()> {
+        match samples.len() == self.data.len() {
+            true => Ok(()),
+            false => Err(RecordError::WrongNumberOfSamples(
+                self.data.len(),
+                samples.len(),
+            )),
+        }
+    }
+
+    /// Data as one flat buffer with dimensions `(num_points, num_arrays)`
+    ///
+    /// The buffer is row-major by point and interleaves the real and
+    /// imaginary parts, so sample `j` of point `i` is at
+    /// `2 * (i * num_arrays + j)` and `2 * (i * num_arrays + j) + 1`. Every
+    /// array must have as many samples as the independent variable.
+    pub fn data_flat(&self) -> Result<(Vec<f64>, usize, usize)> {
+        let num_points = self.header.independent_variable.data.len();
+        let num_arrays = self.data.len();
+        if let Some(array) = self.data.iter().find(|a| a.samples.len() != num_points) {
+            return Err(RecordError::ArrayLengthMismatch(
+                array.name.clone(),
+                num_points,
+                array.samples.len(),
+            )
+            .into());
+        }
+
+        let mut buffer = Vec::with_capacity(2 * num_points * num_arrays);
+        for i in 0..num_points {
+            for array in self.data.iter() {
+                buffer.push(array.samples[i].re);
+                buffer.push(array.samples[i].im);
+            }
+        }
+        Ok((buffer, num_points, num_arrays))
+    }
+
+    /// Convert all data arrays to single precision
+    ///
+    /// The header is copied unchanged; the independent variable stays in
+    /// double precision.
+    pub fn to_f32(&self) -> RecordF32 {
+        RecordF32 {
+            header: self.header.clone(),
+            data: self.data.iter().map(|array| array.to_f32()).collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn blank() -> Record {
+        Record {
+            header: Header::blank(),
+            data: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_record {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn write_gives_error_on_bad_file() {
+        let record = Record::default();
+        let mut file = std::fs::File::create(tempdir().unwrap().path().join("temp.cti")).unwrap();
+        match record.to_writer(&mut file) {
+            Err(Error::WriteError(WriteError::NoName)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    mod test_read_mmap {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("temp.cti");
+            let mut file = std::fs::File::create(&path).unwrap();
+            record.to_writer(&mut file).unwrap();
+            drop(file);
+
+            match unsafe { Record::read_mmap(&path) } {
+                Ok(result) => assert_eq!(result, record),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn empty_file() {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("temp.cti");
+            std::fs::File::create(&path).unwrap();
+            match unsafe { Record::read_mmap(&path) } {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn missing_file() {
+            let dir = tempdir().unwrap();
+            match unsafe { Record::read_mmap(dir.path().join("missing.cti")) } {
+                Err(Error::ReadError(ReadError::ReadingError(e))) => {
+                    assert_eq!(e.kind(), std::io::ErrorKind::NotFound)
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn with_capacity() {
+        let record = Record::with_capacity(100, 4);
+        assert_eq!(record.header, Header::default());
+        assert!(record.header.independent_variable.data.capacity() >= 100);
+        assert_eq!(record.data.len(), 4);
+        for array in record.data.iter() {
+            assert_eq!(array.name, "");
+            assert_eq!(array.format, "");
+            assert!(array.samples.is_empty());
+            assert!(array.samples.capacity() >= 100);
+        }
+    }
+
+    mod test_write {
+        use super::*;
+
+        /// Writer failing once `limit` bytes have been written
+        struct FailingWriter {
+            written: usize,
+            limit: usize,
+        }
+
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.written + buf.len() > self.limit {
+                    return Err(std::io::Error::other("full"));
+                }
+                self.written += buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        fn failing_setup() -> Record {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
+
+        #[test]
+        fn writer_fails_partway() {
+            let mut writer = FailingWriter {
+                written: 0,
+                limit: 40,
+            };
+            match failing_setup().to_writer(&mut writer) {
+                Err(Error::WriteError(WriteError::WrittingError(e))) => {
+                    assert_eq!(e.to_string(), "full")
+                }
+                e => panic!("{:?}", e),
+            }
+            assert!(writer.written <= 40);
+        }
+
+        #[test]
+        fn streaming_writer_fails_partway() {
+            let mut writer = FailingWriter {
+                written: 0,
+                limit: 40,
+            };
+            match failing_setup().write_streaming(&mut writer, &WriteOptions::default()) {
+                Err(Error::WriteError(WriteError::WrittingError(_))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn get_keywords() {
+            let mut record = Record::default();
+            record.header.constants.push(Constant {
+                name: String::from("Const Name"),
+                value: String::from("Value"),
+            });
+            record.header.independent_variable = Var {
+                name: String::from("Var Name"),
+                format: String::from("Format"),
+                data: vec![1.],
+            };
+            record.header.devices.push(Device {
+                name: String::from("Name A"),
+                entries: vec![String::from("entry 1"), String::from("entry 2")],
+            });
+            record.header.comments.push(String::from("A Comment"));
+            record.header.name = String::from("Name");
+            record.header.version = String::from("A.01.00");
+            record.data.push(DataArray {
+                name: String::from("Data Name A"),
+                format: String::from("Format A"),
+                samples: vec![Complex { re: 1., im: 2. }],
+            });
+            record.data.push(DataArray {
+                name: String::from("Data Name B"),
+                format: String::from("Format B"),
+                samples: vec![Complex { re: 3., im: 5. }, Complex { re: 4., im: 6. }],
+            });
+
+            match record.get_keywords(AngleUnit::Degrees, BlockLayout::PerArray) {
+                Ok(v) => assert_eq!(
+                    v,
+                    vec![
+                        Keyword::CitiFile {
+                            version: String::from("A.01.00")
+                        },
+                        Keyword::Name(String::from("Name")),
+                        Keyword::Var {
+                            name: String::from("Var Name"),
+                            format: String::from("Format"),
+                            length: 1
+                        },
+                        Keyword::VarListBegin,
+                        Keyword::VarListItem(1.),
+                        Keyword::VarListEnd,
+                        Keyword::Constant {
+                            name: String::from("Const Name"),
+                            value: String::from("Value")
+                        },
+                        Keyword::Comment(String::from("A Comment")),
+                        Keyword::Device {
+                            name: String::from("Name A"),
+                            value: String::from("entry 1")
+                        },
+                        Keyword::Device {
+                            name: String::from("Name A"),
+                            value: String::from("entry 2")
+                        },
+                        Keyword::Data {
+                            name: String::from("Data Name A"),
+                            format: String::from("Format A")
+                        },
+                        Keyword::Data {
+                            name: String::from("Data Name B"),
+                            format: String::from("Format B")
+                        },
+                        Keyword::Begin,
+                        Keyword::DataPair { real: 1., imag: 2. },
+                        Keyword::End,
+                        Keyword::Begin,
+                        Keyword::DataPair { real: 3., imag: 5. },
+                        Keyword::DataPair { real: 4., imag: 6. },
+                        Keyword::End,
+                    ]
+                ),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        mod test_get_var_keywords {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let record = Record::default();
+                match record.get_var_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one() {
+                let mut record = Record::default();
+                record.header.independent_variable.data.push(1.);
+                match record.get_var_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::VarListBegin,
+                            Keyword::VarListItem(1.),
+                            Keyword::VarListEnd
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn multiple() {
+                let mut record = Record::default();
+                record.header.independent_variable.data.push(1.);
+                record.header.independent_variable.data.push(2.);
+                record.header.independent_variable.data.push(3.);
+                match record.get_var_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::VarListBegin,
+                            Keyword::VarListItem(1.),
+                            Keyword::VarListItem(2.),
+                            Keyword::VarListItem(3.),
+                            Keyword::VarListEnd
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_constants_keywords {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let record = Record::default();
+                match record.get_constants_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one() {
+                let mut record = Record::default();
+                record.header.constants.push(Constant {
+                    name: String::from("Name"),
+                    value: String::from("Value"),
+                });
+                match record.get_constants_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Constant {
+                            name: String::from("Name"),
+                            value: String::from("Value")
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn two() {
+                let mut record = Record::default();
+                record.header.constants.push(Constant {
+                    name: String::from("Name A"),
+                    value: String::from("Value A"),
+                });
+                record.header.constants.push(Constant {
+                    name: String::from("Name B"),
+                    value: String::from("Value B"),
+                });
+                match record.get_constants_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Constant {
+                                name: String::from("Name A"),
+                                value: String::from("Value A")
+                            },
+                            Keyword::Constant {
+                                name: String::from("Name B"),
+                                value: String::from("Value B")
+                            }
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_independent_variable_keywords {
+            use super::*;
+
+            #[test]
+            fn no_format() {
+                let mut record = Record::default();
+                record.header.independent_variable.name = String::from("Name");
+                match record.get_independent_variable_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Var {
+                            name: String::from("Name"),
+                            format: String::new(),
+                            length: 0
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn format() {
+                let mut record = Record::default();
+                record.header.independent_variable.name = String::from("Name");
+                record.header.independent_variable.format = String::from("Format");
+                match record.get_independent_variable_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Var {
+                            name: String::from("Name"),
+                            format: String::from("Format"),
+                            length: 0
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn length() {
+                let mut record = Record::default();
+                record.header.independent_variable.name = String::from("Name");
+                record.header.independent_variable.format = String::from("Format");
+                record.header.independent_variable.data = vec![0.; 10];
+                match record.get_independent_variable_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Var {
+                            name: String::from("Name"),
+                            format: String::from("Format"),
+                            length: 10
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_devices_keywords {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let record = Record::default();
+                match record.get_devices_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one_device_no_entry() {
+                let mut record = Record::default();
+                record.header.devices.push(Device {
+                    name: String::from(""),
+                    entries: vec![],
+                });
+                match record.get_devices_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one_device() {
+                let mut record = Record::default();
+                record.header.devices.push(Device {
+                    name: String::from("Name"),
+                    entries: vec![String::from("entry")],
+                });
+                match record.get_devices_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Device {
+                            name: String::from("Name"),
+                            value: String::from("entry")
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one_device_multiple_entries() {
+                let mut record = Record::default();
+                record.header.devices.push(Device {
+                    name: String::from("Name"),
+                    entries: vec![String::from("entry 1"), String::from("entry 2")],
+                });
+                match record.get_devices_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Device {
+                                name: String::from("Name"),
+                                value: String::from("entry 1")
+                            },
+                            Keyword::Device {
+                                name: String::from("Name"),
+                                value: String::from("entry 2")
+                            }
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn multiple_devices() {
+                let mut record = Record::default();
+                record.header.devices.push(Device {
+                    name: String::from("Name A"),
+                    entries: vec![String::from("entry 1")],
+                });
+                record.header.devices.push(Device {
+                    name: String::from("Name B"),
+                    entries: vec![String::from("entry 2")],
+                });
+                match record.get_devices_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Device {
+                                name: String::from("Name A"),
+                                value: String::from("entry 1")
+                            },
+                            Keyword::Device {
+                                name: String::from("Name B"),
+                                value: String::from("entry 2")
+                            }
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_comments_keywords {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let record = Record::default();
+                match record.get_comments_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one() {
+                let mut record = Record::default();
+                record.header.comments.push(String::from("A Comment"));
+                match record.get_comments_keywords() {
+                    Ok(v) => assert_eq!(v, vec![Keyword::Comment(String::from("A Comment"))]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn multiple() {
+                let mut record = Record::default();
+                record.header.comments.push(String::from("A Comment"));
+                record.header.comments.push(String::from("B Comment"));
+                match record.get_comments_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Comment(String::from("A Comment")),
+                            Keyword::Comment(String::from("B Comment"))
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_unknown_keywords {
+            use super::*;
+
+            #[test]
+            fn empty() {
+                let record = Record::default();
+                match record.get_unknown_keywords() {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one() {
+                let mut record = Record::default();
+                record
+                    .header
+                    .unknown_lines
+                    .push(String::from("SWEEP_TYPE LOG"));
+                match record.get_unknown_keywords() {
+                    Ok(v) => assert_eq!(v, vec![Keyword::Unknown(String::from("SWEEP_TYPE LOG"))]),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_name_keywords {
+            use super::*;
+
+            #[test]
+            fn none() {
+                let mut record = Record::default();
+                record.header.name = String::new();
+                match record.get_name_keywords() {
+                    Err(WriteError::NoName) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn some() {
+                let mut record = Record::default();
+                record.header.name = String::from("A.01.00");
+                match record.get_name_keywords() {
+                    Ok(v) => assert_eq!(v, vec![Keyword::Name(String::from("A.01.00"))]),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_version_keywords {
+            use super::*;
+
+            #[test]
+            fn none() {
+                let mut record = Record::default();
+                record.header.version = String::new();
+                match record.get_version_keywords() {
+                    Err(WriteError::NoVersion) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn some() {
+                let mut record = Record::default();
+                record.header.version = String::from("A.01.00");
+                match record.get_version_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::CitiFile {
+                            version: String::from("A.01.00")
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_data_keywords {
+            use super::*;
+
+            #[test]
+            fn many_values() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::new(),
+                    samples: vec![
+                        Complex { re: 1., im: 4. },
+                        Complex { re: 2., im: 1e-6 },
+                        Complex { re: -3., im: 0. },
+                    ],
+                });
+                match record.get_data_keywords(AngleUnit::Degrees) {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Begin,
+                            Keyword::DataPair { real: 1., imag: 4. },
+                            Keyword::DataPair {
+                                real: 2.,
+                                imag: 1e-6
+                            },
+                            Keyword::DataPair {
+                                real: -3.,
+                                imag: 0.
+                            },
+                            Keyword::End
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn magnitude_angle_written_in_format() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::from("S"),
+                    format: String::from("MAGANGLE"),
+                    samples: vec![Complex { re: 0., im: 2. }],
+                });
+                match record.get_data_keywords(AngleUnit::Degrees) {
+                    Ok(v) => match v[1] {
+                        Keyword::DataPair { real, imag } => {
+                            approx::assert_relative_eq!(real, 2.);
+                            approx::assert_relative_eq!(imag, 90.);
+                        }
+                        ref k => panic!("{:?}", k),
+                    },
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn one_array_gives_correct_result() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::new(),
+                    samples: vec![Complex { re: 1., im: 2. }],
+                });
+                match record.get_data_keywords(AngleUnit::Degrees) {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Begin,
+                            Keyword::DataPair { real: 1., imag: 2. },
+                            Keyword::End
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn multiple_array_gives_correct_result() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::new(),
+                    samples: vec![Complex { re: 1., im: 2. }],
+                });
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::new(),
+                    samples: vec![Complex { re: 3., im: 4. }],
+                });
+                match record.get_data_keywords(AngleUnit::Degrees) {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![
+                            Keyword::Begin,
+                            Keyword::DataPair { real: 1., imag: 2. },
+                            Keyword::End,
+                            Keyword::Begin,
+                            Keyword::DataPair { real: 3., imag: 4. },
+                            Keyword::End
+                        ]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn no_arrays_gives_empty() {
+                let record = Record::default();
+                match record.get_data_keywords(AngleUnit::Degrees) {
+                    Ok(v) => assert_eq!(v, vec![]),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        mod test_get_data_defines_keywords {
+            use super::*;
+
+            #[test]
+            fn one_entry() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::from("Name"),
+                    format: String::from("Format"),
+                    samples: vec![],
+                });
+                match record.get_data_defines_keywords() {
+                    Ok(v) => assert_eq!(
+                        v,
+                        vec![Keyword::Data {
+                            name: String::from("Name"),
+                            format: String::from("Format")
+                        }]
+                    ),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn multiple() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::from("Name A"),
+                    format: String::from("Format A"),
+                    samples: vec![],
+                });
+                record.data.push(DataArray {
+                    name: String::from("Name B"),
+                    format: String::from("Format B"),
+                    samples: vec![],
+                });
+                match record.get_data_defines_keywords() {
+                    Ok(v) => {
+                        assert_eq!(
+                            v,
+                            vec![
+                                Keyword::Data {
+                                    name: String::from("Name A"),
+                                    format: String::from("Format A")
+                                },
+                                Keyword::Data {
+                                    name: String::from("Name B"),
+                                    format: String::from("Format B")
+                                }
+                            ]
+                        );
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn no_name() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::from("Format"),
+                    samples: vec![],
+                });
+                match record.get_data_defines_keywords() {
+                    Err(WriteError::NoDataName(0)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn no_format() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::from("Name"),
+                    format: String::new(),
+                    samples: vec![],
+                });
+                match record.get_data_defines_keywords() {
+                    Err(WriteError::NoDataFormat(0)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn no_name_no_format() {
+                let mut record = Record::default();
+                record.data.push(DataArray {
+                    name: String::new(),
+                    format: String::new(),
+                    samples: vec![],
+                });
+                match record.get_data_defines_keywords() {
+                    Err(WriteError::NoDataName(0)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+    }
+
+    mod test_write_with_progress {
+        use super::*;
+
+        #[test]
+        fn reports_keywords() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let mut reports = vec![];
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_progress(&mut written, &WriteOptions::default(), |n| {
+                    reports.push(n)
+                })
+                .unwrap();
+            assert_eq!(reports, vec![7]);
+        }
+
+        #[test]
+        fn reports_periodically() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.data.push(DataArray::new("S", "RI"));
+            for i in 0..2000 {
+                record
+                    .append_point(i as f64, &[Complex::new(1., 0.)])
+                    .unwrap();
+            }
+            let mut reports = vec![];
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_progress(&mut written, &WriteOptions::default(), |n| {
+                    reports.push(n)
+                })
+                .unwrap();
+            assert_eq!(reports, vec![1024, 2048, 3072, 4008]);
+        }
+    }
+
+    mod test_write_lossy_formats {
+        use super::*;
+
+        fn setup(format: &str, samples: Vec<Complex<f64>>) -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            let n = samples.len();
+            record
+                .header
+                .independent_variable
+                .seq(1e9, n as f64 * 1e9, n);
+            record.data.push(DataArray {
+                name: String::from("S"),
+                format: String::from(format),
+                samples,
+            });
+            record
+        }
+
+        fn round_trip(record: &Record) -> Result<Record> {
+            let mut buffer: Vec<u8> = vec![];
+            record.to_writer(&mut buffer)?;
+            Record::from_reader(&mut buffer.as_slice())
+        }
+
+        #[test]
+        fn magnitude() {
+            let samples = vec![
+                Complex::new(-2., 0.),
+                Complex::new(0., 0.),
+                Complex::new(3., 0.),
+            ];
+            let record = setup("MAG", samples);
+            assert_eq!(round_trip(&record).unwrap().data, record.data);
+        }
+
+        #[test]
+        fn magnitude_angle() {
+            let samples = vec![
+                Complex::new(-2., 0.),
+                Complex::new(0., 0.),
+                Complex::new(0., 3.),
+            ];
+            let record = setup("MAGANGLE", samples);
+            let result = round_trip(&record).unwrap();
+            for (l, r) in result.data[0]
+                .samples
+                .iter()
+                .zip(record.data[0].samples.iter())
+            {
+                approx::assert_abs_diff_eq!(l.re, r.re, epsilon = 1e-12);
+                approx::assert_abs_diff_eq!(l.im, r.im, epsilon = 1e-12);
+            }
+        }
+
+        #[test]
+        fn decibel() {
+            let record = setup("DB", vec![Complex::new(0.1, 0.), Complex::new(10., 0.)]);
+            let result = round_trip(&record).unwrap();
+            assert_complex_array_relative_eq!(result.data[0].samples, record.data[0].samples);
+        }
+
+        #[test]
+        fn magnitude_with_phase() {
+            let record = setup("MAG", vec![Complex::new(1., 0.), Complex::new(1., 1.)]);
+            match round_trip(&record) {
+                Err(Error::WriteError(WriteError::LossySample(name, 1, format))) => {
+                    assert_eq!(name, "S");
+                    assert_eq!(format, "MAG");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn decibel_negative() {
+            let record = setup("DB", vec![Complex::new(-2., 0.)]);
+            match round_trip(&record) {
+                Err(Error::WriteError(WriteError::LossySample(_, 0, _))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn decibel_zero() {
+            for format in ["DB", "DBANGLE"].iter() {
+                let record = setup(format, vec![Complex::new(1., 0.), Complex::new(0., 0.)]);
+                match round_trip(&record) {
+                    Err(Error::WriteError(WriteError::LossySample(_, 1, _))) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        #[test]
+        fn nothing_written() {
+            let record = setup("DB", vec![Complex::new(0., 0.)]);
+            let mut buffer: Vec<u8> = vec![];
+            assert!(record
+                .write_streaming(&mut buffer, &WriteOptions::default())
+                .is_err());
+            assert!(buffer.is_empty());
+        }
+    }
+
+    mod test_write_streaming {
+        use super::*;
+
+        fn write_both(record: &Record, options: &WriteOptions) -> (Vec<u8>, Vec<u8>) {
+            let mut streamed: Vec<u8> = vec![];
+            record.write_streaming(&mut streamed, options).unwrap();
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, options)
+                .unwrap();
+            (streamed, written)
+        }
+
+        fn setup() -> Record {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\n#NA VERSION HP8510B.05.00\nCONSTANT A 1\nVAR FREQ MAG 3\nVAR_LIST_BEGIN\n1000000000\n2000000000\n3000000000\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] MAGANGLE\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\nBEGIN\n1E0,90E0\n2E0,-45E0\n3E0,0E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
+
+        #[test]
+        fn same_as_writer() {
+            let (streamed, written) = write_both(&setup(), &WriteOptions::default());
+            assert_eq!(streamed, written);
+        }
+
+        #[test]
+        fn same_as_writer_with_options() {
+            let options = WriteOptions {
+                normalize_exponent: true,
+                angle_unit: AngleUnit::Radians,
+                normalize_formats: true,
+                align_data: true,
+                strict_text: true,
+                comment_keyword_style: CommentStyle::Keyword,
+                validate_formats: true,
+                exponent_style: ExponentStyle::Engineering { mantissa_digits: 6 },
+                block_layout: BlockLayout::Interleaved,
+                var_list_style: VarListStyle::Exponential,
+            };
+            let (streamed, written) = write_both(&setup(), &options);
+            assert_eq!(streamed, written);
+        }
+
+        #[test]
+        fn same_as_writer_interleaved() {
+            let options = WriteOptions {
+                block_layout: BlockLayout::Interleaved,
+                ..WriteOptions::default()
+            };
+            let (streamed, written) = write_both(&setup(), &options);
+            assert_eq!(streamed, written);
+            let streamed = String::from_utf8(streamed).unwrap();
+            assert!(streamed.ends_with("BEGIN\n-3.54545E-2,-1.38601E-3\n1E0,9E1\n2.3491E-4,-1.39883E-3\n2E0,-4.5E1\n2.00382E-3,-1.40022E-3\n3E0,0E0\nEND\n"));
+        }
+
+        #[test]
+        fn same_as_writer_interleaved_no_data() {
+            let mut record = setup();
+            record.data.clear();
+            let options = WriteOptions {
+                block_layout: BlockLayout::Interleaved,
+                ..WriteOptions::default()
+            };
+            let (streamed, written) = write_both(&record, &options);
+            assert_eq!(streamed, written);
+        }
+
+        #[test]
+        fn same_as_writer_no_var_list() {
+            let mut record = setup();
+            record.header.independent_variable.data.clear();
+            let (streamed, written) = write_both(&record, &WriteOptions::default());
+            assert_eq!(streamed, written);
+        }
+
+        #[test]
+        fn error_before_writing() {
+            let mut record = setup();
+            record.data[1].format = String::new();
+            let mut streamed: Vec<u8> = vec![];
+            match record.write_streaming(&mut streamed, &WriteOptions::default()) {
+                Err(Error::WriteError(WriteError::NoDataFormat(1))) => (),
+                e => panic!("{:?}", e),
+            }
+            assert!(streamed.is_empty());
+        }
+    }
+
+    mod test_estimated_write_size {
+        use super::*;
+
+        fn written_len(record: &Record, options: &WriteOptions) -> usize {
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, options)
+                .unwrap();
+            written.len()
+        }
+
+        fn setup() -> Record {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\n#NA VERSION HP8510B.05.00\nCONSTANT A 1\nVAR FREQ MAG 3\nVAR_LIST_BEGIN\n1000000000\n2000000000\n3000000000\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] MAGANGLE\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\nBEGIN\n1E0,90E0\n2E0,-45E0\n3E0,0E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
+
+        #[test]
+        fn at_least_written_length() {
+            let record = setup();
+            let options = WriteOptions::default();
+            assert!(record.estimated_write_size(&options) >= written_len(&record, &options));
+        }
+
+        #[test]
+        fn at_least_written_length_aligned() {
+            let mut record = setup();
+            record.data[0].samples[0] = Complex::new(-2.2250738585072014E-308, -f64::MAX);
+            for options in [
+                WriteOptions {
+                    align_data: true,
+                    ..WriteOptions::default()
+                },
+                WriteOptions {
+                    normalize_exponent: true,
+                    normalize_formats: true,
+                    ..WriteOptions::default()
+                },
+                WriteOptions {
+                    exponent_style: ExponentStyle::Engineering {
+                        mantissa_digits: 30,
+                    },
+                    ..WriteOptions::default()
+                },
+                WriteOptions {
+                    exponent_style: ExponentStyle::Engineering {
+                        mantissa_digits: 30,
+                    },
+                    align_data: true,
+                    ..WriteOptions::default()
+                },
+            ] {
+                assert!(record.estimated_write_size(&options) >= written_len(&record, &options));
+            }
+        }
+
+        #[test]
+        fn header_exact() {
+            let mut record = setup();
+            record.data.clear();
+            let options = WriteOptions::default();
+            assert_eq!(
+                record.estimated_write_size(&options),
+                written_len(&record, &options)
+            );
+        }
+    }
+
+    mod test_write_with_options {
+        use super::*;
+
+        fn write(contents: &str, options: &WriteOptions) -> String {
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, options)
+                .unwrap();
+            String::from_utf8(written).unwrap()
+        }
+
+        const FIRST: &str = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1000000000\n0.0025\nVAR_LIST_END\nDATA S RI\nBEGIN\n0.5,-12.5\n100,0\nEND\n";
+        const SECOND: &str = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1.0E+09\n25e-4\nVAR_LIST_END\nDATA S RI\nBEGIN\n5.0E-1, -1.25e1\n1E2,0.000\nEND\n";
+
+        #[test]
+        fn normalize_exponent() {
+            let options = WriteOptions {
+                normalize_exponent: true,
+                ..WriteOptions::default()
+            };
+            let first = write(FIRST, &options);
+            assert_eq!(first, write(SECOND, &options));
+            assert!(first.contains("VAR_LIST_BEGIN\n1E9\n2.5E-3\nVAR_LIST_END\n"));
+            assert!(first.contains("BEGIN\n5E-1,-1.25E1\n1E2,0E0\nEND\n"));
+        }
+
+        #[test]
+        fn interleaved_block_layout() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E0\n2E0\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\n7E0,8E0\nEND\n";
+            let options = WriteOptions {
+                block_layout: BlockLayout::Interleaved,
+                ..WriteOptions::default()
+            };
+            let result = write(contents, &options);
+            assert!(result
+                .ends_with("DATA S[2,1] RI\nBEGIN\n1E0,2E0\n5E0,6E0\n3E0,4E0\n7E0,8E0\nEND\n"));
+        }
+
+        #[test]
+        fn interleaved_block_layout_lengths_differ() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E0\n2E0\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\n7E0,8E0\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            record.data[1].samples.pop();
+            let options = WriteOptions {
+                block_layout: BlockLayout::Interleaved,
+                ..WriteOptions::default()
+            };
+            let mut written: Vec<u8> = vec![];
+            match record.to_writer_with_options(&mut written, &options) {
+                Err(Error::RecordError(RecordError::DataArrayLengthsDiffer(2, 1))) => (),
+                e => panic!("{:?}", e),
+            }
+            assert!(written.is_empty());
+        }
+
+        #[test]
+        fn var_list_exponential() {
+            let options = WriteOptions {
+                var_list_style: VarListStyle::Exponential,
+                ..WriteOptions::default()
+            };
+            let result = write(FIRST, &options);
+            assert!(result.contains("VAR_LIST_BEGIN\n1E9\n2.5E-3\nVAR_LIST_END\n"));
+            assert_eq!(result, write(SECOND, &options));
+
+            let options = WriteOptions {
+                var_list_style: VarListStyle::Exponential,
+                exponent_style: ExponentStyle::Engineering { mantissa_digits: 2 },
+                ..WriteOptions::default()
+            };
+            let result = write(FIRST, &options);
+            assert!(result.contains("VAR_LIST_BEGIN\n1.00E+009\n2.50E-003\nVAR_LIST_END\n"));
+        }
+
+        #[test]
+        fn var_list_fixed() {
+            let options = WriteOptions {
+                var_list_style: VarListStyle::Fixed { decimals: 4 },
+                normalize_exponent: true,
+                ..WriteOptions::default()
+            };
+            let result = write(FIRST, &options);
+            assert!(result.contains("VAR_LIST_BEGIN\n1000000000.0000\n0.0025\nVAR_LIST_END\n"));
+            // Data pairs are unaffected
+            assert!(result.contains("BEGIN\n5E-1,-1.25E1\n1E2,0E0\nEND\n"));
+            let record = Record::from_reader(&mut result.as_bytes()).unwrap();
+            assert_eq!(record.header.independent_variable.data, vec![1e9, 0.0025]);
+        }
+
+        #[test]
+        fn default_options() {
+            let result = write(FIRST, &WriteOptions::default());
+            assert!(result.contains("VAR_LIST_BEGIN\n1000000000\n0.0025\nVAR_LIST_END\n"));
+        }
+
+        #[test]
+        fn normalize_formats() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ mag 1\nVAR_LIST_BEGIN\n10\nVAR_LIST_END\nDATA S ri\nDATA T custom\nBEGIN\n1E0,2E0\nEND\nBEGIN\n1E0,2E0\nEND\n";
+            let options = WriteOptions {
+                normalize_formats: true,
+                ..WriteOptions::default()
+            };
+            let result = write(contents, &options);
+            assert!(result.contains("VAR FREQ MAG 1\n"));
+            assert!(result.contains("DATA S RI\nDATA T custom\n"));
+
+            let result = write(contents, &WriteOptions::default());
+            assert!(result.contains("VAR FREQ mag 1\n"));
+            assert!(result.contains("DATA S ri\nDATA T custom\n"));
+        }
+
+        #[test]
+        fn strict_text_splits_comments() {
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record
+                .header
+                .comments
+                .push(String::from("first\nsecond\r\nthird\rfourth"));
+            let options = WriteOptions {
+                strict_text: true,
+                ..WriteOptions::default()
+            };
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\n!first\n!second\n!third\n!fourth\n"));
+
+            let result = Record::from_reader(&mut written.as_bytes()).unwrap();
+            assert_eq!(
+                result.header.comments,
+                vec!["first", "second", "third", "fourth"]
+            );
+        }
+
+        #[test]
+        fn strict_text_rejects_control_characters() {
+            let options = WriteOptions {
+                strict_text: true,
+                ..WriteOptions::default()
+            };
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record.header.name = String::from("MEM\rORY");
+            let mut written: Vec<u8> = vec![];
+            match record.to_writer_with_options(&mut written, &options) {
+                Err(Error::WriteError(WriteError::ControlCharacterInName(name))) => {
+                    assert_eq!(name, "MEM\rORY")
+                }
+                e => panic!("{:?}", e),
+            }
+
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record.data[0].name = String::from("S\u{0}");
+            let mut written: Vec<u8> = vec![];
+            match record.to_writer_with_options(&mut written, &options) {
+                Err(Error::WriteError(WriteError::ControlCharacterInName(_))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        mod test_strict_text {
+            use super::*;
+
+            fn write_strict(record: &Record) -> Result<()> {
+                let options = WriteOptions {
+                    strict_text: true,
+                    ..WriteOptions::default()
+                };
+                let mut written: Vec<u8> = vec![];
+                record.to_writer_with_options(&mut written, &options)
+            }
+
+            fn assert_rejects_text(record: &Record, text: &str) {
+                match write_strict(record) {
+                    Err(Error::WriteError(WriteError::ControlCharacterInText(t))) => {
+                        assert_eq!(t, text)
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            fn assert_rejects_name(record: &Record, name: &str) {
+                match write_strict(record) {
+                    Err(Error::WriteError(WriteError::ControlCharacterInName(n))) => {
+                        assert_eq!(n, name)
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            fn setup() -> Record {
+                Record::from_reader(&mut FIRST.as_bytes()).unwrap()
+            }
+
+            #[test]
+            fn accepts_plain_text() {
+                let mut record = setup();
+                record.header.constants.push(Constant::new("A", "1 2"));
+                record.header.unknown_lines.push(String::from("EXTRA 1"));
+                assert!(write_strict(&record).is_ok());
+            }
+
+            #[test]
+            fn version() {
+                let mut record = setup();
+                record.header.version = String::from("A.01\n.00");
+                assert_rejects_text(&record, "A.01\n.00");
+            }
+
+            #[test]
+            fn var_format() {
+                let mut record = setup();
+                record.header.independent_variable.format = String::from("MAG\r");
+                assert_rejects_text(&record, "MAG\r");
+            }
+
+            #[test]
+            fn data_format() {
+                let mut record = setup();
+                record.data[0].format = String::from("R\u{0}I");
+                assert_rejects_text(&record, "R\u{0}I");
+            }
+
+            #[test]
+            fn constant_name() {
+                let mut record = setup();
+                record.header.constants.push(Constant::new("A\nB", "1"));
+                assert_rejects_name(&record, "A\nB");
+            }
+
+            #[test]
+            fn constant_value() {
+                let mut record = setup();
+                record.header.constants.push(Constant::new("A", "1\n2"));
+                assert_rejects_text(&record, "1\n2");
+            }
+
+            #[test]
+            fn device_name() {
+                let mut record = setup();
+                record.header.add_device("NA\n", "VERSION 1");
+                assert_rejects_name(&record, "NA\n");
+            }
+
+            #[test]
+            fn device_entry() {
+                let mut record = setup();
+                record.header.add_device("NA", "VERSION\r1");
+                assert_rejects_text(&record, "VERSION\r1");
+            }
+
+            #[test]
+            fn unknown_line() {
+                let mut record = setup();
+                record.header.unknown_lines.push(String::from("EXTRA\n1"));
+                assert_rejects_text(&record, "EXTRA\n1");
+            }
+
+            #[test]
+            fn default_options_write_as_is() {
+                let mut record = setup();
+                record.header.constants.push(Constant::new("A", "1\n2"));
+                let mut written: Vec<u8> = vec![];
+                record.to_writer(&mut written).unwrap();
+                assert!(String::from_utf8(written)
+                    .unwrap()
+                    .contains("CONSTANT A 1\n2\n"));
+            }
+        }
+
+        #[test]
+        fn comment_keyword_style() {
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record.header.comments = vec![String::from("first"), String::from("second")];
+            let options = WriteOptions {
+                comment_keyword_style: CommentStyle::Keyword,
+                ..WriteOptions::default()
+            };
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\nCOMMENT first\nCOMMENT second\n"));
+            assert!(!written.contains('!'));
+
+            let result = Record::from_reader(&mut written.as_bytes()).unwrap();
+            assert_eq!(result, record);
+
+            let options = WriteOptions {
+                strict_text: true,
+                ..options
+            };
+            record.header.comments = vec![String::from("a\nb")];
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\nCOMMENT a\nCOMMENT b\n"));
+        }
+
+        #[test]
+        fn engineering_exponent() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1234560000\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n1.23456E9,-1.5E-3\n0,1E0\nEND\n";
+            let options = WriteOptions {
+                exponent_style: ExponentStyle::Engineering { mantissa_digits: 5 },
+                ..WriteOptions::default()
+            };
+            let result = write(contents, &options);
+            assert!(result.contains("\nVAR_LIST_BEGIN\n1.23456E+009\n2.00000E+009\nVAR_LIST_END\n"));
+            assert!(result
+                .contains("\nBEGIN\n1.23456E+009,-1.50000E-003\n0.00000E+000,1.00000E+000\nEND\n"));
+
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let result = Record::from_reader(&mut result.as_bytes()).unwrap();
+            assert_eq!(result, record);
+        }
+
+        #[test]
+        fn engineering_exponent_aligned() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,-2E0\nEND\n";
+            let options = WriteOptions {
+                exponent_style: ExponentStyle::Engineering { mantissa_digits: 2 },
+                align_data: true,
+                ..WriteOptions::default()
+            };
+            let result = write(contents, &options);
+            assert!(result.contains("\n1.00E+000,               -2.00E+000\n"));
+        }
+
+        #[test]
+        fn validate_formats() {
+            let options = WriteOptions {
+                validate_formats: true,
+                ..WriteOptions::default()
+            };
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+
+            record.data[0].format = String::from("BOGUS");
+            let mut written: Vec<u8> = vec![];
+            match record.to_writer_with_options(&mut written, &options) {
+                Err(Error::WriteError(WriteError::UnknownFormat(name, format))) => {
+                    assert_eq!(name, record.data[0].name);
+                    assert_eq!(format, "BOGUS");
+                }
+                e => panic!("{:?}", e),
+            }
+
+            // Permissive by default
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+        }
+
+        #[test]
+        fn validate_var_format() {
+            let options = WriteOptions {
+                validate_formats: true,
+                ..WriteOptions::default()
+            };
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record.header.independent_variable.format = String::new();
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+
+            record.header.independent_variable.format = String::from("Hz");
+            match record.to_writer_with_options(&mut written, &options) {
+                Err(Error::WriteError(WriteError::UnknownFormat(name, format))) => {
+                    assert_eq!(name, record.header.independent_variable.name);
+                    assert_eq!(format, "Hz");
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn text_unchanged_by_default() {
+            let mut record = Record::from_reader(&mut FIRST.as_bytes()).unwrap();
+            record.header.comments.push(String::from("first\nsecond"));
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\n!first\nsecond\n"));
+        }
+
+        #[test]
+        fn align_data() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n1E0,2E0\n-1.25E-10,3E0\n-2.2250738585072014E-308,-4E0\nEND\n";
+            let options = WriteOptions {
+                align_data: true,
+                ..WriteOptions::default()
+            };
+            let result = write(contents, &options);
+            let pairs: Vec<&str> = result
+                .lines()
+                .skip_while(|l| *l != "BEGIN")
+                .skip(1)
+                .take(3)
+                .collect();
+            assert_eq!(
+                pairs,
+                [
+                    "1E0,                     2E0",
+                    "-1.25E-10,               3E0",
+                    "-2.2250738585072014E-308,-4E0",
+                ]
+            );
+            for pair in pairs {
+                assert!(pair[..ALIGNED_REAL_WIDTH].ends_with([',', ' ']));
+                assert!(!pair[ALIGNED_REAL_WIDTH..].starts_with(' '));
+            }
+
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let result = Record::from_reader(&mut result.as_bytes()).unwrap();
+            assert_eq!(result, record);
+        }
+
+        #[test]
+        fn angle_unit_round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S MAGANGLE\nBEGIN\n1E0,1.5E0\nEND\n";
+            let reader_options = ReaderOptions {
+                angle_unit: AngleUnit::Radians,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &reader_options)
+                    .unwrap();
+            approx::assert_relative_eq!(record.data[0].samples[0].arg(), 1.5);
+
+            let options = WriteOptions {
+                angle_unit: AngleUnit::Radians,
+                ..WriteOptions::default()
+            };
+            let mut written: Vec<u8> = vec![];
+            record
+                .to_writer_with_options(&mut written, &options)
+                .unwrap();
+            let written = String::from_utf8(written).unwrap();
+            let mut lines = written.lines().skip_while(|l| *l != "BEGIN").skip(1);
+            match Keyword::from_str(lines.next().unwrap()) {
+                Ok(Keyword::DataPair { real, imag }) => {
+                    approx::assert_relative_eq!(real, 1.);
+                    approx::assert_relative_eq!(imag, 1.5);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test_read {
+        use super::*;
+
+        #[test]
+        fn from_buf_reader() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
+            let reader = std::io::BufReader::new(contents.as_bytes());
+            let result = Record::from_buf_reader(reader).unwrap();
+            let expected = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(result, expected);
+        }
+
+        #[test]
+        fn from_buf_reader_with_options() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\n";
+            let options = ReaderOptions {
+                allow_unterminated_final_block: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_buf_reader_with_options(contents.as_bytes(), &options).unwrap();
+            assert_eq!(record.data[0].samples.len(), 1);
+        }
+
+        #[test]
+        fn progress_is_reported() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
+            let total = contents.len() as u64;
+            let mut reports = vec![];
+            let record =
+                Record::from_reader_with_progress(&mut contents.as_bytes(), Some(total), |n| {
+                    reports.push(n)
+                });
+            assert_eq!(
+                record.unwrap(),
+                Record::from_reader(&mut contents.as_bytes()).unwrap()
+            );
+            assert!(!reports.is_empty());
+            assert_eq!(reports.last(), Some(&total));
+            assert!(reports.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        #[test]
+        fn progress_without_total() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let mut reports = vec![];
+            Record::from_reader_with_progress(&mut contents.as_bytes(), None, |n| reports.push(n))
+                .unwrap();
+            assert_eq!(reports, vec![contents.len() as u64]);
+        }
+
+        #[test]
+        fn cannot_read_empty_record() {
+            match Record::from_reader(&mut "".as_bytes()) {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn cannot_read_blank_lines_only() {
+            match Record::from_reader(&mut "\n\n  \n".as_bytes()) {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn unknown_keyword_rejected_by_default() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nSWEEP_TYPE LOG\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::LineError(2, ParseError::BadKeyword(_)))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn unknown_keyword_round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nSWEEP_TYPE LOG\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let options = ReaderOptions {
+                preserve_unknown_keywords: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            assert_eq!(record.header.unknown_lines, vec!["SWEEP_TYPE LOG"]);
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\nSWEEP_TYPE LOG\n"));
+            let result =
+                Record::from_reader_with_options(&mut written.as_bytes(), &options).unwrap();
+            assert_eq!(result, record);
+        }
+
+        #[test]
+        fn unknown_keyword_written_after_devices() {
+            let contents = "CITIFILE A.01.00\nSWEEP_TYPE LOG\nNAME MEMORY\n#NA VERSION 1\n!A comment\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let options = ReaderOptions {
+                preserve_unknown_keywords: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.starts_with("CITIFILE A.01.00\nNAME MEMORY\n"));
+            assert!(written.contains("\n!A comment\n#NA VERSION 1\nSWEEP_TYPE LOG\nDATA S RI\n"));
+        }
+
+        #[test]
+        fn unknown_keyword_inside_data_block() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n1E0,2E0\ngarbage\n3E0,4E0\nEND\n";
+            let options = ReaderOptions {
+                preserve_unknown_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::LineError(6, ParseError::BadKeyword(line)))) => {
+                    assert_eq!(line, "garbage")
+                }
+                e => panic!("{:?}", e),
+            }
+
+            let (record, errors) =
+                Record::read_collecting_errors(&mut contents.as_bytes(), &options);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(record.unwrap().data[0].samples.len(), 2);
+        }
+
+        #[test]
+        fn unknown_keyword_inside_var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nVAR_LIST_BEGIN\n1E9\ngarbage\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let options = ReaderOptions {
+                preserve_unknown_keywords: true,
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::LineError(5, ParseError::BadKeyword(_)))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn line_too_long() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1.00000000000000000000E0,2E0\nEND\n";
+            let options = ReaderOptions {
+                max_line_bytes: Some(16),
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::LineTooLong(5, 16))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn seg_list_dedup_boundaries() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 5\nSEG_LIST_BEGIN\nSEG 10 20 3\nSEG 20 40 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\n5,0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::DataBlockWrongLength(14, 0, 6, 5))) => (),
+                e => panic!("{:?}", e),
+            }
+
+            let options = ReaderOptions {
+                dedup_seg_boundaries: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            assert_array_relative_eq!(
+                record.header.independent_variable.data,
+                [10., 15., 20., 30., 40.]
+            );
+        }
+
+        #[test]
+        fn device_and_comment_between_data_blocks() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\nEND\n#NA REGISTER 1\n!Second block\nBEGIN\n3E0,4E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.header.devices.len(), 1);
+            assert_eq!(record.header.devices[0].name, "NA");
+            assert_eq!(record.header.devices[0].entries, vec!["REGISTER 1"]);
+            assert_eq!(record.header.comments, vec!["Second block"]);
+            assert_complex_array_relative_eq!(record.data[1].samples, [Complex::new(3., 4.)]);
+        }
+
+        #[test]
+        fn device_inside_data_block() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n#NA REGISTER 1\n1E0,2E0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::OutOfOrderKeyword(keyword, section))) => {
+                    assert!(matches!(keyword, Keyword::Device { .. }));
+                    assert_eq!(section, Section::Data);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn data_pair_before_begin() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\n1E0,2E0\nBEGIN\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(
+                    e @ Error::ReadError(ReadError::OutOfOrderKeyword(
+                        Keyword::DataPair { .. },
+                        Section::Header,
+                    )),
+                ) => {
+                    assert!(format!("{}", e).contains("data pair found outside a BEGIN/END block"))
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn max_points_seg_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nSEG_LIST_BEGIN\nSEG 10 20 18446744073709551615\nSEG_LIST_END\n";
+            let options = ReaderOptions {
+                max_points: Some(1000),
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::TooManyPoints(1000))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn max_points_seg_list_default() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nSEG_LIST_BEGIN\nSEG 1.0 2.0 4000000000000000000\nSEG_LIST_END\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::TooManyPoints(16777216))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn max_points_seg_list_dedup() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 5\nSEG_LIST_BEGIN\nSEG 10 20 3\nSEG 20 40 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\n5,0\nEND\n";
+            let options = ReaderOptions {
+                max_points: Some(5),
+                dedup_seg_boundaries: true,
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            assert_eq!(record.header.independent_variable.data.len(), 5);
+
+            let options = ReaderOptions {
+                max_points: Some(5),
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::TooManyPoints(5))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn max_points_var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nVAR_LIST_BEGIN\n10\n20\n30\nVAR_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\nEND\n";
+            let options = ReaderOptions {
+                max_points: Some(2),
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::TooManyPoints(2))) => (),
+                e => panic!("{:?}", e),
+            }
+
+            let options = ReaderOptions {
+                max_points: Some(3),
+                ..ReaderOptions::default()
+            };
+            let record =
+                Record::from_reader_with_options(&mut contents.as_bytes(), &options).unwrap();
+            assert_eq!(record.header.independent_variable.data.len(), 3);
+        }
+
+        #[test]
+        fn max_points_data() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let options = ReaderOptions {
+                max_points: Some(1),
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::TooManyPoints(1))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn annotated_format() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S[1,1] MAGANGLE[50]\nBEGIN\n2E0,9E1\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.data[0].format, "MAGANGLE[50]");
+            assert_eq!(record.data[0].format_annotation(), Some("50"));
+            assert_complex_array_relative_eq!(record.data[0].samples, [Complex::new(0., 2.)]);
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\nDATA S[1,1] MAGANGLE[50]\n"));
+        }
+
+        #[test]
+        fn independent_variable_source_var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n10\n20\nVAR_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.independent_variable_source(), VarSource::VarList);
+        }
+
+        #[test]
+        fn independent_variable_source_seg_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nSEG_LIST_BEGIN\nSEG 10 20 2\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.independent_variable_source(), VarSource::SegList);
+        }
+
+        #[test]
+        fn independent_variable_source_not_compared() {
+            let var_list = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let seg_list = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nSEG_LIST_BEGIN\nSEG 1E9 2E9 2\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let from_var_list = Record::from_reader(&mut var_list.as_bytes()).unwrap();
+            let from_seg_list = Record::from_reader(&mut seg_list.as_bytes()).unwrap();
+            assert_ne!(
+                from_var_list.independent_variable_source(),
+                from_seg_list.independent_variable_source()
+            );
+            assert_eq!(from_var_list, from_seg_list);
+        }
+
+        #[test]
+        fn seg_list_linear() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nSEG_LIST_BEGIN\nSEG LIN 1e9 4e9 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(
+                record.header.independent_variable.data,
+                vec![1e9, 2.5e9, 4e9]
+            );
+        }
+
+        #[test]
+        fn seg_list_logarithmic() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 4\nSEG_LIST_BEGIN\nSEG LOG 1e9 1e12 4\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let data = &record.header.independent_variable.data;
+            assert_eq!(data.len(), 4);
+            for (v, expected) in data.iter().zip([1e9, 1e10, 1e11, 1e12].iter()) {
+                approx::assert_relative_eq!(*v, *expected, max_relative = 1e-12);
+            }
+        }
+
+        #[test]
+        fn seg_list_logarithmic_zero_end() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 4\nSEG_LIST_BEGIN\nSEG LOG 0.0 1e12 4\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::InvalidLogSegment(4, first, last))) => {
+                    assert_eq!(first, 0.);
+                    assert_eq!(last, 1e12);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn seg_list_logarithmic_mixed_sign() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 4\nSEG_LIST_BEGIN\nSEG LOG -1e9 1e12 4\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::InvalidLogSegment(4, _, _))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn seg_list_logarithmic_negative() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nSEG_LIST_BEGIN\nSEG LOG -1e9 -1e11 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let data = &record.header.independent_variable.data;
+            approx::assert_relative_eq!(data[1], -1e10, max_relative = 1e-12);
+        }
+
+        #[test]
+        fn seg_list_written_back() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 5\nSEG_LIST_BEGIN\nSEG 1e9 2e9 2\nSEG LOG 1e10 1e12 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\n4,0\n5,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written
+                .contains("SEG_LIST_BEGIN\nSEG 1E9 2E9 2\nSEG LOG 1E10 1E12 3\nSEG_LIST_END\n"));
+            assert!(!written.contains("VAR_LIST_BEGIN"));
+
+            let reread = Record::from_reader(&mut written.as_bytes()).unwrap();
+            assert_eq!(
+                reread.header.independent_variable,
+                record.header.independent_variable
+            );
+        }
+
+        #[test]
+        fn seg_list_whole_numbers_round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nSEG_LIST_BEGIN\nSEG 0.0 10.0 3\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\n3,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("SEG_LIST_BEGIN\nSEG 0E0 1E1 3\nSEG_LIST_END\n"));
+
+            let reread = Record::from_reader(&mut written.as_bytes()).unwrap();
+            assert_eq!(reread, record);
+            assert_eq!(reread.independent_variable_source(), VarSource::SegList);
+        }
+
+        #[test]
+        fn seg_list_engineering_exponent() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nSEG_LIST_BEGIN\nSEG 1e9 2e9 2\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let options = WriteOptions {
+                exponent_style: ExponentStyle::Engineering { mantissa_digits: 2 },
+                ..WriteOptions::default()
+            };
+
+            let written = record.to_string_with_options(&options).unwrap();
+            assert!(written.contains("SEG 1.00E+009 2.00E+009 2\n"));
+            let reread = Record::from_str(&written).unwrap();
+            assert_eq!(reread, record);
+        }
+
+        #[test]
+        fn seg_list_changed_written_as_var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nSEG_LIST_BEGIN\nSEG 1e9 2e9 2\nSEG_LIST_END\nDATA S RI\nBEGIN\n1,0\n2,0\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            record.header.independent_variable.data[1] = 3e9;
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("VAR_LIST_BEGIN\n"));
+            assert!(!written.contains("SEG_LIST_BEGIN"));
+        }
+
+        #[test]
+        fn independent_variable_source_length_only() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.independent_variable_source(), VarSource::LengthOnly);
+        }
+
+        #[test]
+        fn var_declared_length_zero() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 0\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.var_declared_length(), Some(0));
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            assert!(String::from_utf8(written)
+                .unwrap()
+                .contains("VAR FREQ MAG 0\n"));
+        }
+
+        #[test]
+        fn var_declared_length_populated() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.var_declared_length(), Some(2));
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            assert!(String::from_utf8(written)
+                .unwrap()
+                .contains("VAR FREQ MAG 2\n"));
+        }
+
+        #[test]
+        fn var_declared_length_built() {
+            assert_eq!(Record::new("A.01.00", "MEMORY").var_declared_length(), None);
+        }
+
+        #[test]
+        fn var_declared_length_reset_on_replace() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.var_declared_length(), Some(3));
+            record.data.clear();
+            record
+                .set_independent_variable(Var::new("FREQ", "MAG"))
+                .unwrap();
+            assert_eq!(record.var_declared_length(), None);
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            assert!(String::from_utf8(written)
+                .unwrap()
+                .contains("VAR FREQ MAG 0\n"));
+        }
+
+        #[test]
+        fn var_declared_length_not_written_once_cleared() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            record.header.independent_variable.data.clear();
+            record.data.clear();
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            assert!(String::from_utf8(written)
+                .unwrap()
+                .contains("VAR FREQ MAG 0\n"));
+        }
+
+        #[test]
+        fn line_within_limit() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let options = ReaderOptions {
+                max_line_bytes: Some(16),
+                ..ReaderOptions::default()
+            };
+            let result = Record::from_reader_with_options(&mut contents.as_bytes(), &options);
+            let expected = Record::from_reader(&mut contents.as_bytes());
+            assert_eq!(result.unwrap(), expected.unwrap());
+        }
+
+        #[test]
+        fn stray_var_list_end() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::UnmatchedListEnd(Keyword::VarListEnd))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn invalid_utf8_comment() {
+            let mut contents = b"CITIFILE A.01.00\nNAME MEMORY\n!25".to_vec();
+            contents.push(0xb0);
+            contents.extend_from_slice(b"C\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n");
+            match Record::from_reader(&mut contents.as_slice()) {
+                Err(Error::ReadError(ReadError::ReadingError(e))) => {
+                    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+                }
+                e => panic!("{:?}", e),
+            }
+
+            for max_line_bytes in [None, Some(32)] {
+                let options = ReaderOptions {
+                    lossy_utf8: true,
+                    max_line_bytes,
+                    ..ReaderOptions::default()
+                };
+                match Record::from_reader_with_options(&mut contents.as_slice(), &options) {
+                    Ok(record) => {
+                        assert_eq!(record.header.comments, vec!["25\u{FFFD}C"]);
+                        assert_eq!(record.data[0].samples, vec![Complex::new(1., 2.)]);
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+        }
+
+        #[test]
+        fn cannot_read_complex_independent_variable() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR X MAGANGLE 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::ComplexIndependentVariableUnsupported(format))) => {
+                    assert_eq!(format, "MAGANGLE")
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn short_second_block() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(e) => {
+                    assert_eq!(e.line(), Some(11));
+                    match e {
+                        Error::ReadError(ReadError::DataBlockWrongLength(11, 1, 2, 1)) => (),
+                        e => panic!("{:?}", e),
+                    }
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn cannot_read_version_only() {
+            match Record::from_reader(&mut "CITIFILE A.01.00\n".as_bytes()) {
+                Err(Error::ReadError(ReadError::NoName)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn succeed_on_multiple_new_lines() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n\n\n\n\n\n\n\n\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Ok(_) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn succeed_on_whitespace_new_lines() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n      \n\n\n\n\n\n\n\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Ok(_) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn fail_on_missing_final_end() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::UnexpectedEndOfRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn lenient_on_missing_final_end() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n";
+            let options = ReaderOptions {
+                allow_unterminated_final_block: true,
+                require_version_first: false,
+                reject_non_finite: false,
+                case_insensitive_keywords: false,
+                angle_unit: AngleUnit::Degrees,
+                preserve_unknown_keywords: false,
+                max_line_bytes: None,
+                max_points: None,
+                dedup_seg_boundaries: false,
+                decimal_comma: false,
+                lossy_utf8: false,
+                comment_prefix: None,
+                trim_whitespace: false,
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Ok(record) => {
+                    assert_eq!(record.data.len(), 1);
+                    assert_complex_array_relative_eq!(
+                        record.data[0].samples,
+                        [
+                            Complex {
+                                re: -0.0354545,
+                                im: -0.00138601
+                            },
+                            Complex {
+                                re: 0.00023491,
+                                im: -0.00139883
+                            },
+                        ]
+                    );
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn complex_data_round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S[1,1] COMPLEX\nBEGIN\n1E0,-2E0\n3E-1,4E-1\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.data[0].format, "COMPLEX");
+            assert_complex_array_relative_eq!(
+                record.data[0].samples,
+                [Complex { re: 1., im: -2. }, Complex { re: 0.3, im: 0.4 }]
+            );
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("DATA S[1,1] COMPLEX\nBEGIN\n1E0,-2E0\n3E-1,4E-1\nEND\n"));
+        }
+
+        mod test_out_of_order_message {
+            use super::*;
+
+            fn read_error_message(contents: &str) -> String {
+                match Record::from_reader(&mut contents.as_bytes()) {
+                    Err(e @ Error::ReadError(ReadError::OutOfOrderKeyword(_, _))) => {
+                        format!("{}", e)
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn header() {
+                let message = read_error_message("CITIFILE A.01.00\nEND\n");
+                assert!(message.contains("Keyword `END` is out of order (in Header section)"));
+            }
+
+            #[test]
+            fn data() {
+                let message =
+                    read_error_message("CITIFILE A.01.00\nDATA S RI\nBEGIN\nNAME MEMORY\n");
+                assert!(message.contains("Keyword `NAME MEMORY` is out of order (in Data section)"));
+            }
+
             #[test]
-            fn empty() {
-                let record = Record::default();
-                match record.get_var_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
+            fn var_list() {
+                let message = read_error_message("CITIFILE A.01.00\nVAR_LIST_BEGIN\nBEGIN\n");
+                assert!(message.contains("Keyword `BEGIN` is out of order (in VarList section)"));
+            }
+
+            #[test]
+            fn seq_list() {
+                let message = read_error_message("CITIFILE A.01.00\nSEG_LIST_BEGIN\nBEGIN\n");
+                assert!(message.contains("Keyword `BEGIN` is out of order (in SeqList section)"));
+            }
+        }
+
+        #[test]
+        fn name_before_version() {
+            let contents =
+                "NAME MEMORY\nCITIFILE A.01.00\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1,2\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Ok(record) => assert_eq!(record.header.version, "A.01.00"),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn name_before_version_strict() {
+            let contents =
+                "NAME MEMORY\nCITIFILE A.01.00\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1,2\nEND\n";
+            let options = ReaderOptions {
+                require_version_first: true,
+                ..ReaderOptions::default()
+            };
+            match Record::from_reader_with_options(&mut contents.as_bytes(), &options) {
+                Err(Error::ReadError(ReadError::VersionNotFirst(Keyword::Name(_)))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn magnitude_angle_data() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S MAGANGLE\nBEGIN\n1,90\n0.5,-180\nEND\n";
+            match Record::from_reader(&mut contents.as_bytes()) {
+                Ok(record) => {
+                    assert_eq!(record.data[0].format, "MAGANGLE");
+                    let samples = &record.data[0].samples;
+                    approx::assert_relative_eq!(samples[0].re, 0., epsilon = 1e-12);
+                    approx::assert_relative_eq!(samples[0].im, 1.);
+                    approx::assert_relative_eq!(samples[1].re, -0.5);
+                    approx::assert_relative_eq!(samples[1].im, 0., epsilon = 1e-12);
+                }
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[cfg(test)]
+        mod test_read_minimal_record {
+            use super::*;
+
+            fn setup() -> Result<Record> {
+                let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
+                Record::from_reader(&mut contents.as_bytes())
+            }
+
+            #[test]
+            fn name() {
+                match setup() {
+                    Ok(record) => assert_eq!(record.header.name, "MEMORY"),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn version() {
+                match setup() {
+                    Ok(record) => assert_eq!(record.header.version, "A.01.00"),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn comments() {
+                match setup() {
+                    Ok(record) => assert_eq!(record.header.comments.len(), 0),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn constants() {
+                match setup() {
+                    Ok(record) => assert_eq!(record.header.constants.len(), 0),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn devices() {
+                match setup() {
+                    Ok(record) => assert_eq!(record.header.devices.len(), 0),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn independent_variable() {
+                match setup() {
+                    Ok(record) => {
+                        assert_eq!(record.header.independent_variable.name, "FREQ");
+                        assert_eq!(record.header.independent_variable.format, "MAG");
+                        assert_eq!(record.header.independent_variable.data.len(), 0);
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn data() {
+                match setup() {
+                    Ok(record) => {
+                        assert_eq!(record.data.len(), 1);
+                        assert_eq!(record.data[0].name, "S");
+                        assert_eq!(record.data[0].format, "RI");
+                        assert_eq!(record.data[0].samples.len(), 3);
+                        assert_complex_array_relative_eq!(
+                            record.data[0].samples,
+                            [
+                                Complex {
+                                    re: -0.0354545,
+                                    im: -0.00138601
+                                },
+                                Complex {
+                                    re: 0.00023491,
+                                    im: -0.00139883
+                                },
+                                Complex {
+                                    re: 0.00200382,
+                                    im: -0.00140022
+                                },
+                            ]
+                        );
+                    }
                     e => panic!("{:?}", e),
                 }
             }
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        let expected = Record {
+            header: Header {
+                version: String::from("A.01.00"),
+                name: String::new(),
+                comments: vec![],
+                devices: vec![],
+                independent_variable: Var {
+                    name: String::new(),
+                    format: String::new(),
+                    data: vec![],
+                },
+                constants: vec![],
+                unknown_lines: vec![],
+                independent_variable_source: VarSource::LengthOnly,
+                var_declared_length: None,
+                seg_items: vec![],
+            },
+            data: vec![],
+        };
+        let result = Record::default();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_new() {
+        let expected = Record {
+            header: Header {
+                version: String::from("A.01.01"),
+                name: String::from("A_NAME"),
+                comments: vec![],
+                devices: vec![],
+                independent_variable: Var {
+                    name: String::new(),
+                    format: String::new(),
+                    data: vec![],
+                },
+                constants: vec![],
+                unknown_lines: vec![],
+                independent_variable_source: VarSource::LengthOnly,
+                var_declared_length: None,
+                seg_items: vec![],
+            },
+            data: vec![],
+        };
+        let result = Record::new("A.01.01", "A_NAME");
+        assert_eq!(result, expected);
+    }
+
+    mod test_read_header_only {
+        use super::*;
+
+        #[test]
+        fn minimal_record() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\nnot data\nEND\n";
+            let mut reader = contents.as_bytes();
+            match Record::read_header_only(&mut reader) {
+                Ok(header) => {
+                    let mut expected = Header::new("A.01.00", "MEMORY");
+                    expected.independent_variable = Var::new("FREQ", "MAG");
+                    expected.var_declared_length = Some(3);
+                    assert_eq!(header, expected);
+                }
+                e => panic!("{:?}", e),
+            }
+            assert_eq!(reader, b"not data\nEND\n");
+        }
+
+        #[test]
+        fn var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\nCONSTANT A 1\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n";
+            let header = Record::read_header_only(&mut contents.as_bytes()).unwrap();
+            assert_eq!(header.independent_variable.data, vec![1e9, 2e9]);
+            assert_eq!(header.comments, vec![String::from("A comment")]);
+            assert_eq!(header.constants, vec![Constant::new("A", "1")]);
+        }
+
+        #[test]
+        fn no_var() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nDATA S RI\nBEGIN\n";
+            match Record::read_header_only(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::NoIndependentVariable)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn unterminated_var_list() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nVAR_LIST_BEGIN\n1E9\n";
+            match Record::read_header_only(&mut contents.as_bytes()) {
+                Err(Error::ReadError(ReadError::UnexpectedEndOfRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn empty() {
+            match Record::read_header_only(&mut "".as_bytes()) {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_read_collecting_errors {
+        use super::*;
+
+        #[test]
+        fn two_bad_lines() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nBOGUS LINE\nVAR FREQ MAG 2\nCONSTANT A\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let (record, errors) =
+                Record::read_collecting_errors(&mut contents.as_bytes(), &ReaderOptions::default());
+            assert_eq!(errors.len(), 2);
+            match &errors[0] {
+                Error::ReadError(ReadError::LineError(2, ParseError::BadKeyword(line))) => {
+                    assert_eq!(line, "BOGUS LINE")
+                }
+                e => panic!("{:?}", e),
+            }
+            assert_eq!(errors[1].line(), Some(4));
+
+            let record = record.unwrap();
+            assert!(record.header.constants.is_empty());
+            assert_eq!(
+                record.data[0].samples,
+                vec![Complex::new(1., 2.), Complex::new(3., 4.)]
+            );
+        }
+
+        #[test]
+        fn no_errors() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let (record, errors) =
+                Record::read_collecting_errors(&mut contents.as_bytes(), &ReaderOptions::default());
+            assert!(errors.is_empty());
+            assert_eq!(
+                record.unwrap(),
+                Record::from_reader(&mut contents.as_bytes()).unwrap()
+            );
+        }
+
+        #[test]
+        fn skipped_pair_ends_read() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n1E0,2E0\n1E0,,2E0\nEND\n";
+            let (record, errors) =
+                Record::read_collecting_errors(&mut contents.as_bytes(), &ReaderOptions::default());
+            assert!(record.is_none());
+            assert_eq!(errors.len(), 2);
+            assert_eq!(errors[0].line(), Some(11));
+            match &errors[1] {
+                Error::ReadError(ReadError::DataBlockWrongLength(12, _, _, _)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn fatal_error() {
+            let contents = "CITIFILE A.01.00\nBOGUS\nEND\nNAME MEMORY\n";
+            let (record, errors) =
+                Record::read_collecting_errors(&mut contents.as_bytes(), &ReaderOptions::default());
+            assert!(record.is_none());
+            assert_eq!(errors.len(), 2);
+            match &errors[1] {
+                Error::ReadError(ReadError::OutOfOrderKeyword(Keyword::End, _)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_content_hash {
+        use super::*;
+
+        fn read(contents: &str) -> Record {
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
+
+        const FIRST: &str = "CITIFILE A.01.00\nNAME MEMORY\n#NA VERSION HP8510B.05.00\n#WVI A B\nCONSTANT A 1\nCONSTANT B 2\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1000000000\n2000000000\nVAR_LIST_END\nDATA S RI\nBEGIN\n0.1,-0.25\n0,3\nEND\n";
+
+        #[test]
+        fn float_formatting() {
+            let second = "CITIFILE A.01.00\nNAME MEMORY\n#NA VERSION HP8510B.05.00\n#WVI A B\nCONSTANT A 1\nCONSTANT B 2\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1.0E9\n2E+09\nVAR_LIST_END\nDATA S RI\nBEGIN\n1.00000000000001E-1,-2.5E-1\n-0E0,3.0E0\nEND\n";
+            assert_eq!(read(FIRST).content_hash(), read(second).content_hash());
+        }
+
+        #[test]
+        fn order_of_constants_and_devices() {
+            let second = "CITIFILE A.01.00\nNAME MEMORY\n#WVI A B\n#NA VERSION HP8510B.05.00\nCONSTANT B 2\nCONSTANT A 1\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1000000000\n2000000000\nVAR_LIST_END\nDATA S RI\nBEGIN\n0.1,-0.25\n0,3\nEND\n";
+            assert_eq!(read(FIRST).content_hash(), read(second).content_hash());
+        }
+
+        #[test]
+        fn different_value() {
+            let mut record = read(FIRST);
+            let hash = record.content_hash();
+            record.data[0].samples[1].im = 3.001;
+            assert_ne!(record.content_hash(), hash);
+        }
+
+        #[test]
+        fn different_name() {
+            let mut record = read(FIRST);
+            let hash = record.content_hash();
+            record.data[0].name = String::from("T");
+            assert_ne!(record.content_hash(), hash);
+        }
+
+        #[test]
+        fn digits() {
+            let mut record = read(FIRST);
+            let hash = record.content_hash_with_digits(3);
+            record.data[0].samples[1].im = 3.001;
+            assert_eq!(record.content_hash_with_digits(3), hash);
+            assert_ne!(record.content_hash_with_digits(6), hash);
+        }
+
+        #[test]
+        fn digits_clamped() {
+            let record = read(FIRST);
+            let hash = record.content_hash_with_digits(17);
+            assert_eq!(record.content_hash_with_digits(18), hash);
+            assert_eq!(record.content_hash_with_digits(usize::MAX), hash);
+            let hash = record.content_hash_with_digits(1);
+            assert_eq!(record.content_hash_with_digits(0), hash);
+        }
+
+        #[test]
+        fn stable() {
+            assert_eq!(Record::default().content_hash(), 0x5851_038e_6fcf_624c);
+        }
+    }
+
+    mod test_diff {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record
+                .header
+                .comments
+                .push(String::from("DATE: 2019.11.01"));
+            record.header.independent_variable = Var {
+                name: String::from("FREQ"),
+                format: String::from("MAG"),
+                data: vec![1e9, 2e9],
+            };
+            let mut array = DataArray::new("S[1,1]", "RI");
+            array.add_sample(1., 2.);
+            array.add_sample(3., 4.);
+            record.data.push(array);
+            let mut array = DataArray::new("S[2,1]", "RI");
+            array.add_sample(5., 6.);
+            array.add_sample(7., 8.);
+            record.data.push(array);
+            record
+        }
+
+        #[test]
+        fn same() {
+            let diff = setup().diff(&setup(), 0.);
+            assert!(diff.is_empty());
+        }
+
+        #[test]
+        fn within_epsilon() {
+            let mut other = setup();
+            other.data[0].samples[1].re += 1e-9;
+            other.header.independent_variable.data[0] += 1e-9;
+            assert!(setup().diff(&other, 1e-6).is_empty());
+        }
+
+        #[test]
+        fn one_sample() {
+            let mut other = setup();
+            other.data[1].samples[1].im += 0.5;
+            let diff = setup().diff(&other, 1e-6);
+            assert_eq!(
+                diff,
+                RecordDiff {
+                    sample_differences: vec![SampleDifference {
+                        array: String::from("S[2,1]"),
+                        index: 1,
+                        delta: 0.5,
+                    }],
+                    ..RecordDiff::default()
+                }
+            );
+            assert_eq!(
+                format!("{}", diff),
+                "Data array S[2,1] differs at point 1 by 5E-1\n"
+            );
+        }
+
+        #[test]
+        fn one_comment() {
+            let mut other = setup();
+            other.header.comments[0] = String::from("DATE: 2020.01.01");
+            let diff = setup().diff(&other, 1e-6);
+            assert_eq!(diff.header, vec!["comments"]);
+            assert!(diff.sample_differences.is_empty());
+            assert_eq!(format!("{}", diff), "Header differs in: comments\n");
+        }
+
+        #[test]
+        fn independent_variable() {
+            let mut other = setup();
+            other.header.independent_variable.data.push(3e9);
+            assert_eq!(
+                setup().diff(&other, 1e-6).header,
+                vec!["independent_variable"]
+            );
+        }
+
+        #[test]
+        fn renamed_array() {
+            let mut other = setup();
+            other.data[0].name = String::from("S11");
+            let diff = setup().diff(&other, 1e-6);
+            assert_eq!(
+                diff.renamed_arrays,
+                vec![(String::from("S[1,1]"), String::from("S11"))]
+            );
+            assert!(diff.added_arrays.is_empty());
+            assert!(diff.removed_arrays.is_empty());
+        }
+
+        #[test]
+        fn added_and_removed_arrays() {
+            let mut other = setup();
+            other.data.remove(0);
+            other.data.push(DataArray::new("S[2,2]", "RI"));
+            let diff = setup().diff(&other, 1e-6);
+            assert_eq!(diff.removed_arrays, vec!["S[1,1]"]);
+            assert_eq!(diff.added_arrays, vec!["S[2,2]"]);
+        }
+
+        #[test]
+        fn format_and_length() {
+            let mut other = setup();
+            other.data[0].format = String::from("COMPLEX");
+            other.data[0].samples.pop();
+            let diff = setup().diff(&other, 1e-6);
+            assert_eq!(diff.format_changes, vec!["S[1,1]"]);
+            assert_eq!(diff.length_changes, vec![(String::from("S[1,1]"), 2, 1)]);
+            assert!(diff.sample_differences.is_empty());
+        }
+    }
+
+    mod test_iter {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record
+        }
+
+        #[test]
+        fn iter() {
+            let record = setup();
+            let names: Vec<&str> = record.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(names, vec!["S[1,1]", "S[2,1]"]);
+        }
+
+        #[test]
+        fn into_iter() {
+            let record = setup();
+            let mut names: Vec<&str> = vec![];
+            for array in &record {
+                names.push(&array.name);
+            }
+            assert_eq!(names, vec!["S[1,1]", "S[2,1]"]);
+        }
 
-            #[test]
-            fn one() {
-                let mut record = Record::default();
-                record.header.independent_variable.data.push(1.);
-                match record.get_var_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::VarListBegin,
-                            Keyword::VarListItem(1.),
-                            Keyword::VarListEnd
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn empty() {
+            assert_eq!(Record::default().iter().count(), 0);
+        }
+
+        #[test]
+        fn enumerate_data() {
+            let record = setup();
+            for (i, array) in record.enumerate_data() {
+                assert_eq!(array, &record.data[i]);
             }
+            let indices: Vec<usize> = record.enumerate_data().map(|(i, _)| i).collect();
+            assert_eq!(indices, vec![0, 1]);
+        }
+    }
 
-            #[test]
-            fn multiple() {
-                let mut record = Record::default();
-                record.header.independent_variable.data.push(1.);
-                record.header.independent_variable.data.push(2.);
-                record.header.independent_variable.data.push(3.);
-                match record.get_var_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::VarListBegin,
-                            Keyword::VarListItem(1.),
-                            Keyword::VarListItem(2.),
-                            Keyword::VarListItem(3.),
-                            Keyword::VarListEnd
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+    mod test_format_consistency {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "MAGANGLE"));
+            record.data.push(DataArray::new("S[1,2]", "COMPLEX"));
+            record.data.push(DataArray::new("S[2,2]", "MAGANGLE"));
+            record
+        }
+
+        #[test]
+        fn arrays_with_format() {
+            let record = setup();
+            let names: Vec<&str> = record
+                .arrays_with_format("RI")
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect();
+            assert_eq!(names, vec!["S[1,1]", "S[1,2]"]);
+        }
+
+        #[test]
+        fn arrays_with_format_none() {
+            assert!(setup().arrays_with_format("DB").is_empty());
+        }
+
+        #[test]
+        fn arrays_not_in_format() {
+            assert_eq!(setup().arrays_not_in_format("RI"), vec!["S[2,1]", "S[2,2]"]);
+        }
+
+        #[test]
+        fn arrays_not_in_format_all_conform() {
+            let mut record = setup();
+            record.data.retain(|a| a.format == "MAGANGLE");
+            assert!(record.arrays_not_in_format("MAGANGLE").is_empty());
+        }
+    }
+
+    mod test_append_point {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record
+        }
+
+        #[test]
+        fn two_points() {
+            let mut record = setup();
+            record
+                .append_point(1e9, &[Complex::new(1., 2.), Complex::new(3., 4.)])
+                .unwrap();
+            record
+                .append_point(2e9, &[Complex::new(5., 6.), Complex::new(7., 8.)])
+                .unwrap();
+            assert_array_relative_eq!(record.header.independent_variable.data, [1e9, 2e9]);
+            assert_complex_array_relative_eq!(
+                record.data[0].samples,
+                [Complex::new(1., 2.), Complex::new(5., 6.)]
+            );
+            assert_complex_array_relative_eq!(
+                record.data[1].samples,
+                [Complex::new(3., 4.), Complex::new(7., 8.)]
+            );
+        }
+
+        #[test]
+        fn too_few_samples() {
+            let mut record = setup();
+            match record.append_point(1e9, &[Complex::new(1., 2.)]) {
+                Err(Error::RecordError(RecordError::WrongNumberOfSamples(2, 1))) => (),
+                e => panic!("{:?}", e),
             }
+            assert_eq!(record, setup());
         }
 
-        mod test_get_constants_keywords {
-            use super::*;
+        #[test]
+        fn too_many_samples() {
+            let mut record = setup();
+            let samples = [Complex::new(1., 2.); 3];
+            match record.append_point(1e9, &samples) {
+                Err(Error::RecordError(RecordError::WrongNumberOfSamples(2, 3))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
 
-            #[test]
-            fn empty() {
-                let record = Record::default();
-                match record.get_constants_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
-                    e => panic!("{:?}", e),
+    mod test_canonicalize {
+        use super::*;
+
+        fn messy() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "mag");
+            record.header.independent_variable.data = vec![3., 1., 2., 1.];
+            record.header.add_device("NA", "REGISTER 1");
+            record.header.add_device("WVI", "A B");
+            record.header.devices.push(Device {
+                name: String::from("NA"),
+                entries: vec![String::from("REGISTER 1")],
+            });
+            record.header.constants.push(Constant::new("A", "1"));
+            record.header.constants.push(Constant::new("B", "2"));
+            record.header.constants.push(Constant::new("A", "1"));
+            record.header.constants.push(Constant::new("A", "3"));
+            record.data.push(DataArray {
+                name: String::from("S[1,1]"),
+                format: String::from("ri"),
+                samples: vec![
+                    Complex::new(3., 0.),
+                    Complex::new(1., 0.),
+                    Complex::new(2., 0.),
+                    Complex::new(1., 1.),
+                ],
+            });
+            record.data.push(DataArray {
+                name: String::from("S[2,1]"),
+                format: String::from("custom"),
+                samples: vec![Complex::new(0., 3.); 4],
+            });
+            record
+        }
+
+        #[test]
+        fn normalizes() {
+            let mut record = messy();
+            record.canonicalize().unwrap();
+            assert_array_relative_eq!(record.header.independent_variable.data, [1., 1., 2., 3.]);
+            assert_complex_array_relative_eq!(
+                record.data[0].samples,
+                [
+                    Complex::new(1., 0.),
+                    Complex::new(1., 1.),
+                    Complex::new(2., 0.),
+                    Complex::new(3., 0.),
+                ]
+            );
+            assert_eq!(record.header.independent_variable.format, "MAG");
+            assert_eq!(record.data[0].format, "RI");
+            assert_eq!(record.data[1].format, "custom");
+            assert_eq!(record.header.devices.len(), 2);
+            assert_eq!(
+                record.header.constants,
+                vec![
+                    Constant::new("A", "1"),
+                    Constant::new("B", "2"),
+                    Constant::new("A", "3"),
+                ]
+            );
+        }
+
+        #[test]
+        fn idempotent() {
+            let mut once = messy();
+            once.canonicalize().unwrap();
+            let mut twice = once.clone();
+            twice.canonicalize().unwrap();
+            assert_eq!(once, twice);
+        }
+
+        #[test]
+        fn length_mismatch() {
+            let mut record = messy();
+            record.data[1].samples.pop();
+            match record.canonicalize() {
+                Err(Error::RecordError(RecordError::ArrayLengthMismatch(name, 4, 3))) => {
+                    assert_eq!(name, "S[2,1]")
                 }
+                e => panic!("{:?}", e),
             }
+            assert_eq!(
+                record.header.independent_variable.data,
+                vec![3., 1., 2., 1.]
+            );
+        }
 
-            #[test]
-            fn one() {
-                let mut record = Record::default();
-                record.header.constants.push(Constant {
-                    name: String::from("Name"),
-                    value: String::from("Value"),
-                });
-                match record.get_constants_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Constant {
-                            name: String::from("Name"),
-                            value: String::from("Value")
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn invalid() {
+            let mut record = messy();
+            record.header.name.clear();
+            match record.canonicalize() {
+                Err(Error::ReadError(ReadError::NoName)) => (),
+                e => panic!("{:?}", e),
             }
+            assert_eq!(record.data[0].format, "ri");
 
-            #[test]
-            fn two() {
-                let mut record = Record::default();
-                record.header.constants.push(Constant {
-                    name: String::from("Name A"),
-                    value: String::from("Value A"),
-                });
-                record.header.constants.push(Constant {
-                    name: String::from("Name B"),
-                    value: String::from("Value B"),
-                });
-                match record.get_constants_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Constant {
-                                name: String::from("Name A"),
-                                value: String::from("Value A")
-                            },
-                            Keyword::Constant {
-                                name: String::from("Name B"),
-                                value: String::from("Value B")
-                            }
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+            let mut record = messy();
+            record.data.clear();
+            record.header.independent_variable.data.clear();
+            match record.canonicalize() {
+                Err(Error::ReadError(ReadError::NoData)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_from_str {
+        use super::*;
+
+        const CONTENTS: &str = "CITIFILE A.01.00\r\nNAME MEMORY\n!A comment\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND";
+
+        #[test]
+        fn same_as_reader() {
+            let expected = Record::from_reader(&mut CONTENTS.as_bytes()).unwrap();
+            match CONTENTS.parse::<Record>() {
+                Ok(record) => assert_eq!(record, expected),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn line_too_long() {
+            let options = ReaderOptions {
+                max_line_bytes: Some(12),
+                ..ReaderOptions::default()
+            };
+            match Record::from_str_with_options(CONTENTS, &options) {
+                Err(Error::ReadError(ReadError::LineTooLong(0, 12))) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn empty() {
+            match Record::from_str("") {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_to_string_with_options {
+        use super::*;
+
+        #[test]
+        fn same_as_writer() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nDATA T MAGANGLE\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n1E0,90E0\n2E0,-45E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            for options in [
+                WriteOptions::default(),
+                WriteOptions {
+                    align_data: true,
+                    block_layout: BlockLayout::Interleaved,
+                    ..WriteOptions::default()
+                },
+            ]
+            .iter()
+            {
+                let mut expected: Vec<u8> = vec![];
+                record
+                    .to_writer_with_options(&mut expected, options)
+                    .unwrap();
+                let written = record.to_string_with_options(options).unwrap();
+                assert_eq!(written.as_bytes(), &expected[..]);
+            }
+        }
+
+        #[test]
+        fn invalid_record() {
+            let mut record = Record::default();
+            record.header.version = String::new();
+            match record.to_string_with_options(&WriteOptions::default()) {
+                Err(Error::WriteError(WriteError::NoVersion)) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_from_keywords {
+        use super::*;
+
+        #[test]
+        fn hand_built() {
+            let keywords = vec![
+                Keyword::CitiFile {
+                    version: String::from("A.01.00"),
+                },
+                Keyword::Name(String::from("MEMORY")),
+                Keyword::Var {
+                    name: String::from("FREQ"),
+                    format: String::from("MAG"),
+                    length: 2,
+                },
+                Keyword::VarListBegin,
+                Keyword::VarListItem(1e9),
+                Keyword::VarListItem(2e9),
+                Keyword::VarListEnd,
+                Keyword::Data {
+                    name: String::from("S"),
+                    format: String::from("RI"),
+                },
+                Keyword::Begin,
+                Keyword::DataPair { real: 1., imag: 2. },
+                Keyword::DataPair { real: 3., imag: 4. },
+                Keyword::End,
+            ];
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let expected = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            match Record::from_keywords(keywords) {
+                Ok(record) => assert_eq!(record, expected),
+                e => panic!("{:?}", e),
             }
         }
 
-        mod test_get_independent_variable_keywords {
-            use super::*;
+        #[test]
+        fn round_trip() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\n#NA VERSION HP8510B.05.00\nCONSTANT A 1\nVAR FREQ MAG 2\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\n7E0,8E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let keywords = record
+                .get_keywords(AngleUnit::Degrees, BlockLayout::PerArray)
+                .unwrap();
+            assert_eq!(Record::from_keywords(keywords).unwrap(), record);
+        }
 
-            #[test]
-            fn no_format() {
-                let mut record = Record::default();
-                record.header.independent_variable.name = String::from("Name");
-                match record.get_independent_variable_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Var {
-                            name: String::from("Name"),
-                            format: String::new(),
-                            length: 0
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn validates() {
+            let keywords = vec![Keyword::CitiFile {
+                version: String::from("A.01.00"),
+            }];
+            match Record::from_keywords(keywords) {
+                Err(Error::ReadError(ReadError::NoName)) => (),
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn format() {
-                let mut record = Record::default();
-                record.header.independent_variable.name = String::from("Name");
-                record.header.independent_variable.format = String::from("Format");
-                match record.get_independent_variable_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Var {
-                            name: String::from("Name"),
-                            format: String::from("Format"),
-                            length: 0
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn empty() {
+            match Record::from_keywords(vec![]) {
+                Err(Error::ReadError(ReadError::EmptyRecord)) => (),
+                e => panic!("{:?}", e),
             }
+        }
+    }
 
-            #[test]
-            fn length() {
-                let mut record = Record::default();
-                record.header.independent_variable.name = String::from("Name");
-                record.header.independent_variable.format = String::from("Format");
-                record.header.independent_variable.data = vec![0.; 10];
-                match record.get_independent_variable_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Var {
-                            name: String::from("Name"),
-                            format: String::from("Format"),
-                            length: 10
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+    mod test_group_delay {
+        use super::*;
+        use approx::assert_relative_eq;
+
+        fn setup(delay: f64) -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.header.independent_variable.seq(1e9, 2e9, 11);
+            let samples = record
+                .header
+                .independent_variable
+                .data
+                .iter()
+                .map(|f| Complex::from_polar(1., -2. * std::f64::consts::PI * f * delay))
+                .collect();
+            record.data.push(DataArray {
+                name: String::from("S[2,1]"),
+                format: String::from("RI"),
+                samples,
+            });
+            record
+        }
+
+        #[test]
+        fn linear_phase() {
+            // 0.25 ns delay turns the phase by 90° per 100 MHz step
+            let result = setup(0.25e-9).group_delay("S[2,1]").unwrap();
+            assert_eq!(result.len(), 11);
+            for v in result.iter() {
+                assert_relative_eq!(*v, 0.25e-9, max_relative = 1e-9);
             }
         }
 
-        mod test_get_devices_keywords {
-            use super::*;
+        #[test]
+        fn missing_array() {
+            assert_eq!(setup(1e-9).group_delay("S[1,1]"), None);
+        }
 
-            #[test]
-            fn empty() {
-                let record = Record::default();
-                match record.get_devices_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn empty_var() {
+            let mut record = setup(1e-9);
+            record.header.independent_variable.data.clear();
+            assert_eq!(record.group_delay("S[2,1]"), None);
+        }
+
+        #[test]
+        fn decreasing() {
+            let mut record = setup(0.25e-9);
+            record.header.independent_variable.data.reverse();
+            record.data[0].samples.reverse();
+            for v in record.group_delay("S[2,1]").unwrap().iter() {
+                assert_relative_eq!(*v, 0.25e-9, max_relative = 1e-9);
             }
+        }
 
-            #[test]
-            fn one_device_no_entry() {
-                let mut record = Record::default();
-                record.header.devices.push(Device {
-                    name: String::from(""),
-                    entries: vec![],
-                });
-                match record.get_devices_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn repeated_frequency() {
+            let mut record = setup(1e-9);
+            record.header.independent_variable.data[5] = 1.4e9;
+            assert_eq!(record.group_delay("S[2,1]"), None);
+            record.header.independent_variable.data = vec![1e9; 11];
+            assert_eq!(record.group_delay("S[2,1]"), None);
+        }
+    }
+
+    mod test_total_samples {
+        use super::*;
+
+        fn setup() -> Record {
+            Record::new("A.01.00", "MEMORY")
+                .with_comment("Note")
+                .with_constant("A", "1")
+                .with_data_array(DataArray::from_tuples("S11", "RI", vec![(0., 0.); 3]))
+                .with_data_array(DataArray::from_tuples("S21", "MAG", vec![(0., 0.); 5]))
+        }
+
+        #[test]
+        fn total_samples() {
+            assert_eq!(setup().total_samples(), 8);
+            assert_eq!(Record::default().total_samples(), 0);
+        }
+
+        #[test]
+        fn approx_memory_bytes() {
+            let mut record = setup();
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.header.independent_variable.data = vec![1., 2., 3.];
+            // 8 samples, 3 points, then the strings
+            let strings = "A.01.00MEMORYNoteA1FREQMAGS11RIS21MAG".len();
+            assert_eq!(record.approx_memory_bytes(), 8 * 16 + 3 * 8 + strings);
+        }
+    }
+
+    mod test_pipe {
+        use super::*;
+
+        const MINIMAL: &str =
+            "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+
+        #[test]
+        fn read_locked_input() {
+            // Stands in for the lock on standard input
+            let stdin = std::io::Cursor::new(MINIMAL.as_bytes());
+            match Record::from_buf_reader(stdin) {
+                Ok(record) => assert_eq!(record.data[0].samples, vec![Complex::new(1., 2.)]),
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn one_device() {
-                let mut record = Record::default();
-                record.header.devices.push(Device {
-                    name: String::from("Name"),
-                    entries: vec![String::from("entry")],
-                });
-                match record.get_devices_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Device {
-                            name: String::from("Name"),
-                            value: String::from("entry")
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn write_buffered() {
+            let record = Record::from_reader(&mut MINIMAL.as_bytes()).unwrap();
+            let mut written: Vec<u8> = vec![];
+            record.write_buffered(&mut written).unwrap();
+            assert_eq!(String::from_utf8(written).unwrap(), MINIMAL);
+        }
+    }
+
+    mod test_with {
+        use super::*;
+
+        #[test]
+        fn data_arrays() {
+            let first = DataArray::from_tuples("S[1,1]", "RI", vec![(1., 2.)]);
+            let second = DataArray::from_tuples("S[2,1]", "MAG", vec![(3., 0.)]);
+            let record = Record::default()
+                .with_data_array(first.clone())
+                .with_data_array(second.clone());
+            assert_eq!(record.data, vec![first, second]);
+            assert_eq!(record.header, Header::default());
+        }
+
+        #[test]
+        fn header() {
+            let record = Record::default()
+                .with_comment("A comment")
+                .with_constant("A", "1")
+                .with_device("NA", "VERSION HP8510B.05.00")
+                .with_device("NA", "REGISTER 1");
+            assert_eq!(record.header.comments, vec![String::from("A comment")]);
+            assert_eq!(record.header.constants, vec![Constant::new("A", "1")]);
+            assert_eq!(record.header.devices.len(), 1);
+            assert_eq!(
+                record.header.devices[0].entries,
+                vec![
+                    String::from("VERSION HP8510B.05.00"),
+                    String::from("REGISTER 1")
+                ]
+            );
+        }
+    }
+
+    mod test_validate_all {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert!(record.validate_all().is_empty());
+        }
+
+        #[test]
+        fn missing_name_version_and_data() {
+            let mut record = Record::new("", "");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            let errors = record.validate_all();
+            assert_eq!(errors.len(), 3);
+            match (&errors[0], &errors[1], &errors[2]) {
+                (ReadError::NoName, ReadError::NoVersion, ReadError::NoData) => (),
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn one_device_multiple_entries() {
-                let mut record = Record::default();
-                record.header.devices.push(Device {
-                    name: String::from("Name"),
-                    entries: vec![String::from("entry 1"), String::from("entry 2")],
-                });
-                match record.get_devices_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Device {
-                                name: String::from("Name"),
-                                value: String::from("entry 1")
-                            },
-                            Keyword::Device {
-                                name: String::from("Name"),
-                                value: String::from("entry 2")
-                            }
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn every_length_mismatch() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.header.independent_variable.data = vec![1., 2.];
+            for length in [2, 1, 3] {
+                let mut array = DataArray::new("S", "RI");
+                array.samples = vec![Complex::new(0., 0.); length];
+                record.data.push(array);
+            }
+            let errors = record.validate_all();
+            assert_eq!(errors.len(), 2);
+            match (&errors[0], &errors[1]) {
+                (
+                    ReadError::VarAndDataDifferentLengths(2, 1, 1),
+                    ReadError::VarAndDataDifferentLengths(2, 3, 2),
+                ) => (),
+                e => panic!("{:?}", e),
             }
+        }
+    }
 
-            #[test]
-            fn multiple_devices() {
-                let mut record = Record::default();
-                record.header.devices.push(Device {
-                    name: String::from("Name A"),
-                    entries: vec![String::from("entry 1")],
-                });
-                record.header.devices.push(Device {
-                    name: String::from("Name B"),
-                    entries: vec![String::from("entry 2")],
-                });
-                match record.get_devices_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Device {
-                                name: String::from("Name A"),
-                                value: String::from("entry 1")
-                            },
-                            Keyword::Device {
-                                name: String::from("Name B"),
-                                value: String::from("entry 2")
-                            }
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+    mod test_assert_valid {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            let contents =
+                "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes())
+                .unwrap()
+                .assert_valid();
+        }
+
+        #[test]
+        #[should_panic(expected = "Data array 0 has no format")]
+        fn write_error() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S", ""));
+            record.assert_valid();
+        }
+
+        #[test]
+        #[should_panic(expected = "Invalid record")]
+        fn read_error() {
+            Record::new("A.01.00", "MEMORY").assert_valid();
+        }
+    }
+
+    mod test_var_getters {
+        use super::*;
+
+        #[test]
+        fn blank() {
+            let record = Record::new("A.01.00", "MEMORY");
+            assert_eq!(record.var_name(), None);
+            assert_eq!(record.var_format(), None);
+        }
+
+        #[test]
+        fn populated() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            assert_eq!(record.var_name(), Some("FREQ"));
+            assert_eq!(record.var_format(), Some("MAG"));
+        }
+
+        #[test]
+        fn name_without_format() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("TIME", "");
+            assert_eq!(record.var_name(), Some("TIME"));
+            assert_eq!(record.var_format(), None);
+        }
+    }
+
+    mod test_frequency_unit {
+        use super::*;
+
+        #[test]
+        fn from_comment() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!DATE: 2019.11.01\n!FREQ_UNIT: GHZ\nVAR FREQ MAG 1\nDATA S RI\nBEGIN\n1E0,2E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            assert_eq!(record.frequency_unit(), Some(FrequencyUnit::GHz));
+        }
+
+        #[test]
+        fn from_constant() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record
+                .header
+                .constants
+                .push(Constant::new("FREQ_UNIT", "MHz"));
+            assert_eq!(record.frequency_unit(), Some(FrequencyUnit::MHz));
+        }
+
+        #[test]
+        fn comment_first() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record
+                .header
+                .constants
+                .push(Constant::new("FREQ_UNIT", "MHZ"));
+            record.append_comment("FREQ_UNIT: KHZ");
+            assert_eq!(record.frequency_unit(), Some(FrequencyUnit::KHz));
+        }
+
+        #[test]
+        fn missing_or_unknown() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            assert_eq!(record.frequency_unit(), None);
+            record.append_comment("FREQ_UNIT: furlongs");
+            assert_eq!(record.frequency_unit(), None);
+        }
+    }
+
+    mod test_var_setters {
+        use super::*;
+
+        #[test]
+        fn set_fields() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.set_var_name("FREQ");
+            record.set_var_format("MAG");
+            assert_eq!(record.header.independent_variable.name, "FREQ");
+            assert_eq!(record.header.independent_variable.format, "MAG");
+        }
+
+        #[test]
+        fn var_keyword() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR F MAG 2\nVAR_LIST_BEGIN\n1E0\n2E0\nVAR_LIST_END\nDATA S RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            record.set_var_name("FREQ");
+            record.set_var_format("RI");
+            match record.get_independent_variable_keywords() {
+                Ok(keywords) => assert_eq!(
+                    keywords,
+                    vec![Keyword::Var {
+                        name: String::from("FREQ"),
+                        format: String::from("RI"),
+                        length: 2
+                    }]
+                ),
+                e => panic!("{:?}", e),
             }
+
+            let mut written: Vec<u8> = vec![];
+            record.to_writer(&mut written).unwrap();
+            let written = String::from_utf8(written).unwrap();
+            assert!(written.contains("\nVAR FREQ RI 2\n"));
         }
+    }
 
-        mod test_get_comments_keywords {
-            use super::*;
+    mod test_push_var_point {
+        use super::*;
 
-            #[test]
-            fn empty() {
-                let record = Record::default();
-                match record.get_comments_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
-                    e => panic!("{:?}", e),
-                }
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record
+        }
+
+        #[test]
+        fn var_first() {
+            let mut record = setup();
+            record.push_var_point(1.).unwrap();
+            record.push_var_point(2.).unwrap();
+            for array in record.data.iter_mut() {
+                array.samples.push(Complex::new(1., 2.));
             }
+            // Arrays still shorter than the variable
+            record.push_var_point(3.).unwrap();
+            assert_eq!(record.header.independent_variable.data, vec![1., 2., 3.]);
+        }
 
-            #[test]
-            fn one() {
-                let mut record = Record::default();
-                record.header.comments.push(String::from("A Comment"));
-                match record.get_comments_keywords() {
-                    Ok(v) => assert_eq!(v, vec![Keyword::Comment(String::from("A Comment"))]),
-                    e => panic!("{:?}", e),
+        #[test]
+        fn without_arrays() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.push_var_point(1.).unwrap();
+            assert_eq!(record.header.independent_variable.data, vec![1.]);
+        }
+
+        #[test]
+        fn after_data_caught_up() {
+            let mut record = setup();
+            record.push_var_point(1.).unwrap();
+            record.data[1].samples.push(Complex::new(1., 2.));
+            match record.push_var_point(2.) {
+                Err(Error::RecordError(RecordError::VarBehindData(name, 1))) => {
+                    assert_eq!(name, "S[2,1]")
                 }
+                e => panic!("{:?}", e),
             }
+            assert_eq!(record.header.independent_variable.data, vec![1.]);
+        }
 
-            #[test]
-            fn multiple() {
-                let mut record = Record::default();
-                record.header.comments.push(String::from("A Comment"));
-                record.header.comments.push(String::from("B Comment"));
-                match record.get_comments_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Comment(String::from("A Comment")),
-                            Keyword::Comment(String::from("B Comment"))
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
+        #[test]
+        fn after_data_ahead() {
+            let mut record = setup();
+            record.data[0].samples.push(Complex::new(1., 2.));
+            match record.push_var_point(1.) {
+                Err(Error::RecordError(RecordError::VarBehindData(name, 1))) => {
+                    assert_eq!(name, "S[1,1]")
                 }
+                e => panic!("{:?}", e),
             }
+            assert!(record.header.independent_variable.data.is_empty());
         }
+    }
 
-        mod test_get_name_keywords {
-            use super::*;
+    mod test_append_comment {
+        use super::*;
 
-            #[test]
-            fn none() {
-                let mut record = Record::default();
-                record.header.name = String::new();
-                match record.get_name_keywords() {
-                    Err(WriteError::NoName) => (),
-                    e => panic!("{:?}", e),
-                }
-            }
+        #[test]
+        fn plain() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.comments.push(String::from("first"));
+            record.append_comment("processed by mytool");
+            assert_eq!(record.header.comments, vec!["first", "processed by mytool"]);
+        }
 
-            #[test]
-            fn some() {
-                let mut record = Record::default();
-                record.header.name = String::from("A.01.00");
-                match record.get_name_keywords() {
-                    Ok(v) => assert_eq!(v, vec![Keyword::Name(String::from("A.01.00"))]),
-                    e => panic!("{:?}", e),
-                }
-            }
+        #[cfg(feature = "timestamp")]
+        #[test]
+        fn timestamped_format() {
+            use chrono::TimeZone;
+
+            let time = chrono::Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+            assert_eq!(
+                timestamped_comment(time, "processed by mytool"),
+                "2024-05-01T12:00:00Z processed by mytool"
+            );
         }
 
-        mod test_get_version_keywords {
-            use super::*;
+        #[cfg(feature = "timestamp")]
+        #[test]
+        fn timestamped() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.append_timestamped_comment("processed by mytool");
+            let comment = &record.header.comments[0];
+            let (timestamp, text) = comment.split_at(20);
+            assert_eq!(text, " processed by mytool");
+            assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+            assert!(timestamp.ends_with('Z'));
+        }
+    }
 
-            #[test]
-            fn none() {
-                let mut record = Record::default();
-                record.header.version = String::new();
-                match record.get_version_keywords() {
-                    Err(WriteError::NoVersion) => (),
-                    e => panic!("{:?}", e),
-                }
-            }
+    mod test_retain_data_arrays {
+        use super::*;
 
-            #[test]
-            fn some() {
-                let mut record = Record::default();
-                record.header.version = String::from("A.01.00");
-                match record.get_version_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::CitiFile {
-                            version: String::from("A.01.00")
-                        }]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn transmission_only() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            for name in ["S[1,1]", "S[2,1]", "S[1,2]", "S[2,2]"].iter() {
+                record.data.push(DataArray::new(name, "RI"));
             }
+            record.retain_data_arrays(|array| array.name.starts_with("S[2"));
+            let names: Vec<&str> = record.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(names, ["S[2,1]", "S[2,2]"]);
         }
+    }
 
-        mod test_get_data_keywords {
-            use super::*;
+    mod test_reverse {
+        use super::*;
 
-            #[test]
-            fn many_values() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::new(),
-                    samples: vec![
-                        Complex { re: 1., im: 4. },
-                        Complex { re: 2., im: 1e-6 },
-                        Complex { re: -3., im: 0. },
-                    ],
-                });
-                match record.get_data_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Begin,
-                            Keyword::DataPair { real: 1., imag: 4. },
-                            Keyword::DataPair {
-                                real: 2.,
-                                imag: 1e-6
-                            },
-                            Keyword::DataPair {
-                                real: -3.,
-                                imag: 0.
-                            },
-                            Keyword::End
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn three_points() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nVAR_LIST_BEGIN\n1E9\n2E9\n3E9\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\n5E0,6E0\nEND\nBEGIN\n7E0,8E0\n9E0,10E0\n11E0,12E0\nEND\n";
+            let mut record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            record.reverse();
+            assert_eq!(record.header.independent_variable.data, vec![3e9, 2e9, 1e9]);
+            assert_eq!(
+                record.data[0].samples,
+                vec![
+                    Complex::new(5., 6.),
+                    Complex::new(3., 4.),
+                    Complex::new(1., 2.)
+                ]
+            );
+            assert_eq!(
+                record.data[1].samples,
+                vec![
+                    Complex::new(11., 12.),
+                    Complex::new(9., 10.),
+                    Complex::new(7., 8.)
+                ]
+            );
+
+            record.reverse();
+            assert_eq!(
+                record,
+                Record::from_reader(&mut contents.as_bytes()).unwrap()
+            );
+        }
+    }
+
+    mod test_pad_data_arrays {
+        use super::*;
+
+        #[test]
+        fn pad_short_array() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record.data[0].samples = vec![Complex::new(1., 2.), Complex::new(3., 4.)];
+            record.data[1].samples = vec![
+                Complex::new(5., 6.),
+                Complex::new(7., 8.),
+                Complex::new(9., 10.),
+            ];
+
+            let fill = Complex::new(f64::NAN, f64::NAN);
+            assert_eq!(record.pad_data_arrays(fill), 1);
+            assert_eq!(record.data[0].samples.len(), 3);
+            assert_eq!(record.data[1].samples.len(), 3);
+            assert_eq!(
+                record.data[0].samples[..2],
+                [Complex::new(1., 2.), Complex::new(3., 4.)]
+            );
+            assert!(record.data[0].samples[2].is_nan());
+            assert_eq!(record.data[1].samples[2], Complex::new(9., 10.));
+        }
+
+        #[test]
+        fn already_equal() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S", "RI"));
+            record.data[0].samples = vec![Complex::new(1., 2.)];
+            assert_eq!(record.pad_data_arrays(Complex::new(0., 0.)), 0);
+            assert_eq!(record.data[0].samples, vec![Complex::new(1., 2.)]);
+        }
+
+        #[test]
+        fn no_arrays() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            assert_eq!(record.pad_data_arrays(Complex::new(0., 0.)), 0);
+        }
+    }
+
+    mod test_data_array {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record
+        }
+
+        #[test]
+        fn in_range() {
+            let record = setup();
+            assert_eq!(
+                record.data_array(0).map(|a| a.name.as_str()),
+                Some("S[1,1]")
+            );
+            assert_eq!(
+                record.data_array(1).map(|a| a.name.as_str()),
+                Some("S[2,1]")
+            );
+        }
+
+        #[test]
+        fn out_of_range() {
+            let mut record = setup();
+            assert!(record.data_array(2).is_none());
+            assert!(record.data_array_mut(2).is_none());
+            assert!(Record::default().data_array(0).is_none());
+        }
+
+        #[test]
+        fn mutate() {
+            let mut record = setup();
+            if let Some(array) = record.data_array_mut(1) {
+                array.samples.push(Complex::new(1., 2.));
             }
+            assert_eq!(record.data[1].samples, vec![Complex::new(1., 2.)]);
+            assert!(record.data[0].samples.is_empty());
+        }
+    }
 
-            #[test]
-            fn one_array_gives_correct_result() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::new(),
-                    samples: vec![Complex { re: 1., im: 2. }],
-                });
-                match record.get_data_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Begin,
-                            Keyword::DataPair { real: 1., imag: 2. },
-                            Keyword::End
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
+    mod test_data_formats {
+        use super::*;
+
+        fn setup() -> Record {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 1\nDATA S[1,1] MAGANGLE\nDATA S[2,1] DB\nBEGIN\n1E0,90E0\nEND\nBEGIN\n-3E0,0E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
+
+        #[test]
+        fn data_array_format() {
+            let record = setup();
+            assert_eq!(record.data_array_format("S[1,1]"), Some("MAGANGLE"));
+            assert_eq!(record.data_array_format("S[2,1]"), Some("DB"));
+            assert_eq!(record.data_array_format("S[2,2]"), None);
+        }
+
+        #[test]
+        fn set_all_data_formats() {
+            let mut record = setup();
+            let samples: Vec<Vec<Complex<f64>>> =
+                record.data.iter().map(|a| a.samples.clone()).collect();
+            record.set_all_data_formats("RI");
+            assert_eq!(record.data_array_format("S[1,1]"), Some("RI"));
+            assert_eq!(record.data_array_format("S[2,1]"), Some("RI"));
+            for (array, samples) in record.data.iter().zip(samples.iter()) {
+                assert_eq!(&array.samples, samples);
             }
+        }
+    }
 
-            #[test]
-            fn multiple_array_gives_correct_result() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::new(),
-                    samples: vec![Complex { re: 1., im: 2. }],
-                });
+    mod test_two_port {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            for (i, name) in ["S[1,1]", "S[1,2]", "S[2,1]", "S[2,2]"].iter().enumerate() {
                 record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::new(),
-                    samples: vec![Complex { re: 3., im: 4. }],
+                    name: String::from(*name),
+                    format: String::from("RI"),
+                    samples: vec![Complex::new(i as f64, 1.)],
                 });
-                match record.get_data_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![
-                            Keyword::Begin,
-                            Keyword::DataPair { real: 1., imag: 2. },
-                            Keyword::End,
-                            Keyword::Begin,
-                            Keyword::DataPair { real: 3., imag: 4. },
-                            Keyword::End
-                        ]
-                    ),
-                    e => panic!("{:?}", e),
-                }
             }
+            record
+        }
+
+        #[test]
+        fn s_parameter() {
+            let record = setup();
+            assert_eq!(record.s_parameter(2, 1).unwrap().name, "S[2,1]");
+            assert!(record.s_parameter(3, 1).is_none());
+        }
+
+        #[test]
+        fn s_parameter_with_spaces() {
+            let mut record = setup();
+            record.data[1].name = String::from("S[ 1, 2 ]");
+            assert_eq!(record.s_parameter(1, 2).unwrap().name, "S[ 1, 2 ]");
+        }
+
+        #[test]
+        fn complete() {
+            let record = setup();
+            let two_port = record.as_two_port().unwrap();
+            assert_complex_array_relative_eq!(two_port.s11, [Complex::new(0., 1.)]);
+            assert_complex_array_relative_eq!(two_port.s12, [Complex::new(1., 1.)]);
+            assert_complex_array_relative_eq!(two_port.s21, [Complex::new(2., 1.)]);
+            assert_complex_array_relative_eq!(two_port.s22, [Complex::new(3., 1.)]);
+        }
+
+        #[test]
+        fn missing_s12() {
+            let mut record = setup();
+            record.data.remove(1);
+            assert!(record.as_two_port().is_none());
+        }
+
+        #[test]
+        fn num_ports() {
+            assert_eq!(setup().num_ports(), Some(2));
+        }
 
-            #[test]
-            fn no_arrays_gives_empty() {
-                let record = Record::default();
-                match record.get_data_keywords() {
-                    Ok(v) => assert_eq!(v, vec![]),
-                    e => panic!("{:?}", e),
+        #[test]
+        fn num_ports_not_matrix() {
+            let mut record = setup();
+            record.data.push(DataArray::new("E", "RI"));
+            assert_eq!(record.num_ports(), None);
+        }
+
+        #[test]
+        fn num_ports_no_arrays() {
+            assert_eq!(Record::new("A.01.00", "MEMORY").num_ports(), None);
+        }
+
+        #[test]
+        fn parse_indices() {
+            assert_eq!(parse_s_parameter_indices("S[1,2]"), Some((1, 2)));
+            assert_eq!(parse_s_parameter_indices("S[10,2]"), Some((10, 2)));
+            assert_eq!(parse_s_parameter_indices("S"), None);
+            assert_eq!(parse_s_parameter_indices("S[1]"), None);
+            assert_eq!(parse_s_parameter_indices("E[1,2]"), None);
+        }
+    }
+
+    mod test_set_independent_variable {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.header.independent_variable.data = vec![1e9, 2e9];
+            record.data.push(DataArray {
+                name: String::from("S[1,1]"),
+                format: String::from("RI"),
+                samples: vec![Complex::new(1., 2.), Complex::new(3., 4.)],
+            });
+            record
+        }
+
+        #[test]
+        fn matching_length() {
+            let mut record = setup();
+            let mut var = Var::new("TIME", "MAG");
+            var.data = vec![0., 1e-9];
+            record.set_independent_variable(var.clone()).unwrap();
+            assert_eq!(record.header.independent_variable, var);
+        }
+
+        #[test]
+        fn mismatched_length() {
+            let mut record = setup();
+            let mut var = Var::new("TIME", "MAG");
+            var.data = vec![0., 1e-9, 2e-9];
+            match record.set_independent_variable(var) {
+                Err(Error::RecordError(RecordError::ArrayLengthMismatch(name, 3, 2))) => {
+                    assert_eq!(name, "S[1,1]")
                 }
+                e => panic!("{:?}", e),
             }
+            assert_eq!(record, setup());
         }
 
-        mod test_get_data_defines_keywords {
-            use super::*;
+        #[test]
+        fn no_data() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = vec![1e9, 2e9, 3e9];
+            record.set_independent_variable(var.clone()).unwrap();
+            assert_eq!(record.header.independent_variable, var);
+        }
+    }
 
-            #[test]
-            fn one_entry() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::from("Name"),
-                    format: String::from("Format"),
-                    samples: vec![],
-                });
-                match record.get_data_defines_keywords() {
-                    Ok(v) => assert_eq!(
-                        v,
-                        vec![Keyword::Data {
-                            name: String::from("Name"),
-                            format: String::from("Format")
-                        }]
+    mod test_map_independent_variable {
+        use super::*;
+
+        #[test]
+        fn offset() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.header.independent_variable.data = vec![1e9, 2e9, 3e9];
+            record.data.push(DataArray::from_tuples(
+                "S",
+                "RI",
+                vec![(1., 2.), (3., 4.), (5., 6.)],
+            ));
+            let data = record.data.clone();
+
+            record.map_independent_variable(|f| f + 10e6);
+            assert_eq!(
+                record.header.independent_variable.data,
+                vec![1.01e9, 2.01e9, 3.01e9]
+            );
+            assert_eq!(record.data, data);
+        }
+
+        #[test]
+        fn empty() {
+            let mut record = Record::default();
+            record.map_independent_variable(|f| f * 2.);
+            assert!(record.header.independent_variable.data.is_empty());
+        }
+    }
+
+    mod test_from_columns {
+        use super::*;
+
+        #[test]
+        fn two_arrays() {
+            let result = Record::from_columns(
+                "A.01.00",
+                "MEMORY",
+                "FREQ",
+                "MAG",
+                vec![1e9, 2e9],
+                vec![
+                    (
+                        String::from("S[1,1]"),
+                        String::from("RI"),
+                        vec![Complex::new(1., 2.), Complex::new(3., 4.)],
                     ),
-                    e => panic!("{:?}", e),
+                    (
+                        String::from("S[2,1]"),
+                        String::from("RI"),
+                        vec![Complex::new(5., 6.), Complex::new(7., 8.)],
+                    ),
+                ],
+            )
+            .unwrap();
+
+            let mut expected = Record::new("A.01.00", "MEMORY");
+            expected.header.independent_variable = Var::new("FREQ", "MAG");
+            expected.data.push(DataArray::new("S[1,1]", "RI"));
+            expected.data.push(DataArray::new("S[2,1]", "RI"));
+            expected
+                .append_point(1e9, &[Complex::new(1., 2.), Complex::new(5., 6.)])
+                .unwrap();
+            expected
+                .append_point(2e9, &[Complex::new(3., 4.), Complex::new(7., 8.)])
+                .unwrap();
+            assert_eq!(result, expected);
+
+            let mut written: Vec<u8> = vec![];
+            result.to_writer(&mut written).unwrap();
+            let read = Record::from_reader(&mut written.as_slice()).unwrap();
+            assert_eq!(read.data.len(), 2);
+        }
+
+        #[test]
+        fn wrong_length() {
+            let result = Record::from_columns(
+                "A.01.00",
+                "MEMORY",
+                "FREQ",
+                "MAG",
+                vec![1e9, 2e9],
+                vec![(
+                    String::from("S[1,1]"),
+                    String::from("RI"),
+                    vec![Complex::new(1., 2.)],
+                )],
+            );
+            match result {
+                Err(Error::RecordError(RecordError::ArrayLengthMismatch(name, 2, 1))) => {
+                    assert_eq!(name, "S[1,1]")
                 }
+                e => panic!("{:?}", e),
             }
+        }
+    }
 
-            #[test]
-            fn multiple() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::from("Name A"),
-                    format: String::from("Format A"),
-                    samples: vec![],
-                });
-                record.data.push(DataArray {
-                    name: String::from("Name B"),
-                    format: String::from("Format B"),
-                    samples: vec![],
-                });
-                match record.get_data_defines_keywords() {
-                    Ok(v) => {
-                        assert_eq!(
-                            v,
-                            vec![
-                                Keyword::Data {
-                                    name: String::from("Name A"),
-                                    format: String::from("Format A")
-                                },
-                                Keyword::Data {
-                                    name: String::from("Name B"),
-                                    format: String::from("Format B")
-                                }
-                            ]
-                        );
-                    }
-                    e => panic!("{:?}", e),
-                }
-            }
+    mod test_data_flat {
+        use super::*;
 
-            #[test]
-            fn no_name() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::from("Format"),
-                    samples: vec![],
-                });
-                match record.get_data_defines_keywords() {
-                    Err(WriteError::NoDataName(0)) => (),
-                    e => panic!("{:?}", e),
-                }
-            }
+        #[test]
+        fn layout() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            record
+                .append_point(1e9, &[Complex::new(1., 2.), Complex::new(3., 4.)])
+                .unwrap();
+            record
+                .append_point(2e9, &[Complex::new(5., 6.), Complex::new(7., 8.)])
+                .unwrap();
+            record
+                .append_point(3e9, &[Complex::new(9., 10.), Complex::new(11., 12.)])
+                .unwrap();
 
-            #[test]
-            fn no_format() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::from("Name"),
-                    format: String::new(),
-                    samples: vec![],
-                });
-                match record.get_data_defines_keywords() {
-                    Err(WriteError::NoDataFormat(0)) => (),
-                    e => panic!("{:?}", e),
-                }
-            }
+            let (buffer, num_points, num_arrays) = record.data_flat().unwrap();
+            assert_eq!(num_points, 3);
+            assert_eq!(num_arrays, 2);
+            assert_array_relative_eq!(buffer, [1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11., 12.]);
+        }
 
-            #[test]
-            fn no_name_no_format() {
-                let mut record = Record::default();
-                record.data.push(DataArray {
-                    name: String::new(),
-                    format: String::new(),
-                    samples: vec![],
-                });
-                match record.get_data_defines_keywords() {
-                    Err(WriteError::NoDataName(0)) => (),
-                    e => panic!("{:?}", e),
+        #[test]
+        fn empty() {
+            let (buffer, num_points, num_arrays) = Record::default().data_flat().unwrap();
+            assert!(buffer.is_empty());
+            assert_eq!(num_points, 0);
+            assert_eq!(num_arrays, 0);
+        }
+
+        #[test]
+        fn inconsistent_lengths() {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable.data = vec![1e9, 2e9];
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data[0].add_sample(1., 2.);
+            match record.data_flat() {
+                Err(Error::RecordError(RecordError::ArrayLengthMismatch(name, 2, 1))) => {
+                    assert_eq!(name, "S[1,1]")
                 }
+                e => panic!("{:?}", e),
             }
         }
     }
 
-    #[cfg(test)]
-    mod test_read {
+    mod test_interpolate_to {
         use super::*;
 
+        fn setup() -> Record {
+            Record::from_columns(
+                "A.01.00",
+                "MEMORY",
+                "FREQ",
+                "MAG",
+                vec![1., 2., 3.],
+                vec![(
+                    String::from("S"),
+                    String::from("RI"),
+                    vec![
+                        Complex::new(0., 10.),
+                        Complex::new(2., 20.),
+                        Complex::new(6., 0.),
+                    ],
+                )],
+            )
+            .unwrap()
+        }
+
+        fn var(data: Vec<f64>) -> Var {
+            let mut var = Var::new("FREQ", "MAG");
+            var.data = data;
+            var
+        }
+
         #[test]
-        fn cannot_read_empty_record() {
-            match Record::from_reader(&mut "".as_bytes()) {
-                Err(Error::ReadError(ReadError::NoName)) => (),
+        fn finer_grid() {
+            let new_var = var(vec![1., 1.5, 2., 2.5, 3.]);
+            let result = setup().interpolate_to(&new_var).unwrap();
+            assert_eq!(result.header.independent_variable, new_var);
+            assert_complex_array_relative_eq!(
+                result.data[0].samples,
+                [
+                    Complex::new(0., 10.),
+                    Complex::new(1., 15.),
+                    Complex::new(2., 20.),
+                    Complex::new(4., 10.),
+                    Complex::new(6., 0.),
+                ]
+            );
+        }
+
+        #[test]
+        fn decreasing() {
+            let mut record = setup();
+            record.header.independent_variable.data.reverse();
+            record.data[0].samples.reverse();
+            let result = record.interpolate_to(&var(vec![2.5, 1.5])).unwrap();
+            assert_complex_array_relative_eq!(
+                result.data[0].samples,
+                [Complex::new(4., 10.), Complex::new(1., 15.)]
+            );
+        }
+
+        #[test]
+        fn not_monotonic() {
+            let mut record = setup();
+            record.header.independent_variable.data = vec![1., 3., 2.];
+            match record.interpolate_to(&var(vec![1.5])) {
+                Err(Error::RecordError(RecordError::NotMonotonic(2))) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn succeed_on_multiple_new_lines() {
-            let contents = "CITIFILE A.01.00\nNAME MEMORY\n\n\n\n\n\n\n\n\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
-            match Record::from_reader(&mut contents.as_bytes()) {
-                Ok(_) => (),
+        fn repeated_point() {
+            let mut record = setup();
+            record.header.independent_variable.data = vec![1., 1., 2.];
+            match record.interpolate_to(&var(vec![1.5])) {
+                Err(Error::RecordError(RecordError::NotMonotonic(1))) => (),
                 e => panic!("{:?}", e),
             }
         }
 
         #[test]
-        fn succeed_on_whitespace_new_lines() {
-            let contents = "CITIFILE A.01.00\nNAME MEMORY\n      \n\n\n\n\n\n\n\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
-            match Record::from_reader(&mut contents.as_bytes()) {
-                Ok(_) => (),
+        fn out_of_range() {
+            match setup().interpolate_to(&var(vec![2., 3.5])) {
+                Err(Error::RecordError(RecordError::OutOfInterpolationRange(v))) => {
+                    approx::assert_relative_eq!(v, 3.5)
+                }
                 e => panic!("{:?}", e),
             }
         }
 
-        #[cfg(test)]
-        mod test_read_minimal_record {
-            use super::*;
-
-            fn setup() -> Result<Record> {
-                let contents = "CITIFILE A.01.00\nNAME MEMORY\nVAR FREQ MAG 3\nDATA S RI\nBEGIN\n-3.54545E-2,-1.38601E-3\n0.23491E-3,-1.39883E-3\n2.00382E-3,-1.40022E-3\nEND\n";
-                Record::from_reader(&mut contents.as_bytes())
-            }
+        #[test]
+        fn clamp() {
+            let result = setup()
+                .interpolate_to_with_extrapolation(&var(vec![0., 4.]), Extrapolation::Clamp)
+                .unwrap();
+            assert_complex_array_relative_eq!(
+                result.data[0].samples,
+                [Complex::new(0., 10.), Complex::new(6., 0.)]
+            );
+        }
 
-            #[test]
-            fn name() {
-                match setup() {
-                    Ok(record) => assert_eq!(record.header.name, "MEMORY"),
-                    e => panic!("{:?}", e),
+        #[test]
+        fn clamp_nan() {
+            let result = setup()
+                .interpolate_to_with_extrapolation(&var(vec![f64::NAN]), Extrapolation::Clamp);
+            match result {
+                Err(Error::RecordError(RecordError::OutOfInterpolationRange(v))) => {
+                    assert!(v.is_nan())
                 }
+                e => panic!("{:?}", e),
             }
+        }
 
-            #[test]
-            fn version() {
-                match setup() {
-                    Ok(record) => assert_eq!(record.header.version, "A.01.00"),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn empty_source() {
+            let record = Record::new("A.01.00", "MEMORY");
+            match record.interpolate_to(&var(vec![1.])) {
+                Err(Error::RecordError(RecordError::OutOfInterpolationRange(_))) => (),
+                e => panic!("{:?}", e),
             }
+            let result = record.interpolate_to(&var(vec![])).unwrap();
+            assert!(result.header.independent_variable.data.is_empty());
+        }
 
-            #[test]
-            fn comments() {
-                match setup() {
-                    Ok(record) => assert_eq!(record.header.comments.len(), 0),
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn length_mismatch() {
+            let mut record = setup();
+            record.data[0].samples.pop();
+            match record.interpolate_to(&var(vec![1.5])) {
+                Err(Error::RecordError(RecordError::ArrayLengthMismatch(_, 3, 2))) => (),
+                e => panic!("{:?}", e),
             }
+        }
+    }
 
-            #[test]
-            fn constants() {
-                match setup() {
-                    Ok(record) => assert_eq!(record.header.constants.len(), 0),
-                    e => panic!("{:?}", e),
-                }
-            }
+    mod test_clone_structure {
+        use super::*;
 
-            #[test]
-            fn devices() {
-                match setup() {
-                    Ok(record) => assert_eq!(record.header.devices.len(), 0),
-                    e => panic!("{:?}", e),
-                }
-            }
+        fn setup() -> Record {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n!A comment\n#NA VERSION HP8510B.05.00\nCONSTANT A 1\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] MAGANGLE\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\n7E0,8E0\nEND\n";
+            Record::from_reader(&mut contents.as_bytes()).unwrap()
+        }
 
-            #[test]
-            fn independent_variable() {
-                match setup() {
-                    Ok(record) => {
-                        assert_eq!(record.header.independent_variable.name, "FREQ");
-                        assert_eq!(record.header.independent_variable.format, "MAG");
-                        assert_eq!(record.header.independent_variable.data.len(), 0);
-                    }
-                    e => panic!("{:?}", e),
-                }
+        #[test]
+        fn same_structure() {
+            let record = setup();
+            let result = record.clone_structure();
+            assert_eq!(result.header, record.header);
+            assert_eq!(result.data.len(), 2);
+            for (result, array) in result.data.iter().zip(record.data.iter()) {
+                assert_eq!(result.name, array.name);
+                assert_eq!(result.format, array.format);
+                assert!(result.samples.is_empty());
             }
+        }
+
+        #[test]
+        fn clear_var() {
+            let mut result = setup().clone_structure();
+            let var = Var::new(&result.header.independent_variable.name, "MAG");
+            result.set_independent_variable(var).unwrap();
+            assert!(result.header.independent_variable.data.is_empty());
+            assert_eq!(result.header.independent_variable.name, "FREQ");
+        }
+    }
 
-            #[test]
-            fn data() {
-                match setup() {
-                    Ok(record) => {
-                        assert_eq!(record.data.len(), 1);
-                        assert_eq!(record.data[0].name, "S");
-                        assert_eq!(record.data[0].format, "RI");
-                        assert_eq!(record.data[0].samples.len(), 3);
-                        assert_complex_array_relative_eq!(
-                            record.data[0].samples,
-                            vec![
-                                Complex {
-                                    re: -0.0354545,
-                                    im: -0.00138601
-                                },
-                                Complex {
-                                    re: 0.00023491,
-                                    im: -0.00139883
-                                },
-                                Complex {
-                                    re: 0.00200382,
-                                    im: -0.00140022
-                                },
-                            ]
-                        );
-                    }
+    mod test_split_by_data_array {
+        use super::*;
+
+        #[test]
+        fn two_arrays() {
+            let contents = "CITIFILE A.01.00\nNAME MEMORY\n#NA VERSION HP8510B.05.00\nCONSTANT A 1\nVAR FREQ MAG 2\nVAR_LIST_BEGIN\n1E9\n2E9\nVAR_LIST_END\nDATA S[1,1] RI\nDATA S[2,1] RI\nBEGIN\n1E0,2E0\n3E0,4E0\nEND\nBEGIN\n5E0,6E0\n7E0,8E0\nEND\n";
+            let record = Record::from_reader(&mut contents.as_bytes()).unwrap();
+            let records = record.split_by_data_array();
+            assert_eq!(records.len(), 2);
+            for (result, array) in records.iter().zip(record.data.iter()) {
+                assert_eq!(result.header, record.header);
+                assert_eq!(result.data, vec![array.clone()]);
+
+                // Reading back runs the full validation
+                let mut written: Vec<u8> = vec![];
+                result.to_writer(&mut written).unwrap();
+                match Record::from_reader(&mut written.as_slice()) {
+                    Ok(read) => assert_eq!(&read, result),
                     e => panic!("{:?}", e),
                 }
             }
         }
+
+        #[test]
+        fn no_arrays() {
+            let record = Record::new("A.01.00", "MEMORY");
+            assert!(record.split_by_data_array().is_empty());
+        }
     }
 
-    #[test]
-    fn test_default() {
-        let expected = Record {
-            header: Header {
-                version: String::from("A.01.00"),
-                name: String::new(),
-                comments: vec![],
-                devices: vec![],
-                independent_variable: Var {
-                    name: String::new(),
-                    format: String::new(),
-                    data: vec![],
-                },
-                constants: vec![],
-            },
-            data: vec![],
-        };
-        let result = Record::default();
-        assert_eq!(result, expected);
+    mod test_subset {
+        use super::*;
+
+        fn setup() -> Record {
+            let mut record = Record::new("A.01.00", "MEMORY");
+            record.header.independent_variable = Var::new("FREQ", "MAG");
+            record.data.push(DataArray::new("S[1,1]", "RI"));
+            record.data.push(DataArray::new("S[2,1]", "RI"));
+            for i in 1..6 {
+                let f = i as f64;
+                record
+                    .append_point(f * 1e9, &[Complex::new(f, 0.), Complex::new(0., f)])
+                    .unwrap();
+            }
+            record
+        }
+
+        #[test]
+        fn points() {
+            let result = setup().subset_points(1, 3);
+            assert_array_relative_eq!(result.header.independent_variable.data, [2e9, 3e9]);
+            assert_complex_array_relative_eq!(
+                result.data[0].samples,
+                [Complex::new(2., 0.), Complex::new(3., 0.)]
+            );
+            assert_complex_array_relative_eq!(
+                result.data[1].samples,
+                [Complex::new(0., 2.), Complex::new(0., 3.)]
+            );
+            assert_eq!(result.header.name, "MEMORY");
+            assert_eq!(result.data[1].name, "S[2,1]");
+        }
+
+        #[test]
+        fn points_past_end() {
+            let result = setup().subset_points(3, 10);
+            assert_array_relative_eq!(result.header.independent_variable.data, [4e9, 5e9]);
+            assert_eq!(result.data[0].samples.len(), 2);
+        }
+
+        #[test]
+        fn points_empty() {
+            let result = setup().subset_points(3, 3);
+            assert!(result.header.independent_variable.data.is_empty());
+            assert!(result.data[0].samples.is_empty());
+            assert_eq!(result.data.len(), 2);
+        }
+
+        #[test]
+        fn var_range() {
+            let result = setup().subset_var_range(1e9, 3e9);
+            assert_array_relative_eq!(result.header.independent_variable.data, [1e9, 2e9, 3e9]);
+            assert_complex_array_relative_eq!(
+                result.data[1].samples,
+                [
+                    Complex::new(0., 1.),
+                    Complex::new(0., 2.),
+                    Complex::new(0., 3.)
+                ]
+            );
+        }
+
+        #[test]
+        fn var_range_between_points() {
+            let result = setup().subset_var_range(3.5e9, 4.5e9);
+            assert_array_relative_eq!(result.header.independent_variable.data, [4e9]);
+            assert_complex_array_relative_eq!(result.data[0].samples, [Complex::new(4., 0.)]);
+        }
+
+        #[test]
+        fn short_array() {
+            let mut record = setup();
+            record.data[1].samples.truncate(3);
+            let result = record.subset_points(1, 5);
+            assert_array_relative_eq!(result.header.independent_variable.data, [2e9, 3e9]);
+            assert_eq!(result.data[0].samples.len(), 2);
+            assert_complex_array_relative_eq!(
+                result.data[1].samples,
+                [Complex::new(0., 2.), Complex::new(0., 3.)]
+            );
+            let result = record.subset_var_range(2e9, 5e9);
+            assert_array_relative_eq!(result.header.independent_variable.data, [2e9, 3e9]);
+            assert_eq!(result.data[0].samples.len(), 2);
+            assert_eq!(result.data[1].samples.len(), 2);
+        }
+
+        #[test]
+        fn short_var() {
+            let mut record = setup();
+            record.header.independent_variable.data.truncate(2);
+            let result = record.subset_points(0, 5);
+            assert_eq!(result.header.independent_variable.data.len(), 2);
+            assert_eq!(result.data[0].samples.len(), 2);
+            assert_eq!(result.data[1].samples.len(), 2);
+        }
     }
 
     #[test]
-    fn test_new() {
-        let expected = Record {
-            header: Header {
-                version: String::from("A.01.01"),
-                name: String::from("A_NAME"),
-                comments: vec![],
-                devices: vec![],
-                independent_variable: Var {
-                    name: String::new(),
-                    format: String::new(),
-                    data: vec![],
-                },
-                constants: vec![],
-            },
-            data: vec![],
+    fn test_to_f32() {
+        let mut record = Record::new("A.01.00", "MEMORY");
+        record.header.independent_variable = Var {
+            name: String::from("FREQ"),
+            format: String::from("MAG"),
+            data: vec![1e9, 2e9],
         };
-        let result = Record::new("A.01.01", "A_NAME");
-        assert_eq!(result, expected);
+        let mut array = DataArray::new("S[1,1]", "RI");
+        array.add_sample(-3.54545E-2, -1.38601E-3);
+        array.add_sample(0.23491E-3, 1.39883E-3);
+        record.data.push(array);
+
+        let result = record.to_f32();
+        assert_eq!(result.header, record.header);
+        assert_eq!(result.data.len(), 1);
+        assert_eq!(result.data[0].name, "S[1,1]");
+        assert_eq!(result.data[0].format, "RI");
+        for (single, double) in result.data[0]
+            .samples
+            .iter()
+            .zip(record.data[0].samples.iter())
+        {
+            approx::assert_relative_eq!(single.re as f64, double.re, max_relative = 1e-6);
+            approx::assert_relative_eq!(single.im as f64, double.im, max_relative = 1e-6);
+        }
     }
 
     #[test]
@@ -2975,6 +11909,10 @@ mod test_record {
                     data: vec![],
                 },
                 constants: vec![],
+                unknown_lines: vec![],
+                independent_variable_source: VarSource::LengthOnly,
+                var_declared_length: None,
+                seg_items: vec![],
             },
             data: vec![],
         };
@@ -2983,6 +11921,256 @@ mod test_record {
     }
 }
 
+/// 64 bit FNV-1a, used as its output does not change between releases
+struct ContentHasher(u64);
+
+impl ContentHasher {
+    fn new() -> ContentHasher {
+        ContentHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    /// Length prefixed, so that `("ab", "c")` and `("a", "bc")` differ
+    fn write_str(&mut self, value: &str) {
+        self.write_usize(value.len());
+        self.write(value.as_bytes());
+    }
+
+    fn write_strs<S: AsRef<str>, I: ExactSizeIterator<Item = S>>(&mut self, values: I) {
+        self.write_usize(values.len());
+        for value in values {
+            self.write_str(value.as_ref());
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Indices of a data array named `S[i,j]`
+fn parse_s_parameter_indices(name: &str) -> Option<(usize, usize)> {
+    let inner = name.trim().strip_prefix("S[")?.strip_suffix(']')?;
+    let (i, j) = inner.split_once(',')?;
+    Some((i.trim().parse().ok()?, j.trim().parse().ok()?))
+}
+
+/// Scattering parameters of a 2-port
+///
+/// See [`Record::as_two_port`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TwoPort<'a> {
+    /// Input reflection, from `S[1,1]`
+    pub s11: &'a [Complex<f64>],
+    /// Reverse transmission, from `S[1,2]`
+    pub s12: &'a [Complex<f64>],
+    /// Forward transmission, from `S[2,1]`
+    pub s21: &'a [Complex<f64>],
+    /// Output reflection, from `S[2,2]`
+    pub s22: &'a [Complex<f64>],
+}
+
+/// Remove values equal to an earlier one, keeping the first occurrence
+fn dedup_unordered<T: PartialEq>(values: &mut Vec<T>) {
+    let mut i = 0;
+    while i < values.len() {
+        match values[..i].contains(&values[i]) {
+            true => {
+                values.remove(i);
+            }
+            false => i += 1,
+        }
+    }
+}
+
+#[cfg(feature = "timestamp")]
+fn timestamped_comment(time: chrono::DateTime<chrono::Utc>, text: &str) -> String {
+    format!("{} {}", time.format("%Y-%m-%dT%H:%M:%SZ"), text)
+}
+
+/// Index of the point before `v` in `x` and the weight of the point after
+///
+/// `x` must be strictly monotonic, in the direction given by `increasing`.
+fn interpolation_weight(
+    x: &[f64],
+    increasing: bool,
+    v: f64,
+    extrapolation: Extrapolation,
+) -> RecordResult<(usize, f64)> {
+    let (first, last) = match (x.first(), x.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return Err(RecordError::OutOfInterpolationRange(v)),
+    };
+    let (lo, hi) = match increasing {
+        true => (first, last),
+        false => (last, first),
+    };
+    let v = match (lo <= v && v <= hi, extrapolation) {
+        (true, _) => v,
+        (false, Extrapolation::Clamp) if !v.is_nan() => v.max(lo).min(hi),
+        (false, _) => return Err(RecordError::OutOfInterpolationRange(v)),
+    };
+
+    let after = match increasing {
+        true => x.partition_point(|&p| p < v),
+        false => x.partition_point(|&p| p > v),
+    };
+    match after {
+        0 => Ok((0, 0.)),
+        _ => {
+            let before = after - 1;
+            Ok((before, (v - x[before]) / (x[after] - x[before])))
+        }
+    }
+}
+
+/// Representation of a file with single precision data
+///
+/// See [`Record::to_f32`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct RecordF32 {
+    pub header: Header,
+    pub data: Vec<DataArrayF32>,
+}
+
+/// Sample in a data array that differs between two records
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampleDifference {
+    /// Name of the data array in the first record
+    pub array: String,
+    /// Index of the point
+    pub index: usize,
+    /// Magnitude of the difference
+    pub delta: f64,
+}
+
+/// Differences between two records
+///
+/// See [`Record::diff`].
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RecordDiff {
+    /// Header fields which differ e.g. `name` or `comments`
+    pub header: Vec<String>,
+    /// Names of data arrays only in the second record
+    pub added_arrays: Vec<String>,
+    /// Names of data arrays only in the first record
+    pub removed_arrays: Vec<String>,
+    /// Data arrays at the same position with a different name, as `(old, new)`
+    pub renamed_arrays: Vec<(String, String)>,
+    /// Names of data arrays in both records with a different format
+    pub format_changes: Vec<String>,
+    /// Data arrays in both records with a different number of samples, as `(name, old, new)`
+    pub length_changes: Vec<(String, usize, usize)>,
+    /// Samples which differ by more than the tolerance
+    pub sample_differences: Vec<SampleDifference>,
+}
+
+impl RecordDiff {
+    /// True if no differences were found
+    pub fn is_empty(&self) -> bool {
+        self == &RecordDiff::default()
+    }
+}
+
+impl fmt::Display for RecordDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Records are equal");
+        }
+        if !self.header.is_empty() {
+            writeln!(f, "Header differs in: {}", self.header.join(", "))?;
+        }
+        if !self.added_arrays.is_empty() {
+            writeln!(f, "Added data arrays: {}", self.added_arrays.join(", "))?;
+        }
+        if !self.removed_arrays.is_empty() {
+            writeln!(f, "Removed data arrays: {}", self.removed_arrays.join(", "))?;
+        }
+        for (old, new) in self.renamed_arrays.iter() {
+            writeln!(f, "Data array {} renamed to {}", old, new)?;
+        }
+        for name in self.format_changes.iter() {
+            writeln!(f, "Data array {} changed format", name)?;
+        }
+        for (name, old, new) in self.length_changes.iter() {
+            writeln!(f, "Data array {} changed length ({} != {})", name, old, new)?;
+        }
+        for difference in self.sample_differences.iter() {
+            writeln!(
+                f,
+                "Data array {} differs at point {} by {:E}",
+                difference.array, difference.index, difference.delta
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_record_diff {
+    use super::*;
+
+    #[test]
+    fn default_is_empty() {
+        assert!(RecordDiff::default().is_empty());
+    }
+
+    #[test]
+    fn not_empty() {
+        let diff = RecordDiff {
+            header: vec![String::from("name")],
+            ..RecordDiff::default()
+        };
+        assert!(!diff.is_empty());
+    }
+
+    mod test_display {
+        use super::*;
+
+        #[test]
+        fn empty() {
+            assert_eq!(format!("{}", RecordDiff::default()), "Records are equal\n");
+        }
+
+        #[test]
+        fn all() {
+            let diff = RecordDiff {
+                header: vec![String::from("name"), String::from("comments")],
+                added_arrays: vec![String::from("A")],
+                removed_arrays: vec![String::from("B")],
+                renamed_arrays: vec![(String::from("C"), String::from("D"))],
+                format_changes: vec![String::from("E")],
+                length_changes: vec![(String::from("F"), 1, 2)],
+                sample_differences: vec![SampleDifference {
+                    array: String::from("G"),
+                    index: 3,
+                    delta: 0.5,
+                }],
+            };
+            assert_eq!(
+                format!("{}", diff),
+                "Header differs in: name, comments\n\
+                 Added data arrays: A\n\
+                 Removed data arrays: B\n\
+                 Data array C renamed to D\n\
+                 Data array E changed format\n\
+                 Data array F changed length (1 != 2)\n\
+                 Data array G differs at point 3 by 5E-1\n"
+            );
+        }
+    }
+}
+
 /// Error during reading
 #[derive(Error, Debug)]
 pub enum ReadError {
@@ -2992,12 +12180,13 @@ pub enum ReadError {
     IndependentVariableDefinedTwice,
     #[error("Single use keyword `{0}` defined twice")]
     SingleUseKeywordDefinedTwice(Keyword),
-    #[error("Keyword `{0}` is out of order in the record")]
-    OutOfOrderKeyword(Keyword),
+    #[error("Keyword `{0}` is out of order (in {1} section){hint}", hint = out_of_order_hint(.0, .1))]
+    OutOfOrderKeyword(Keyword, Section),
     #[error("Error on line {0}: {1}")]
-    LineError(usize, ParseError),
+    LineError(usize, #[source] ParseError),
+    #[cfg(feature = "std")]
     #[error("Reading error occured: {0}")]
-    ReadingError(std::io::Error),
+    ReadingError(#[source] std::io::Error),
     #[error("Version is not defined")]
     NoVersion,
     #[error("Name is not defined")]
@@ -3008,13 +12197,254 @@ pub enum ReadError {
     NoData,
     #[error("Independent variable and data array {2} are different lengths ({0} != {1})")]
     VarAndDataDifferentLengths(usize, usize, usize),
+    #[error("Record ended before the current block was closed")]
+    UnexpectedEndOfRecord,
+    #[error("Parser cannot continue after a previous error")]
+    ParserPoisoned,
+    #[error("Keyword `{0}` appears before the version")]
+    VersionNotFirst(Keyword),
+    #[error("Record has no keywords")]
+    EmptyRecord,
+    #[error("Line {0} is longer than {1} bytes")]
+    LineTooLong(usize, usize),
+    #[error("Independent variable format `{0}` is complex, which is not supported")]
+    ComplexIndependentVariableUnsupported(String),
+    #[error("Data array {1} ending on line {0} has {3} samples, expected {2}")]
+    DataBlockWrongLength(usize, usize, usize, usize),
+    #[error("Independent variable or data array has more than {0} points")]
+    TooManyPoints(usize),
+    #[error("{0} without {begin}", begin = list_begin(.0))]
+    UnmatchedListEnd(Keyword),
+    #[error("Logarithmic SEG on line {0} from {1} to {2} needs non-zero ends of the same sign")]
+    InvalidLogSegment(usize, f64, f64),
+}
+
+/// Likely cause of an out of order keyword, for error messages
+fn out_of_order_hint(keyword: &Keyword, section: &Section) -> &'static str {
+    match (keyword, section) {
+        (Keyword::DataPair { .. }, Section::Header) => {
+            ": data pair found outside a BEGIN/END block"
+        }
+        _ => "",
+    }
+}
+
+/// Keyword opening the list that `end` closes, for error messages
+fn list_begin(end: &Keyword) -> Keyword {
+    match end {
+        Keyword::SegListEnd => Keyword::SegListBegin,
+        _ => Keyword::VarListBegin,
+    }
+}
+type ReaderResult<T> = core::result::Result<T, ReadError>;
+
+/// Reader reporting the number of bytes read at least every `step` bytes
+#[cfg(feature = "std")]
+struct ProgressReader<'a, R, F> {
+    inner: &'a mut R,
+    step: u64,
+    read: u64,
+    reported: u64,
+    progress: F,
+}
+
+#[cfg(feature = "std")]
+impl<'a, R: std::io::Read, F: FnMut(u64)> std::io::Read for ProgressReader<'a, R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        let at_end = n == 0 && !buf.is_empty();
+        if self.read - self.reported >= self.step || (at_end && self.read > self.reported) {
+            self.reported = self.read;
+            (self.progress)(self.read);
+        }
+        Ok(n)
+    }
+}
+
+/// Call `f` on each line of `reader`, as set by the line options
+#[cfg(feature = "std")]
+fn for_each_line<B: BufRead, F: FnMut(&str) -> Result<()>>(
+    mut reader: B,
+    options: &ReaderOptions,
+    mut f: F,
+) -> Result<()> {
+    match (options.max_line_bytes, options.lossy_utf8) {
+        (None, false) => {
+            for line in reader.lines() {
+                f(&line.map_err(ReadError::ReadingError)?)?;
+            }
+        }
+        (limit, lossy) => {
+            let mut i = 0;
+            while let Some(line) = read_line_limited(&mut reader, i, limit, lossy)? {
+                f(&line)?;
+                i += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read line `line` of at most `limit` bytes, excluding the line ending
+///
+/// At most a couple of bytes past the limit are read, so an overlong
+/// line is rejected without being held in memory. When `lossy` is set,
+/// invalid UTF-8 is replaced with `U+FFFD` instead of failing.
+#[cfg(feature = "std")]
+fn read_line_limited<R: BufRead>(
+    reader: &mut R,
+    line: usize,
+    limit: Option<usize>,
+    lossy: bool,
+) -> ReaderResult<Option<String>> {
+    let mut buffer = vec![];
+    reader
+        .by_ref()
+        .take(limit.map_or(u64::MAX, |limit| limit as u64 + 2))
+        .read_until(b'\n', &mut buffer)
+        .map_err(ReadError::ReadingError)?;
+
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    if buffer.last() == Some(&b'\n') {
+        buffer.pop();
+        if buffer.last() == Some(&b'\r') {
+            buffer.pop();
+        }
+    }
+    if let Some(limit) = limit.filter(|&limit| buffer.len() > limit) {
+        return Err(ReadError::LineTooLong(line, limit));
+    }
+    if lossy {
+        return Ok(Some(String::from_utf8_lossy(&buffer).into_owned()));
+    }
+    String::from_utf8(buffer).map(Some).map_err(|e| {
+        ReadError::ReadingError(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    })
+}
+
+#[cfg(test)]
+mod test_read_line_limited {
+    use super::*;
+
+    fn read_all(contents: &str, limit: usize) -> ReaderResult<Vec<String>> {
+        let mut reader = contents.as_bytes();
+        let mut lines = vec![];
+        while let Some(line) = read_line_limited(&mut reader, lines.len(), Some(limit), false)? {
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+
+    #[test]
+    fn same_as_lines() {
+        let contents = "BEGIN\r\n\n1,2\nEND";
+        let expected: Vec<String> = contents.as_bytes().lines().map(|l| l.unwrap()).collect();
+        assert_eq!(read_all(contents, 5).unwrap(), expected);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(read_all("", 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn at_limit() {
+        assert_eq!(read_all("12345\n12345\r\n12345", 5).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn too_long() {
+        match read_all("1234\n123456\n", 5) {
+            Err(ReadError::LineTooLong(1, 5)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn too_long_without_newline() {
+        match read_all("123456", 5) {
+            Err(ReadError::LineTooLong(0, 5)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut reader: &[u8] = &[0xff, b'\n'];
+        match read_line_limited(&mut reader, 0, Some(5), false) {
+            Err(ReadError::ReadingError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_lossy() {
+        let mut reader: &[u8] = &[b'!', 0xb0, b'C', b'\n'];
+        match read_line_limited(&mut reader, 0, None, true) {
+            Ok(Some(line)) => assert_eq!(line, "!\u{FFFD}C"),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn unlimited() {
+        let line = "1".repeat(10_000);
+        let mut reader = line.as_bytes();
+        match read_line_limited(&mut reader, 0, None, false) {
+            Ok(Some(read)) => assert_eq!(read, line),
+            e => panic!("{:?}", e),
+        }
+    }
 }
-type ReaderResult<T> = std::result::Result<T, ReadError>;
 
 #[cfg(test)]
 mod test_reader_error {
     use super::*;
 
+    mod test_source {
+        use super::*;
+        use std::error::Error;
+
+        #[test]
+        fn line_error() {
+            let error = ReadError::LineError(10, ParseError::BadKeyword(String::from("X")));
+            assert_eq!(
+                error.source().map(|e| e.to_string()),
+                Some(String::from("Keyword `X` is not supported"))
+            );
+        }
+
+        #[test]
+        fn reading_error() {
+            let error = ReadError::ReadingError(std::io::Error::from(std::io::ErrorKind::Other));
+            assert!(error.source().is_some());
+        }
+
+        #[test]
+        fn no_source() {
+            assert!(ReadError::NoName.source().is_none());
+        }
+
+        #[test]
+        fn chain() {
+            let error = crate::Error::ReadError(ReadError::LineError(3, ParseError::BadRegex));
+            let mut chain: Vec<String> = vec![error.to_string()];
+            let mut source = error.source();
+            while let Some(e) = source {
+                chain.push(e.to_string());
+                source = e.source();
+            }
+            assert_eq!(chain.len(), 3);
+            assert_eq!(chain[2], "Regex could not be parsed");
+        }
+    }
+
     mod test_display {
         use super::*;
 
@@ -3044,10 +12474,22 @@ mod test_reader_error {
 
         #[test]
         fn out_of_order_keyword() {
-            let error = ReadError::OutOfOrderKeyword(Keyword::Begin);
+            let error = ReadError::OutOfOrderKeyword(Keyword::Begin, Section::VarList);
+            assert_eq!(
+                format!("{}", error),
+                "Keyword `BEGIN` is out of order (in VarList section)"
+            );
+        }
+
+        #[test]
+        fn out_of_order_data_pair() {
+            let error = ReadError::OutOfOrderKeyword(
+                Keyword::DataPair { real: 1., imag: 2. },
+                Section::Header,
+            );
             assert_eq!(
                 format!("{}", error),
-                "Keyword `BEGIN` is out of order in the record"
+                "Keyword `1E0,2E0` is out of order (in Header section): data pair found outside a BEGIN/END block"
             );
         }
 
@@ -3101,6 +12543,255 @@ mod test_reader_error {
                 "Independent variable and data array 3 are different lengths (1 != 2)"
             );
         }
+
+        #[test]
+        fn unexpected_end_of_record() {
+            let error = ReadError::UnexpectedEndOfRecord;
+            assert_eq!(
+                format!("{}", error),
+                "Record ended before the current block was closed"
+            );
+        }
+
+        #[test]
+        fn parser_poisoned() {
+            let error = ReadError::ParserPoisoned;
+            assert_eq!(
+                format!("{}", error),
+                "Parser cannot continue after a previous error"
+            );
+        }
+
+        #[test]
+        fn version_not_first() {
+            let error = ReadError::VersionNotFirst(Keyword::Name(String::from("CAL_SET")));
+            assert_eq!(
+                format!("{}", error),
+                "Keyword `NAME CAL_SET` appears before the version"
+            );
+        }
+
+        #[test]
+        fn empty_record() {
+            let error = ReadError::EmptyRecord;
+            assert_eq!(format!("{}", error), "Record has no keywords");
+        }
+
+        #[test]
+        fn line_too_long() {
+            let error = ReadError::LineTooLong(3, 80);
+            assert_eq!(format!("{}", error), "Line 3 is longer than 80 bytes");
+        }
+
+        #[test]
+        fn unmatched_list_end() {
+            let error = ReadError::UnmatchedListEnd(Keyword::VarListEnd);
+            assert_eq!(format!("{}", error), "VAR_LIST_END without VAR_LIST_BEGIN");
+            let error = ReadError::UnmatchedListEnd(Keyword::SegListEnd);
+            assert_eq!(format!("{}", error), "SEG_LIST_END without SEG_LIST_BEGIN");
+        }
+
+        #[test]
+        fn too_many_points() {
+            let error = ReadError::TooManyPoints(100);
+            assert_eq!(
+                format!("{}", error),
+                "Independent variable or data array has more than 100 points"
+            );
+        }
+
+        #[test]
+        fn complex_independent_variable_unsupported() {
+            let error = ReadError::ComplexIndependentVariableUnsupported(String::from("MAGANGLE"));
+            assert_eq!(
+                format!("{}", error),
+                "Independent variable format `MAGANGLE` is complex, which is not supported"
+            );
+        }
+
+        #[test]
+        fn data_block_wrong_length() {
+            let error = ReadError::DataBlockWrongLength(12, 1, 3, 2);
+            assert_eq!(
+                format!("{}", error),
+                "Data array 1 ending on line 12 has 2 samples, expected 3"
+            );
+        }
+    }
+}
+
+/// Points allowed by default in [`ReaderOptions::max_points`]
+const DEFAULT_MAX_POINTS: usize = 1 << 24;
+
+/// Options controlling how leniently a record is read
+///
+/// The defaults follow the standard strictly, with a limit on the number
+/// of points.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReaderOptions {
+    /// Treat the end of the record inside a data block as an implicit `END`
+    ///
+    /// Truncated captures sometimes lack the final `END`. When set, the
+    /// last array is kept provided it holds at least one sample.
+    pub allow_unterminated_final_block: bool,
+    /// Require `CITIFILE` to be the first keyword other than comments
+    ///
+    /// The standard expects this, but some vendor files put `NAME` first.
+    pub require_version_first: bool,
+    /// Reject infinite and NaN values with [`ParseError::NonFiniteValue`]
+    ///
+    /// Instruments may write these on overflow. By default they are read.
+    pub reject_non_finite: bool,
+    /// Accept keywords in any case e.g. `begin` or `Var`
+    ///
+    /// Only the keyword itself is affected. Names and values keep their case.
+    pub case_insensitive_keywords: bool,
+    /// Unit of the angle in `MAGANGLE` and `DBANGLE` pairs
+    ///
+    /// Write with the same unit in [`WriteOptions`] for a faithful round trip.
+    pub angle_unit: AngleUnit,
+    /// Keep unrecognised header lines in [`Header::unknown_lines`]
+    ///
+    /// Vendor extensions add keywords such as `SWEEP_TYPE LOG`. When set,
+    /// these are written back verbatim, after the devices, instead of
+    /// failing the read. Lines that cannot be parsed inside a list or data
+    /// block are still rejected with [`ReadError::LineError`].
+    pub preserve_unknown_keywords: bool,
+    /// Reject lines longer than this many bytes with [`ReadError::LineTooLong`]
+    ///
+    /// A safety limit for untrusted input, where a single line without a
+    /// new line would otherwise be read into memory whole.
+    pub max_line_bytes: Option<usize>,
+    /// Reject independent variables and data arrays with more points than
+    /// this with [`ReadError::TooManyPoints`]
+    ///
+    /// A safety limit for untrusted input, where a single `SEG` item may
+    /// otherwise ask for any number of points. Defaults to 16777216 (2^24)
+    /// points; `None` removes the limit.
+    pub max_points: Option<usize>,
+    /// Drop the repeated point where one `SEG` item starts on the last
+    /// point of the previous one
+    ///
+    /// This gives a piecewise axis without the boundary point twice.
+    pub dedup_seg_boundaries: bool,
+    /// Read commas in numbers as decimal separators e.g. `1,5` as 1.5
+    ///
+    /// Applies to `VAR_LIST` and `SEG` items. Data pairs must then be
+    /// separated by whitespace instead, as in `1,5 -2,5`.
+    pub decimal_comma: bool,
+    /// Replace invalid UTF-8 with `U+FFFD` instead of failing the read
+    ///
+    /// Comments are sometimes saved in another encoding, such as a Latin-1
+    /// degree sign, which would otherwise abort the whole record.
+    pub lossy_utf8: bool,
+    /// Also read lines starting with this character as comments e.g. `;`
+    ///
+    /// `!` and `COMMENT` always start a comment. The prefix is checked
+    /// before any keyword, so `#` would read device lines as comments.
+    pub comment_prefix: Option<char>,
+    /// Ignore whitespace at the start and end of each line
+    ///
+    /// Some tools indent data pairs or leave trailing spaces, which the
+    /// standard does not allow.
+    pub trim_whitespace: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        ReaderOptions {
+            allow_unterminated_final_block: false,
+            require_version_first: false,
+            reject_non_finite: false,
+            case_insensitive_keywords: false,
+            angle_unit: AngleUnit::default(),
+            preserve_unknown_keywords: false,
+            max_line_bytes: None,
+            max_points: Some(DEFAULT_MAX_POINTS),
+            dedup_seg_boundaries: false,
+            decimal_comma: false,
+            lossy_utf8: false,
+            comment_prefix: None,
+            trim_whitespace: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_reader_options {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let expected = ReaderOptions {
+            allow_unterminated_final_block: false,
+            require_version_first: false,
+            reject_non_finite: false,
+            case_insensitive_keywords: false,
+            angle_unit: AngleUnit::Degrees,
+            preserve_unknown_keywords: false,
+            max_line_bytes: None,
+            max_points: Some(1 << 24),
+            dedup_seg_boundaries: false,
+            decimal_comma: false,
+            lossy_utf8: false,
+            comment_prefix: None,
+            trim_whitespace: false,
+        };
+        assert_eq!(ReaderOptions::default(), expected);
+    }
+}
+
+/// Part of a record the reader was in, reported with
+/// [`ReadError::OutOfOrderKeyword`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    /// Header keywords, or between data blocks
+    Header,
+    /// Inside a `BEGIN`/`END` data block
+    Data,
+    /// Inside a `VAR_LIST_BEGIN`/`VAR_LIST_END` list
+    VarList,
+    /// Inside a `SEG_LIST_BEGIN`/`SEG_LIST_END` list
+    SeqList,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Header => write!(f, "Header"),
+            Section::Data => write!(f, "Data"),
+            Section::VarList => write!(f, "VarList"),
+            Section::SeqList => write!(f, "SeqList"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_section {
+    use super::*;
+
+    mod test_display {
+        use super::*;
+
+        #[test]
+        fn header() {
+            assert_eq!(format!("{}", Section::Header), "Header");
+        }
+
+        #[test]
+        fn data() {
+            assert_eq!(format!("{}", Section::Data), "Data");
+        }
+
+        #[test]
+        fn var_list() {
+            assert_eq!(format!("{}", Section::VarList), "VarList");
+        }
+
+        #[test]
+        fn seq_list() {
+            assert_eq!(format!("{}", Section::SeqList), "SeqList");
+        }
     }
 }
 
@@ -3113,6 +12804,17 @@ enum RecordReaderStates {
     SeqList,
 }
 
+impl RecordReaderStates {
+    fn section(self) -> Section {
+        match self {
+            RecordReaderStates::Header => Section::Header,
+            RecordReaderStates::Data => Section::Data,
+            RecordReaderStates::VarList => Section::VarList,
+            RecordReaderStates::SeqList => Section::SeqList,
+        }
+    }
+}
+
 /// Represents state in a CITI record reader FSM
 #[derive(Debug, PartialEq, Clone)]
 struct RecordReaderState {
@@ -3123,10 +12825,18 @@ struct RecordReaderState {
     version_aready_read: bool,
     name_already_read: bool,
     var_already_read: bool,
+    options: ReaderOptions,
+    /// Line of the keyword being processed, for errors
+    line: usize,
 }
 
 impl RecordReaderState {
+    #[cfg(test)]
     pub fn new() -> RecordReaderState {
+        RecordReaderState::with_options(ReaderOptions::default())
+    }
+
+    pub fn with_options(options: ReaderOptions) -> RecordReaderState {
         RecordReaderState {
             record: Record {
                 header: Header::blank(),
@@ -3138,10 +12848,19 @@ impl RecordReaderState {
             version_aready_read: false,
             name_already_read: false,
             var_already_read: false,
+            options,
+            line: 0,
         }
     }
 
     pub fn process_keyword(self, keyword: Keyword) -> ReaderResult<Self> {
+        if self.options.require_version_first && !self.version_aready_read {
+            match keyword {
+                Keyword::CitiFile { .. } | Keyword::Comment(_) => (),
+                _ => return Err(ReadError::VersionNotFirst(keyword)),
+            }
+        }
+
         match self.state {
             RecordReaderStates::Header => RecordReaderState::state_header(self, keyword),
             RecordReaderStates::Data => RecordReaderState::state_data(self, keyword),
@@ -3178,6 +12897,10 @@ impl RecordReaderState {
                 self.record.header.comments.push(comment);
                 Ok(self)
             }
+            Keyword::Unknown(line) => {
+                self.record.header.unknown_lines.push(line);
+                Ok(self)
+            }
             Keyword::Constant { name, value } => {
                 self.record
                     .header
@@ -3195,10 +12918,14 @@ impl RecordReaderState {
                     format,
                     length,
                 })),
+                false if DataFormat::from(format.as_str()).has_phase() => {
+                    Err(ReadError::ComplexIndependentVariableUnsupported(format))
+                }
                 false => {
                     self.var_already_read = true;
                     self.record.header.independent_variable.name = name;
                     self.record.header.independent_variable.format = format;
+                    self.record.header.var_declared_length = Some(length);
                     Ok(self)
                 }
             },
@@ -3224,7 +12951,8 @@ impl RecordReaderState {
                 self.record.data.push(DataArray::new(&name, &format));
                 Ok(self)
             }
-            _ => Err(ReadError::OutOfOrderKeyword(keyword)),
+            Keyword::VarListEnd | Keyword::SegListEnd => Err(ReadError::UnmatchedListEnd(keyword)),
+            _ => Err(ReadError::OutOfOrderKeyword(keyword, self.state.section())),
         }
     }
 
@@ -3232,33 +12960,66 @@ impl RecordReaderState {
         match keyword {
             Keyword::DataPair { real, imag } => {
                 if self.data_array_counter < self.record.data.len() {
-                    self.record.data[self.data_array_counter].add_sample(real, imag);
+                    self.check_points(self.record.data[self.data_array_counter].samples.len() + 1)?;
+                    let unit = self.options.angle_unit;
+                    self.record.data[self.data_array_counter].add_pair(real, imag, unit);
                     Ok(self)
                 } else {
                     Err(ReadError::DataArrayOverIndex)
                 }
             }
             Keyword::End => {
+                self.check_block_length()?;
                 self.state = RecordReaderStates::Header;
                 self.data_array_counter += 1;
                 Ok(self)
             }
-            _ => Err(ReadError::OutOfOrderKeyword(keyword)),
+            _ => Err(ReadError::OutOfOrderKeyword(keyword, self.state.section())),
+        }
+    }
+
+    /// Compare the block just ended against the length established so far
+    ///
+    /// As in the final validation, a zero length is not established.
+    fn check_block_length(&self) -> ReaderResult<()> {
+        let i = self.data_array_counter;
+        let found = match self.record.data.get(i) {
+            Some(array) => array.samples.len(),
+            None => return Ok(()),
+        };
+        let established = core::iter::once(self.record.header.independent_variable.data.len())
+            .chain(self.record.data[..i].iter().map(|a| a.samples.len()))
+            .find(|&n| n != 0);
+        match established {
+            Some(expected) if expected != found => Err(ReadError::DataBlockWrongLength(
+                self.line, i, expected, found,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a list may grow to `points` under [`ReaderOptions::max_points`]
+    fn check_points(&self, points: usize) -> ReaderResult<()> {
+        match self.options.max_points {
+            Some(limit) if points > limit => Err(ReadError::TooManyPoints(limit)),
+            _ => Ok(()),
         }
     }
 
     fn state_var_list(mut self, keyword: Keyword) -> ReaderResult<Self> {
         match keyword {
             Keyword::VarListItem(value) => {
+                self.check_points(self.record.header.independent_variable.data.len() + 1)?;
                 self.record.header.independent_variable.push(value);
                 Ok(self)
             }
             Keyword::VarListEnd => {
                 self.independent_variable_already_read = true;
+                self.record.header.independent_variable_source = VarSource::VarList;
                 self.state = RecordReaderStates::Header;
                 Ok(self)
             }
-            _ => Err(ReadError::OutOfOrderKeyword(keyword)),
+            _ => Err(ReadError::OutOfOrderKeyword(keyword, self.state.section())),
         }
     }
 
@@ -3268,19 +13029,57 @@ impl RecordReaderState {
                 first,
                 last,
                 number,
+                spacing,
             } => {
-                self.record
-                    .header
-                    .independent_variable
-                    .seq(first, last, number);
+                let log = spacing == Some(SegSpacing::Logarithmic);
+                let same_sign = first * last > 0.;
+                if log && number > 0 && !same_sign {
+                    return Err(ReadError::InvalidLogSegment(self.line, first, last));
+                }
+                let var = &self.record.header.independent_variable;
+                let contiguous = var.data.last() == Some(&first) && number > 0;
+                let dedup = self.options.dedup_seg_boundaries && contiguous;
+                self.check_points((var.data.len() - dedup as usize).saturating_add(number))?;
+
+                let var = &mut self.record.header.independent_variable;
+                if dedup {
+                    var.data.pop();
+                }
+                var.push_seg(first, last, number, spacing);
+                self.record.header.seg_items.push(keyword);
                 Ok(self)
             }
             Keyword::SegListEnd => {
                 self.independent_variable_already_read = true;
+                self.record.header.independent_variable_source = VarSource::SegList;
                 self.state = RecordReaderStates::Header;
                 Ok(self)
             }
-            _ => Err(ReadError::OutOfOrderKeyword(keyword)),
+            _ => Err(ReadError::OutOfOrderKeyword(keyword, self.state.section())),
+        }
+    }
+
+    /// Close out the FSM once there are no more keywords
+    ///
+    /// Only the header state is a valid place for a record to end, unless
+    /// the options allow an unterminated final data block.
+    pub fn end_of_record(self) -> ReaderResult<Self> {
+        match self.state {
+            RecordReaderStates::Header => Ok(self),
+            RecordReaderStates::Data => {
+                let has_samples = self
+                    .record
+                    .data
+                    .get(self.data_array_counter)
+                    .is_some_and(|array| !array.samples.is_empty());
+                match self.options.allow_unterminated_final_block && has_samples {
+                    true => self.process_keyword(Keyword::End),
+                    false => Err(ReadError::UnexpectedEndOfRecord),
+                }
+            }
+            RecordReaderStates::VarList | RecordReaderStates::SeqList => {
+                Err(ReadError::UnexpectedEndOfRecord)
+            }
         }
     }
 
@@ -3332,42 +13131,203 @@ impl RecordReaderState {
                 return Err(ReadError::VarAndDataDifferentLengths(n, k, i));
             }
         }
-        Ok(self)
-    }
-}
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test_record_reader_state {
+    use super::*;
+    use approx::*;
+
+    #[test]
+    fn test_new() {
+        let expected = RecordReaderState {
+            record: Record {
+                header: Header {
+                    version: String::new(),
+                    name: String::new(),
+                    comments: vec![],
+                    devices: vec![],
+                    independent_variable: Var {
+                        name: String::new(),
+                        format: String::new(),
+                        data: vec![],
+                    },
+                    constants: vec![],
+                    unknown_lines: vec![],
+                    independent_variable_source: VarSource::LengthOnly,
+                    var_declared_length: None,
+                    seg_items: vec![],
+                },
+                data: vec![],
+            },
+            state: RecordReaderStates::Header,
+            data_array_counter: 0,
+            independent_variable_already_read: false,
+            version_aready_read: false,
+            name_already_read: false,
+            var_already_read: false,
+            options: ReaderOptions::default(),
+            line: 0,
+        };
+        let result = RecordReaderState::new();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_with_options() {
+        let options = ReaderOptions {
+            allow_unterminated_final_block: true,
+            require_version_first: false,
+            reject_non_finite: false,
+            case_insensitive_keywords: false,
+            angle_unit: AngleUnit::Degrees,
+            preserve_unknown_keywords: false,
+            max_line_bytes: None,
+            max_points: None,
+            dedup_seg_boundaries: false,
+            decimal_comma: false,
+            lossy_utf8: false,
+            comment_prefix: None,
+            trim_whitespace: false,
+        };
+        let result = RecordReaderState::with_options(options);
+        assert_eq!(result.options, options);
+        assert_eq!(result.state, RecordReaderStates::Header);
+    }
+
+    mod test_require_version_first {
+        use super::*;
+
+        fn initialize_state() -> RecordReaderState {
+            RecordReaderState::with_options(ReaderOptions {
+                require_version_first: true,
+                ..ReaderOptions::default()
+            })
+        }
+
+        #[test]
+        fn version_first() {
+            let state = initialize_state();
+            let keyword = Keyword::CitiFile {
+                version: String::from("A.01.00"),
+            };
+            let state = state.process_keyword(keyword).unwrap();
+            match state.process_keyword(Keyword::Name(String::from("MEMORY"))) {
+                Ok(s) => assert_eq!(s.record.header.name, "MEMORY"),
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn comment_before_version() {
+            let state = initialize_state();
+            match state.process_keyword(Keyword::Comment(String::from("A comment"))) {
+                Ok(s) => assert_eq!(s.record.header.comments, vec!["A comment"]),
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn name_before_version() {
+            let state = initialize_state();
+            match state.process_keyword(Keyword::Name(String::from("MEMORY"))) {
+                Err(ReadError::VersionNotFirst(Keyword::Name(name))) => assert_eq!(name, "MEMORY"),
+                e => panic!("{:?}", e),
+            }
+        }
+    }
+
+    mod test_end_of_record {
+        use super::*;
+
+        fn initialize_state(
+            state: RecordReaderStates,
+            options: ReaderOptions,
+        ) -> RecordReaderState {
+            let mut reader_state = RecordReaderState::with_options(options);
+            reader_state.state = state;
+            reader_state.record.data.push(DataArray::new("S", "RI"));
+            reader_state
+        }
+
+        fn lenient() -> ReaderOptions {
+            ReaderOptions {
+                allow_unterminated_final_block: true,
+                require_version_first: false,
+                reject_non_finite: false,
+                case_insensitive_keywords: false,
+                angle_unit: AngleUnit::Degrees,
+                preserve_unknown_keywords: false,
+                max_line_bytes: None,
+                max_points: None,
+                dedup_seg_boundaries: false,
+                decimal_comma: false,
+                lossy_utf8: false,
+                comment_prefix: None,
+                trim_whitespace: false,
+            }
+        }
+
+        #[test]
+        fn header() {
+            let state = initialize_state(RecordReaderStates::Header, ReaderOptions::default());
+            match state.end_of_record() {
+                Ok(s) => assert_eq!(s.state, RecordReaderStates::Header),
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn data_strict() {
+            let mut state = initialize_state(RecordReaderStates::Data, ReaderOptions::default());
+            state.record.data[0].add_sample(1., 2.);
+            match state.end_of_record() {
+                Err(ReadError::UnexpectedEndOfRecord) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn data_lenient() {
+            let mut state = initialize_state(RecordReaderStates::Data, lenient());
+            state.record.data[0].add_sample(1., 2.);
+            match state.end_of_record() {
+                Ok(s) => {
+                    assert_eq!(s.state, RecordReaderStates::Header);
+                    assert_eq!(s.data_array_counter, 1);
+                }
+                Err(e) => panic!("{:?}", e),
+            }
+        }
 
-#[cfg(test)]
-mod test_record_reader_state {
-    use super::*;
-    use approx::*;
+        #[test]
+        fn data_lenient_no_samples() {
+            let state = initialize_state(RecordReaderStates::Data, lenient());
+            match state.end_of_record() {
+                Err(ReadError::UnexpectedEndOfRecord) => (),
+                e => panic!("{:?}", e),
+            }
+        }
 
-    #[test]
-    fn test_new() {
-        let expected = RecordReaderState {
-            record: Record {
-                header: Header {
-                    version: String::new(),
-                    name: String::new(),
-                    comments: vec![],
-                    devices: vec![],
-                    independent_variable: Var {
-                        name: String::new(),
-                        format: String::new(),
-                        data: vec![],
-                    },
-                    constants: vec![],
-                },
-                data: vec![],
-            },
-            state: RecordReaderStates::Header,
-            data_array_counter: 0,
-            independent_variable_already_read: false,
-            version_aready_read: false,
-            name_already_read: false,
-            var_already_read: false,
-        };
-        let result = RecordReaderState::new();
-        assert_eq!(result, expected);
+        #[test]
+        fn var_list() {
+            let state = initialize_state(RecordReaderStates::VarList, lenient());
+            match state.end_of_record() {
+                Err(ReadError::UnexpectedEndOfRecord) => (),
+                e => panic!("{:?}", e),
+            }
+        }
+
+        #[test]
+        fn seq_list() {
+            let state = initialize_state(RecordReaderStates::SeqList, lenient());
+            match state.end_of_record() {
+                Err(ReadError::UnexpectedEndOfRecord) => (),
+                e => panic!("{:?}", e),
+            }
+        }
     }
 
     mod test_state_header {
@@ -3393,7 +13353,20 @@ mod test_record_reader_state {
                     Ok(s) => {
                         assert_eq!(s.record.header.version, "A.01.01");
                         assert_eq!(s.state, RecordReaderStates::Header);
-                        assert_eq!(s.version_aready_read, true);
+                        assert!(s.version_aready_read);
+                    }
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn unknown() {
+                let keyword = Keyword::Unknown(String::from("SWEEP_TYPE LOG"));
+                let state = initialize_state();
+                match state.process_keyword(keyword) {
+                    Ok(s) => {
+                        assert_eq!(s.record.header.unknown_lines, vec!["SWEEP_TYPE LOG"]);
+                        assert_eq!(s.state, RecordReaderStates::Header);
                     }
                     Err(e) => panic!("{:?}", e),
                 }
@@ -3422,7 +13395,7 @@ mod test_record_reader_state {
                     Ok(s) => {
                         assert_eq!(s.record.header.name, "Name");
                         assert_eq!(s.state, RecordReaderStates::Header);
-                        assert_eq!(s.name_already_read, true);
+                        assert!(s.name_already_read);
                     }
                     Err(e) => panic!("{:?}", e),
                 }
@@ -3454,12 +13427,28 @@ mod test_record_reader_state {
                         assert_eq!(s.record.header.independent_variable.name, "Name");
                         assert_eq!(s.record.header.independent_variable.format, "MAG");
                         assert_eq!(s.state, RecordReaderStates::Header);
-                        assert_eq!(s.var_already_read, true);
+                        assert!(s.var_already_read);
                     }
                     Err(e) => panic!("{:?}", e),
                 }
             }
 
+            #[test]
+            fn var_complex_format() {
+                let keyword = Keyword::Var {
+                    name: String::from("X"),
+                    format: String::from("MAGANGLE"),
+                    length: 102,
+                };
+                let state = initialize_state();
+                match state.process_keyword(keyword) {
+                    Err(ReadError::ComplexIndependentVariableUnsupported(format)) => {
+                        assert_eq!(format, "MAGANGLE")
+                    }
+                    e => panic!("{:?}", e),
+                }
+            }
+
             #[test]
             fn var_cannot_be_called_twice() {
                 let keyword = Keyword::Var {
@@ -3610,14 +13599,19 @@ mod test_record_reader_state {
                     first: 10.,
                     last: 100.,
                     number: 2,
+                    spacing: None,
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegItem {
-                        first,
-                        last,
-                        number,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::SegItem {
+                            first,
+                            last,
+                            number,
+                            spacing: None,
+                        },
+                        Section::Header,
+                    )) => {
                         assert_relative_eq!(first, 10.);
                         assert_relative_eq!(last, 100.);
                         assert_eq!(number, 2);
@@ -3631,7 +13625,17 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListEnd;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListEnd)) => (),
+                    Err(ReadError::UnmatchedListEnd(Keyword::SegListEnd)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn var_list_end() {
+                let keyword = Keyword::VarListEnd;
+                let state = initialize_state();
+                match state.process_keyword(keyword) {
+                    Err(ReadError::UnmatchedListEnd(Keyword::VarListEnd)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -3662,7 +13666,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListItem(1.);
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f), Section::Header)) => {
                         assert_relative_eq!(f, 1.)
                     }
                     e => panic!("{:?}", e),
@@ -3732,7 +13736,10 @@ mod test_record_reader_state {
                 let keyword = Keyword::DataPair { real: 1., imag: 2. };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::DataPair { real, imag })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::DataPair { real, imag },
+                        Section::Header,
+                    )) => {
                         assert_relative_eq!(real, 1.);
                         assert_relative_eq!(imag, 2.);
                     }
@@ -3758,7 +13765,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::End;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::End)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::End, Section::Header)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -3821,7 +13828,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::CitiFile { version })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::CitiFile { version },
+                        Section::Data,
+                    )) => {
                         assert_eq!(version, "A.01.01")
                     }
                     e => panic!("{:?}", e),
@@ -3833,7 +13843,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Name(String::from("Name"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name), Section::Data)) => {
                         assert_eq!(name, "Name")
                     }
                     e => panic!("{:?}", e),
@@ -3849,11 +13859,14 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Var {
-                        name,
-                        format,
-                        length,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Var {
+                            name,
+                            format,
+                            length,
+                        },
+                        Section::Data,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "MAG");
                         assert_eq!(length, 102);
@@ -3870,7 +13883,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Constant { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Constant { name, value },
+                        Section::Data,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -3886,7 +13902,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Device { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Device { name, value },
+                        Section::Data,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -3899,7 +13918,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin, Section::Data)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -3910,14 +13929,19 @@ mod test_record_reader_state {
                     first: 10.,
                     last: 100.,
                     number: 2,
+                    spacing: None,
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegItem {
-                        first,
-                        last,
-                        number,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::SegItem {
+                            first,
+                            last,
+                            number,
+                            spacing: None,
+                        },
+                        Section::Data,
+                    )) => {
                         assert_relative_eq!(first, 10.);
                         assert_relative_eq!(last, 100.);
                         assert_eq!(number, 2);
@@ -3931,7 +13955,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListEnd;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListEnd)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListEnd, Section::Data)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -3941,7 +13965,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin, Section::Data)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -3951,7 +13975,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListItem(1.);
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f), Section::Data)) => {
                         assert_relative_eq!(f, 1.)
                     }
                     e => panic!("{:?}", e),
@@ -3963,7 +13987,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListItem(1e9);
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f), Section::Data)) => {
                         assert_relative_eq!(f, 1e9)
                     }
                     e => panic!("{:?}", e),
@@ -3975,7 +13999,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListEnd;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListEnd)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListEnd, Section::Data)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -3988,7 +14012,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Data { name, format })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Data { name, format },
+                        Section::Data,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "Format");
                     }
@@ -4005,7 +14032,7 @@ mod test_record_reader_state {
                         assert_eq!(s.record.data.len(), 1);
                         assert_complex_array_relative_eq!(
                             s.record.data[0].samples,
-                            vec![Complex { re: 1., im: 2. }]
+                            [Complex { re: 1., im: 2. }]
                         );
                         assert_eq!(s.state, RecordReaderStates::Data);
                     }
@@ -4013,6 +14040,23 @@ mod test_record_reader_state {
                 }
             }
 
+            #[test]
+            fn data_pair_magnitude_angle() {
+                let keyword = Keyword::DataPair {
+                    real: 2.,
+                    imag: 90.,
+                };
+                let mut state = initialize_state();
+                state.record.data[0].format = String::from("MAGANGLE");
+                match state.process_keyword(keyword) {
+                    Ok(s) => {
+                        assert_relative_eq!(s.record.data[0].samples[0].re, 0., epsilon = 1e-12);
+                        assert_relative_eq!(s.record.data[0].samples[0].im, 2.);
+                    }
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+
             #[test]
             fn data_pair_second_array() {
                 let keyword = Keyword::DataPair { real: 1., imag: 2. };
@@ -4025,7 +14069,7 @@ mod test_record_reader_state {
                         assert_eq!(s.record.data[0].samples, vec![]);
                         assert_complex_array_relative_eq!(
                             s.record.data[1].samples,
-                            vec![Complex { re: 1., im: 2. }]
+                            [Complex { re: 1., im: 2. }]
                         );
                         assert_eq!(s.state, RecordReaderStates::Data);
                     }
@@ -4049,7 +14093,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Begin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin, Section::Data)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -4067,6 +14111,44 @@ mod test_record_reader_state {
                 }
             }
 
+            #[test]
+            fn end_matching_var_length() {
+                let mut state = initialize_state();
+                state.record.header.independent_variable.data = vec![1., 2.];
+                state.record.data[0].add_sample(1., 2.);
+                state.record.data[0].add_sample(3., 4.);
+                match state.process_keyword(Keyword::End) {
+                    Ok(s) => assert_eq!(s.data_array_counter, 1),
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn end_short_block() {
+                let mut state = initialize_state();
+                state.line = 7;
+                state.record.header.independent_variable.data = vec![1., 2.];
+                state.record.data[0].add_sample(1., 2.);
+                match state.process_keyword(Keyword::End) {
+                    Err(ReadError::DataBlockWrongLength(7, 0, 2, 1)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
+            #[test]
+            fn end_long_block_against_first_array() {
+                let mut state = initialize_state();
+                state.record.data[0].add_sample(1., 2.);
+                state.record.data.push(DataArray::blank());
+                state.record.data[1].add_sample(1., 2.);
+                state.record.data[1].add_sample(3., 4.);
+                state.data_array_counter = 1;
+                match state.process_keyword(Keyword::End) {
+                    Err(ReadError::DataBlockWrongLength(0, 1, 1, 2)) => (),
+                    e => panic!("{:?}", e),
+                }
+            }
+
             #[test]
             fn end_increment_index() {
                 let keyword = Keyword::End;
@@ -4086,7 +14168,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Comment(String::from("Comment"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Comment(comment))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Comment(comment), Section::Data)) => {
                         assert_eq!(comment, "Comment")
                     }
                     e => panic!("{:?}", e),
@@ -4115,7 +14197,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::CitiFile { version })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::CitiFile { version },
+                        Section::VarList,
+                    )) => {
                         assert_eq!(version, "A.01.01")
                     }
                     e => panic!("{:?}", e),
@@ -4127,7 +14212,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Name(String::from("Name"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name), Section::VarList)) => {
                         assert_eq!(name, "Name")
                     }
                     e => panic!("{:?}", e),
@@ -4143,11 +14228,14 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Var {
-                        name,
-                        format,
-                        length,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Var {
+                            name,
+                            format,
+                            length,
+                        },
+                        Section::VarList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "MAG");
                         assert_eq!(length, 102);
@@ -4164,7 +14252,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Constant { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Constant { name, value },
+                        Section::VarList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -4180,7 +14271,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Device { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Device { name, value },
+                        Section::VarList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -4193,7 +14287,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin, Section::VarList)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -4204,14 +14298,19 @@ mod test_record_reader_state {
                     first: 10.,
                     last: 100.,
                     number: 2,
+                    spacing: None,
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegItem {
-                        first,
-                        last,
-                        number,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::SegItem {
+                            first,
+                            last,
+                            number,
+                            spacing: None,
+                        },
+                        Section::VarList,
+                    )) => {
                         assert_relative_eq!(first, 10.);
                         assert_relative_eq!(last, 100.);
                         assert_eq!(number, 2);
@@ -4225,7 +14324,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListEnd;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListEnd)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListEnd, Section::VarList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4235,7 +14334,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin, Section::VarList)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -4286,7 +14385,7 @@ mod test_record_reader_state {
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
                     Ok(s) => {
-                        assert_eq!(s.independent_variable_already_read, true);
+                        assert!(s.independent_variable_already_read);
                         assert_eq!(s.state, RecordReaderStates::Header);
                     }
                     Err(e) => panic!("{:?}", e),
@@ -4301,7 +14400,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Data { name, format })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Data { name, format },
+                        Section::VarList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "Format");
                     }
@@ -4314,7 +14416,10 @@ mod test_record_reader_state {
                 let keyword = Keyword::DataPair { real: 1., imag: 1. };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::DataPair { real, imag })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::DataPair { real, imag },
+                        Section::VarList,
+                    )) => {
                         assert_relative_eq!(real, 1.);
                         assert_relative_eq!(imag, 1.);
                     }
@@ -4327,7 +14432,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Begin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin, Section::VarList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4337,7 +14442,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::End;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::End)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::End, Section::VarList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4347,7 +14452,10 @@ mod test_record_reader_state {
                 let keyword = Keyword::Comment(String::from("Comment"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Comment(comment))) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Comment(comment),
+                        Section::VarList,
+                    )) => {
                         assert_eq!(comment, "Comment")
                     }
                     e => panic!("{:?}", e),
@@ -4376,7 +14484,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::CitiFile { version })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::CitiFile { version },
+                        Section::SeqList,
+                    )) => {
                         assert_eq!(version, "A.01.01")
                     }
                     e => panic!("{:?}", e),
@@ -4388,7 +14499,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Name(String::from("Name"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Name(name), Section::SeqList)) => {
                         assert_eq!(name, "Name")
                     }
                     e => panic!("{:?}", e),
@@ -4404,11 +14515,14 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Var {
-                        name,
-                        format,
-                        length,
-                    })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Var {
+                            name,
+                            format,
+                            length,
+                        },
+                        Section::SeqList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "MAG");
                         assert_eq!(length, 102);
@@ -4425,7 +14539,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Constant { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Constant { name, value },
+                        Section::SeqList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -4441,7 +14558,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Device { name, value })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Device { name, value },
+                        Section::SeqList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(value, "Value");
                     }
@@ -4454,7 +14574,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::SegListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::SegListBegin, Section::SeqList)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -4465,6 +14585,7 @@ mod test_record_reader_state {
                     first: 10.,
                     last: 100.,
                     number: 2,
+                    spacing: None,
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
@@ -4476,12 +14597,53 @@ mod test_record_reader_state {
                 }
             }
 
+            #[test]
+            fn seg_items_shared_endpoint() {
+                let mut state = initialize_state();
+                for (first, last) in [(10., 20.), (20., 40.)].iter() {
+                    let keyword = Keyword::SegItem {
+                        first: *first,
+                        last: *last,
+                        number: 3,
+                        spacing: None,
+                    };
+                    state = state.process_keyword(keyword).unwrap();
+                }
+                assert_eq!(
+                    state.record.header.independent_variable.data,
+                    vec![10., 15., 20., 20., 30., 40.]
+                );
+            }
+
+            #[test]
+            fn seg_items_shared_endpoint_dedup() {
+                let mut state = RecordReaderState::with_options(ReaderOptions {
+                    dedup_seg_boundaries: true,
+                    ..ReaderOptions::default()
+                });
+                state.state = RecordReaderStates::SeqList;
+                for (first, last) in [(10., 20.), (20., 40.), (50., 60.)].iter() {
+                    let keyword = Keyword::SegItem {
+                        first: *first,
+                        last: *last,
+                        number: 3,
+                        spacing: None,
+                    };
+                    state = state.process_keyword(keyword).unwrap();
+                }
+                assert_eq!(
+                    state.record.header.independent_variable.data,
+                    vec![10., 15., 20., 30., 40., 50., 55., 60.]
+                );
+            }
+
             #[test]
             fn seg_item_triple() {
                 let keyword = Keyword::SegItem {
                     first: 10.,
                     last: 100.,
                     number: 3,
+                    spacing: None,
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
@@ -4502,7 +14664,7 @@ mod test_record_reader_state {
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
                     Ok(s) => {
-                        assert_eq!(s.independent_variable_already_read, true);
+                        assert!(s.independent_variable_already_read);
                         assert_eq!(s.state, RecordReaderStates::Header);
                     }
                     Err(e) => panic!("{:?}", e),
@@ -4514,7 +14676,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListBegin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListBegin, Section::SeqList)) => {}
                     e => panic!("{:?}", e),
                 }
             }
@@ -4524,7 +14686,10 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListItem(1.);
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListItem(f))) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::VarListItem(f),
+                        Section::SeqList,
+                    )) => {
                         assert_relative_eq!(f, 1.0)
                     }
                     e => panic!("{:?}", e),
@@ -4536,7 +14701,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::VarListEnd;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListEnd)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::VarListEnd, Section::SeqList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4549,7 +14714,10 @@ mod test_record_reader_state {
                 };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Data { name, format })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::Data { name, format },
+                        Section::SeqList,
+                    )) => {
                         assert_eq!(name, "Name");
                         assert_eq!(format, "Format");
                     }
@@ -4562,7 +14730,10 @@ mod test_record_reader_state {
                 let keyword = Keyword::DataPair { real: 1., imag: 1. };
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::DataPair { real, imag })) => {
+                    Err(ReadError::OutOfOrderKeyword(
+                        Keyword::DataPair { real, imag },
+                        Section::SeqList,
+                    )) => {
                         assert_relative_eq!(real, 1.);
                         assert_relative_eq!(imag, 1.);
                     }
@@ -4575,7 +14746,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Begin;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Begin, Section::SeqList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4585,7 +14756,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::End;
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::End)) => (),
+                    Err(ReadError::OutOfOrderKeyword(Keyword::End, Section::SeqList)) => (),
                     e => panic!("{:?}", e),
                 }
             }
@@ -4595,7 +14766,7 @@ mod test_record_reader_state {
                 let keyword = Keyword::Comment(String::from("Comment"));
                 let state = initialize_state();
                 match state.process_keyword(keyword) {
-                    Err(ReadError::OutOfOrderKeyword(Keyword::Comment(s))) => {
+                    Err(ReadError::OutOfOrderKeyword(Keyword::Comment(s), Section::SeqList)) => {
                         assert_eq!(s, "Comment")
                     }
                     e => panic!("{:?}", e),
@@ -4885,3 +15056,278 @@ mod test_record_reader_state {
         }
     }
 }
+
+/// Incremental CITI record parser
+///
+/// Drives the reader FSM one line at a time, e.g. for interactive tools.
+/// [`Record::from_reader`] is built on top of this.
+///
+/// Example usage:
+/// ```
+/// use citi::{ParserState, ReaderOptions};
+///
+/// let mut parser = ParserState::new(ReaderOptions::default());
+/// for line in ["CITIFILE A.01.00", "NAME MEMORY", "VAR FREQ MAG 1"].iter() {
+///     parser.feed_line(line).unwrap();
+/// }
+/// for line in ["DATA S RI", "BEGIN", "1,2", "END"].iter() {
+///     parser.feed_line(line).unwrap();
+/// }
+/// let record = parser.finish().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParserState {
+    state: Option<RecordReaderState>,
+    options: ReaderOptions,
+    line: usize,
+    has_keyword: bool,
+}
+
+impl ParserState {
+    /// Parser awaiting the first line of a record read with `options`
+    pub fn new(options: ReaderOptions) -> ParserState {
+        ParserState {
+            state: Some(RecordReaderState::with_options(options)),
+            options,
+            line: 0,
+            has_keyword: false,
+        }
+    }
+
+    /// Process the next line of a record
+    ///
+    /// Blank lines are skipped. A line that cannot be parsed leaves the
+    /// parser untouched, but any other error means the parser cannot be
+    /// used further and all later calls return [`ReadError::ParserPoisoned`].
+    pub fn feed_line(&mut self, line: &str) -> Result<()> {
+        let i = self.line;
+        self.line += 1;
+
+        // Filter out new lines
+        if line.trim().is_empty() {
+            return match self.state {
+                Some(_) => Ok(()),
+                None => Err(ReadError::ParserPoisoned.into()),
+            };
+        }
+
+        let keyword =
+            Keyword::parse_line(line, &self.options).map_err(|e| ReadError::LineError(i, e))?;
+        // Unknown lines are only kept in the header, elsewhere they cannot be parsed
+        if let (Keyword::Unknown(text), Some(state)) = (&keyword, &self.state) {
+            if state.state != RecordReaderStates::Header {
+                let error = ParseError::BadKeyword(text.clone());
+                return Err(ReadError::LineError(i, error).into());
+            }
+        }
+        self.process_keyword(i, keyword)
+    }
+
+    /// Process the next keyword of a record, skipping the text parsing
+    ///
+    /// Each keyword counts as one line in error positions. Any error means
+    /// the parser cannot be used further, as in [`ParserState::feed_line`].
+    pub fn feed_keyword(&mut self, keyword: Keyword) -> Result<()> {
+        let i = self.line;
+        self.line += 1;
+        self.process_keyword(i, keyword)
+    }
+
+    fn process_keyword(&mut self, line: usize, keyword: Keyword) -> Result<()> {
+        let mut state = self.state.take().ok_or(ReadError::ParserPoisoned)?;
+        state.line = line;
+        self.has_keyword = true;
+        self.state = Some(state.process_keyword(keyword)?);
+        Ok(())
+    }
+
+    /// Validate and return the record
+    ///
+    /// [`ReadError::EmptyRecord`] is returned if no keyword was fed.
+    pub fn finish(self) -> Result<Record> {
+        let state = self.state.ok_or(ReadError::ParserPoisoned)?;
+        if !self.has_keyword {
+            return Err(ReadError::EmptyRecord.into());
+        }
+        Ok(state.end_of_record()?.validate_record()?.record)
+    }
+
+    /// Check and return the header of a record read up to its data
+    #[cfg(feature = "std")]
+    fn finish_header(self) -> Result<Header> {
+        let state = self.state.ok_or(ReadError::ParserPoisoned)?;
+        if !self.has_keyword {
+            return Err(ReadError::EmptyRecord.into());
+        }
+        match state.state {
+            RecordReaderStates::VarList | RecordReaderStates::SeqList => {
+                Err(ReadError::UnexpectedEndOfRecord.into())
+            }
+            _ => Ok(state.has_name()?.has_version()?.has_var()?.record.header),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_parser_state {
+    use super::*;
+
+    const MINIMAL_RECORD: &[&str] = &[
+        "CITIFILE A.01.00",
+        "NAME MEMORY",
+        "VAR FREQ MAG 3",
+        "DATA S RI",
+        "BEGIN",
+        "-3.54545E-2,-1.38601E-3",
+        "0.23491E-3,-1.39883E-3",
+        "2.00382E-3,-1.40022E-3",
+        "END",
+    ];
+
+    #[test]
+    fn feed_minimal_record() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        for line in MINIMAL_RECORD.iter() {
+            if let Err(e) = parser.feed_line(line) {
+                panic!("{:?}", e);
+            }
+        }
+        match parser.finish() {
+            Ok(record) => {
+                let expected = Record::from_reader(&mut MINIMAL_RECORD.join("\n").as_bytes());
+                assert_eq!(record, expected.unwrap());
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn feed_keywords() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        for line in MINIMAL_RECORD.iter() {
+            let keyword = Keyword::parse_line(line, &ReaderOptions::default()).unwrap();
+            if let Err(e) = parser.feed_keyword(keyword) {
+                panic!("{:?}", e);
+            }
+        }
+        let expected = Record::from_reader(&mut MINIMAL_RECORD.join("\n").as_bytes());
+        assert_eq!(parser.finish().unwrap(), expected.unwrap());
+    }
+
+    #[test]
+    fn feed_keyword_poisoned() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        match parser.feed_keyword(Keyword::End) {
+            Err(Error::ReadError(ReadError::OutOfOrderKeyword(Keyword::End, _))) => (),
+            e => panic!("{:?}", e),
+        }
+        match parser.feed_keyword(Keyword::Name(String::from("MEMORY"))) {
+            Err(Error::ReadError(ReadError::ParserPoisoned)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn finish_validates() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        parser.feed_line("CITIFILE A.01.00").unwrap();
+        match parser.finish() {
+            Err(Error::ReadError(ReadError::NoName)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn blank_lines_skipped() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        for line in ["", "  ", "CITIFILE A.01.00"].iter() {
+            parser.feed_line(line).unwrap();
+        }
+        assert_eq!(parser.line, 3);
+    }
+
+    #[test]
+    fn parse_error_has_line() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        parser.feed_line("").unwrap();
+        match parser.feed_line("not a keyword") {
+            Err(Error::ReadError(ReadError::LineError(1, ParseError::BadKeyword(_)))) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_does_not_poison() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        assert!(parser.feed_line("not a keyword").is_err());
+        for line in MINIMAL_RECORD.iter() {
+            parser.feed_line(line).unwrap();
+        }
+        assert!(parser.finish().is_ok());
+    }
+
+    #[test]
+    fn non_finite_accepted() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        for line in MINIMAL_RECORD.iter().take(5) {
+            parser.feed_line(line).unwrap();
+        }
+        for line in ["INF,0", "0,NAN", "-INF,-0", "END"].iter() {
+            parser.feed_line(line).unwrap();
+        }
+        let record = parser.finish().unwrap();
+        assert_eq!(record.data[0].samples[0].re, f64::INFINITY);
+        assert!(record.data[0].samples[1].im.is_nan());
+        assert_eq!(record.data[0].samples[2].re, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let options = ReaderOptions {
+            case_insensitive_keywords: true,
+            ..ReaderOptions::default()
+        };
+        let mut parser = ParserState::new(options);
+        for line in MINIMAL_RECORD.iter() {
+            parser.feed_line(&line.to_lowercase()).unwrap();
+        }
+        let record = parser.finish().unwrap();
+        assert_eq!(record.header.name, "memory");
+    }
+
+    #[test]
+    fn non_finite_rejected() {
+        let options = ReaderOptions {
+            reject_non_finite: true,
+            ..ReaderOptions::default()
+        };
+        let mut parser = ParserState::new(options);
+        for line in MINIMAL_RECORD.iter().take(5) {
+            parser.feed_line(line).unwrap();
+        }
+        match parser.feed_line("INF,0") {
+            Err(Error::ReadError(ReadError::LineError(5, ParseError::NonFiniteValue(line)))) => {
+                assert_eq!(line, "INF,0")
+            }
+            e => panic!("{:?}", e),
+        }
+        parser.feed_line("1,2").unwrap();
+    }
+
+    #[test]
+    fn state_error_poisons() {
+        let mut parser = ParserState::new(ReaderOptions::default());
+        match parser.feed_line("END") {
+            Err(Error::ReadError(ReadError::OutOfOrderKeyword(Keyword::End, Section::Header))) => {}
+            e => panic!("{:?}", e),
+        }
+        match parser.feed_line("CITIFILE A.01.00") {
+            Err(Error::ReadError(ReadError::ParserPoisoned)) => (),
+            e => panic!("{:?}", e),
+        }
+        match parser.finish() {
+            Err(Error::ReadError(ReadError::ParserPoisoned)) => (),
+            e => panic!("{:?}", e),
+        }
+    }
+}
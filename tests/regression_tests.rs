@@ -366,6 +366,7 @@ mod cti_read_regression_tests {
         }
 
         #[test]
+        #[allow(clippy::excessive_precision)]
         fn data() {
             match setup() {
                 Ok(file) => {
@@ -591,7 +592,7 @@ mod cti_write_regression_tests {
         let mut record = Record::new("A.01.00", "MEMORY");
         record.header.devices.push(Device {
             name: String::from("NA"),
-            entries: vec!["VERSION HP8510B.05.00", "REGISTER 1"]
+            entries: ["VERSION HP8510B.05.00", "REGISTER 1"]
                 .iter()
                 .map(|&s| String::from(s))
                 .collect(),
@@ -627,3 +628,66 @@ mod cti_write_regression_tests {
         assert_files_equal!(display_memory_filename(), filename);
     }
 }
+
+#[cfg(test)]
+mod cti_mutation_regression_tests {
+    use super::*;
+    use citi::ReaderOptions;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn fixtures() -> Vec<Vec<u8>> {
+        let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path_buf.push("tests");
+        path_buf.push("regression_files");
+        std::fs::read_dir(path_buf)
+            .unwrap()
+            .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+            .collect()
+    }
+
+    fn mutate(rng: &mut StdRng, contents: &mut Vec<u8>) {
+        const BYTES: &[u8] = b"0123456789.,+-eE \t\r\n!#[]SEGVARDATABEGINEND_LIST\xff";
+        for _ in 0..rng.gen_range(1..8) {
+            let i = rng.gen_range(0..=contents.len());
+            match rng.gen_range(0..4) {
+                0 => contents.insert(i, BYTES[rng.gen_range(0..BYTES.len())]),
+                1 if i < contents.len() => {
+                    contents.remove(i);
+                }
+                2 if i < contents.len() => contents[i] = BYTES[rng.gen_range(0..BYTES.len())],
+                _ => contents.truncate(i),
+            }
+        }
+    }
+
+    /// Mutated fixtures must give a record or an error, never a panic
+    #[test]
+    fn never_panics() {
+        let lenient = ReaderOptions {
+            allow_unterminated_final_block: true,
+            case_insensitive_keywords: true,
+            preserve_unknown_keywords: true,
+            dedup_seg_boundaries: true,
+            max_line_bytes: Some(1 << 12),
+            max_points: Some(1 << 16),
+            ..ReaderOptions::default()
+        };
+        let strict = ReaderOptions {
+            require_version_first: true,
+            reject_non_finite: true,
+            max_points: Some(1 << 16),
+            ..ReaderOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for fixture in fixtures() {
+            for _ in 0..200 {
+                let mut contents = fixture.clone();
+                mutate(&mut rng, &mut contents);
+                for options in [lenient, strict].iter() {
+                    let _ = Record::from_reader_with_options(&mut contents.as_slice(), options);
+                }
+            }
+        }
+    }
+}